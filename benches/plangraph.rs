@@ -1,10 +1,11 @@
 #[macro_use] extern crate criterion;
-use criterion::Criterion;
+use criterion::{BatchSize, Criterion};
 
 #[macro_use] extern crate graphplan;
 use graphplan::proposition::Proposition;
 use graphplan::action::Action;
 use graphplan::plangraph::PlanGraph;
+use graphplan::GraphPlan;
 
 fn plangraph_benchmark(c: &mut Criterion) {
     let p1 = Proposition::from("tired");
@@ -17,27 +18,88 @@ fn plangraph_benchmark(c: &mut Criterion) {
 
     let a1 = Action::new(
         String::from("coffee"),
-        hashset!{&p1},
-        hashset!{&p3}
+        fragset!{[&p1]},
+        fragset!{[&p3]}
     );
     let a2 = Action::new(
         String::from("walk dog"),
-        hashset!{&p2, &p3},
-        hashset!{&not_p2},
+        fragset!{[&p2, &p3]},
+        fragset!{[&not_p2]},
     );
 
     c.bench_function("plangraph 100", |b| b.iter(||{
         let mut pg = PlanGraph::new(
-            hashset!{&p1, &p2, &p3},
-            hashset!{&not_p1, &not_p2, &p3},
-            hashset!{&a1, &a2}
+            fragset!{[&p1, &p2, &p3]},
+            fragset!{[&not_p1, &not_p2, &p3]},
+            fragset!{[&a1, &a2]}
         );
         for i in 0..100 {
-            pg.extend();
+            pg.extend().unwrap();
             assert!(pg.depth() == i + 1);
         }
     }));
 }
 
-criterion_group!(benches, plangraph_benchmark);
+/// Packages that can each be moved between any two locations. Every
+/// move deletes where the package was so there are many mutexes.
+fn logistics(packages: usize, locations: usize) -> graphplan::Domain<String, String> {
+    let at = |p: usize, l: usize| Proposition::from(format!("package{}_location{}", p, l));
+    let mut actions = Vec::new();
+    for p in 0..packages {
+        for from in 0..locations {
+            for to in (0..locations).filter(|to| *to != from) {
+                let (here, there) = (at(p, from), at(p, to));
+                let not_here = here.negate();
+                actions.push(Action::new(
+                    format!("move_package{}_location{}_location{}", p, from, to),
+                    fragset!{[&here]},
+                    fragset!{[&there, &not_here]},
+                ));
+            }
+        }
+    }
+    let initial_props: Vec<_> = (0..packages).map(|p| at(p, 0)).collect();
+    let goals: Vec<_> = (0..packages).map(|p| at(p, locations - 1)).collect();
+    GraphPlan::create_domain(
+        initial_props.iter().collect(),
+        goals.iter().collect(),
+        actions.iter().collect(),
+    )
+}
+
+/// Which path computes the layers and mutexes. Run the benchmarks
+/// with and without `--features rayon` to compare them.
+fn mode() -> &'static str {
+    if cfg!(feature = "rayon") { "rayon" } else { "sequential" }
+}
+
+fn large_plangraph_benchmark(c: &mut Criterion) {
+    let domain = logistics(8, 8);
+    c.bench_function(&format!("plangraph logistics 8x8 {}", mode()), |b| b.iter(|| {
+        let mut pg = PlanGraph::from_domain(domain.clone());
+        for _ in 0..3 {
+            pg.extend().unwrap();
+        }
+    }));
+}
+
+/// Mutexes are most of the work of extending a plangraph so these
+/// are timed on their own on a plangraph with many actions
+fn mutex_benchmark(c: &mut Criterion) {
+    let domain = logistics(16, 8);
+    c.bench_function(&format!("plangraph mutexes logistics 16x8 {}", mode()), |b| b.iter_batched(
+        || {
+            let mut pg = PlanGraph::from_domain(domain.clone());
+            pg.extend().unwrap();
+            pg.extend().unwrap();
+            pg
+        },
+        |mut pg| {
+            pg.extend().unwrap();
+        },
+        BatchSize::SmallInput,
+    ));
+}
+
+criterion_group!(benches, plangraph_benchmark, large_plangraph_benchmark, mutex_benchmark);
 criterion_main!(benches);
@@ -4,6 +4,7 @@ use criterion::Criterion;
 #[macro_use] extern crate graphplan;
 use graphplan::{GraphPlan, Proposition, Action};
 use graphplan::solver::SimpleSolver;
+use graphplan::{HeuristicSearchSolver, SatSolver};
 
 fn solver_benchmark(c: &mut Criterion) {
     let p1 = Proposition::from("rocket1_location1");
@@ -14,29 +15,39 @@ fn solver_benchmark(c: &mut Criterion) {
 
     let a1 = Action::new(
        "move_rocket1_location2",
-       hashset!{&p1},
-       hashset!{&p2},
+       fragset!{[&p1]},
+       fragset!{[&p2]},
     );
     let a2 = Action::new(
        "move_rocket1_location3",
-       hashset!{&p2},
-       hashset!{&p3},
+       fragset!{[&p2]},
+       fragset!{[&p3]},
     );
     let a3 = Action::new(
        "move_rocket2_location3",
-       hashset!{&p4},
-       hashset!{&p5},
+       fragset!{[&p4]},
+       fragset!{[&p5]},
     );
 
     let domain = GraphPlan::create_domain(
-        hashset!{&p1, &p4},
-        hashset!{&p3, &p5},
-        hashset!{&a1, &a2, &a3}
+        fragset!{[&p1, &p4]},
+        fragset!{[&p3, &p5]},
+        fragset!{[&a1, &a2, &a3]}
     );
 
     c.bench_function("solve 100", |b| b.iter(||{
         let mut pg = GraphPlan::from_domain(&domain);
-        assert!(pg.search::<SimpleSolver>().is_some());
+        assert!(pg.search::<SimpleSolver>().is_ok());
+    }));
+
+    c.bench_function("solve sat 100", |b| b.iter(||{
+        let mut pg = GraphPlan::from_domain(&domain);
+        assert!(pg.search::<SatSolver>().is_ok());
+    }));
+
+    c.bench_function("solve heuristic 100", |b| b.iter(||{
+        let mut pg = GraphPlan::from_domain(&domain);
+        assert!(pg.search::<HeuristicSearchSolver>().is_ok());
     }));
 }
 
@@ -0,0 +1,124 @@
+use std::error::Error;
+use graphplan::{GraphPlan, SimpleSolver};
+use graphplan::schema::{proposition, ActionSchema, Grounder, Parameter, Template};
+
+
+/// The kitchen domain from `pddl/kitchen-domain.pddl` written with
+/// action schemas instead of PDDL
+fn schemas() -> Vec<ActionSchema> {
+    let pick_up = ActionSchema::new(
+        "pick-up",
+        vec![
+            Parameter::new("?arm", "bot"),
+            Parameter::new("?cupcake", "locatable"),
+            Parameter::new("?loc", "location"),
+        ],
+        vec![
+            Template::new("on", &["?arm", "?loc"]),
+            Template::new("on", &["?cupcake", "?loc"]),
+            Template::new("arm-empty", &[]),
+        ],
+        vec![
+            Template::new("on", &["?cupcake", "?loc"]).negate(),
+            Template::new("holding", &["?arm", "?cupcake"]),
+            Template::new("arm-empty", &[]).negate(),
+        ],
+    );
+
+    let drop = ActionSchema::new(
+        "drop",
+        vec![
+            Parameter::new("?arm", "bot"),
+            Parameter::new("?cupcake", "locatable"),
+            Parameter::new("?loc", "location"),
+        ],
+        vec![
+            Template::new("on", &["?arm", "?loc"]),
+            Template::new("holding", &["?arm", "?cupcake"]),
+        ],
+        vec![
+            Template::new("on", &["?cupcake", "?loc"]),
+            Template::new("arm-empty", &[]),
+            Template::new("holding", &["?arm", "?cupcake"]).negate(),
+        ],
+    );
+
+    let move_arm = ActionSchema::new(
+        "move",
+        vec![
+            Parameter::new("?arm", "bot"),
+            Parameter::new("?from", "location"),
+            Parameter::new("?to", "location"),
+        ],
+        vec![
+            Template::new("on", &["?arm", "?from"]),
+            Template::new("path", &["?from", "?to"]),
+        ],
+        vec![
+            Template::new("on", &["?arm", "?to"]),
+            Template::new("on", &["?arm", "?from"]).negate(),
+        ],
+    );
+
+    vec![pick_up, drop, move_arm]
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut grounder = Grounder::new();
+    grounder
+        .add_type("location", "object")
+        .add_type("locatable", "object")
+        .add_type("bot", "locatable")
+        .add_type("cup", "locatable")
+        .add_type("robot", "bot")
+        .add_object("arm", "robot")
+        .add_object("coffeecup", "cup")
+        .add_object("teacup", "cup");
+    for loc in &["cupboard", "table", "plate", "fridge"] {
+        grounder.add_object(loc, "location");
+    }
+
+    let paths = [
+        ("table", "cupboard"),
+        ("cupboard", "plate"),
+        ("table", "fridge"),
+        ("plate", "table"),
+        ("cupboard", "fridge"),
+        ("fridge", "cupboard"),
+        ("fridge", "plate"),
+    ];
+    let mut initial_props = vec![
+        proposition("on", &["arm", "table"]),
+        proposition("on", &["coffeecup", "cupboard"]),
+        proposition("on", &["teacup", "cupboard"]),
+        proposition("arm-empty", &[]),
+    ];
+    initial_props.extend(paths.iter().map(|(from, to)| proposition("path", &[from, to])));
+    let goals = [
+        proposition("on", &["coffeecup", "plate"]),
+        proposition("on", &["teacup", "plate"]),
+    ];
+
+    // Moves along paths that don't exist are never generated
+    let ground = grounder.ground(&schemas(), &initial_props.iter().collect());
+    let actions = ground.actions();
+    println!("Grounded {} actions", actions.len());
+
+    let domain = GraphPlan::create_domain(
+        initial_props.iter().collect(),
+        goals.iter().collect(),
+        actions.iter().collect(),
+    );
+    let mut pg = GraphPlan::from_domain(&domain);
+
+    println!("Plan:");
+
+    let plan = pg.search::<SimpleSolver>()?;
+    for (idx, step) in GraphPlan::format_plan(plan).iter().enumerate() {
+        for action in step {
+            println!("{}. {}", idx + 1, action.get_action()?);
+        }
+    }
+
+    Ok(())
+}
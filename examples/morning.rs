@@ -1,5 +1,5 @@
 #[macro_use] extern crate graphplan;
-use graphplan::{Proposition, Action, GraphPlan, SimpleSolver};
+use graphplan::{Proposition, Action, DomainOptions, GraphPlan, SimpleSolver};
 
 
 fn main() {
@@ -10,7 +10,6 @@ fn main() {
     let not_p2 = p2.negate();
 
     let p3 = Proposition::from("at work");
-    let p4 = p3.negate();
 
     let a1 = Action::new(
         "drink coffee",
@@ -30,10 +29,13 @@ fn main() {
         fragset!{[&p3]},
     );
 
-    let domain = GraphPlan::create_domain(
-        fragset!{[&p1, &p2, &p4]},
+    // Not being at work is implied by leaving it out of the
+    // initial state
+    let domain = GraphPlan::create_domain_with(
+        fragset!{[&p1, &p2]},
         fragset!{[&not_p1, &not_p2, &p3]},
-        fragset!{[&a1, &a2, &a3]}
+        fragset!{[&a1, &a2, &a3]},
+        DomainOptions::new().closed_world(true),
     );
     let mut pg = GraphPlan::from_domain(&domain);
 
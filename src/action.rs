@@ -1,84 +1,195 @@
 use std::cmp::{Ordering};
 use std::fmt::Display;
 use std::hash::{Hash, Hasher};
-use std::collections::hash_map::DefaultHasher;
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
 use crate::proposition::Proposition;
+use crate::error::Error;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 #[derive(Hash, Eq, PartialEq, Clone, Debug, Ord, PartialOrd)]
-pub enum ActionType<'a, ActionId, PropositionId: Display + Hash> {
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ActionType<ActionId, PropositionId: Display + Hash> {
     Action(ActionId),
-    Maintenance(&'a Proposition<PropositionId>)
+    Maintenance(Proposition<PropositionId>)
+}
+
+/// Effects that only happen if all of the conditions hold before
+/// the action is taken, like PDDL's `(when cond effect)`
+#[derive(Eq, PartialEq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ConditionalEffect<PropositionId: Display + Hash + PartialEq + Eq + Clone> {
+    pub conditions: HashSet<Proposition<PropositionId>>,
+    pub effects: HashSet<Proposition<PropositionId>>,
 }
 
 #[derive(Eq, Clone, Debug)]
-pub struct Action<'a, ActionId: Hash + Clone, PropositionId: Display + Hash + PartialEq + Eq + Clone> {
-    pub id: ActionType<'a, ActionId, PropositionId>,
-    pub reqs: HashSet<&'a Proposition<PropositionId>>,
-    pub effects: HashSet<&'a Proposition<PropositionId>>,
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Action<ActionId: Hash + Clone, PropositionId: Display + Hash + PartialEq + Eq + Clone> {
+    pub id: ActionType<ActionId, PropositionId>,
+    pub reqs: HashSet<Proposition<PropositionId>>,
+    pub effects: HashSet<Proposition<PropositionId>>,
+    #[cfg_attr(feature = "serde", serde(default = "Vec::new"))]
+    pub conditional_effects: Vec<ConditionalEffect<PropositionId>>,
+    /// Cost of taking the action, 1 by default and 0 for maintenance
+    /// actions
+    #[cfg_attr(feature = "serde", serde(default = "default_cost"))]
+    pub cost: u64,
+}
+
+#[cfg(feature = "serde")]
+fn default_cost() -> u64 {
+    1
 }
 
 /// Actions are hashed based on their id, that means you can't have
 /// two actions of the same id in a HashSet even if they have
 /// different reqs and effects
-impl<'a, ActionId: Hash + Clone, PropositionId: Display + Hash + PartialEq + Eq + Clone> Hash for Action<'a, ActionId, PropositionId> {
+impl<ActionId: Hash + Clone, PropositionId: Display + Hash + PartialEq + Eq + Clone> Hash for Action<ActionId, PropositionId> {
     fn hash<H>(&self, state: &mut H) where H: Hasher {
         self.id.hash(state);
     }
 }
 
-impl<'a, ActionId: Hash + Clone, PropositionId: Clone + Eq + Hash + Display> PartialEq for Action<'a, ActionId, PropositionId> {
+/// Like hashing, actions are compared by their id alone
+impl<ActionId: Hash + Clone + PartialEq, PropositionId: Clone + Eq + Hash + Display> PartialEq for Action<ActionId, PropositionId> {
     fn eq(&self, other: &Self) -> bool {
-        let mut hasher_left = DefaultHasher::new();
-        let mut hasher_right = DefaultHasher::new();
-        self.hash(&mut hasher_left);
-        other.hash(&mut hasher_right);
-        hasher_left.finish() == hasher_right.finish()
+        self.id == other.id
     }
 }
 
-impl<'a, ActionId: Ord + Clone + Hash, PropositionId: Ord + PartialEq + Eq + Display + Hash + Clone> Ord for Action<'a, ActionId, PropositionId> {
+impl<ActionId: Ord + Clone + Hash, PropositionId: Ord + PartialEq + Eq + Display + Hash + Clone> Ord for Action<ActionId, PropositionId> {
     fn cmp(&self, other: &Self) -> Ordering {
         (self.id).cmp(&other.id)
     }
 }
 
-impl<'a, ActionId: Hash + Ord + Clone, PropositionId: Ord + PartialEq + Eq + Display + Hash + Clone> PartialOrd for Action<'a, ActionId, PropositionId> {
+impl<ActionId: Hash + Ord + Clone, PropositionId: Ord + PartialEq + Eq + Display + Hash + Clone> PartialOrd for Action<ActionId, PropositionId> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some((self.id).cmp(&other.id))
+        Some(self.cmp(other))
     }
 }
 
-impl<'a, ActionId: Hash + Clone, PropositionId: Display + Hash + Clone + PartialEq + Eq> Action<'a, ActionId, PropositionId> {
-    pub fn new(id: ActionId,
-               reqs: HashSet<&'a Proposition<PropositionId>>,
-               effects: HashSet<&'a Proposition<PropositionId>>)
-               -> Action<'a, ActionId, PropositionId> {
+impl<ActionId: Hash + Clone, PropositionId: Display + Hash + Clone + PartialEq + Eq> Action<ActionId, PropositionId> {
+    /// Returns a new action. The action keeps its own copy of the
+    /// propositions so it does not borrow from them.
+    pub fn new<'p, R, E>(id: ActionId, reqs: R, effects: E) -> Action<ActionId, PropositionId>
+    where
+        PropositionId: 'p,
+        R: IntoIterator<Item = &'p Proposition<PropositionId>>,
+        E: IntoIterator<Item = &'p Proposition<PropositionId>>,
+    {
         Action {
             id: ActionType::Action(id),
-            reqs,
-            effects,
+            reqs: reqs.into_iter().cloned().collect(),
+            effects: effects.into_iter().cloned().collect(),
+            conditional_effects: Vec::new(),
+            cost: 1,
         }
     }
 
-    pub fn new_maintenance(prop: &'a Proposition<PropositionId>) -> Action<'a, ActionId, PropositionId> {
+    pub fn new_maintenance(prop: &Proposition<PropositionId>) -> Action<ActionId, PropositionId> {
         Action {
-            id: ActionType::Maintenance(prop),
-            reqs: fragset!{[prop]},
-            // reqs: hashset!{prop},
-            effects: fragset!{[prop]},
-            // effects: hashset!{prop},
+            id: ActionType::Maintenance(prop.clone()),
+            reqs: fragset!{[prop.clone()]},
+            effects: fragset!{[prop.clone()]},
+            conditional_effects: Vec::new(),
+            cost: 0,
         }
     }
 
-    pub fn get_action(&self) -> &ActionId {
+    pub fn set_cost(&mut self, cost: u64) -> &mut Self {
+        self.cost = cost;
+        self
+    }
+
+    /// Adds effects that only happen if all of `conditions` hold
+    /// before the action is taken. A conditional effect overrides an
+    /// unconditional one it contradicts. Condition atoms that are
+    /// neither true nor false in the initial state are assumed false
+    /// when the domain is created.
+    pub fn when<'p, C, E>(&mut self, conditions: C, effects: E) -> &mut Self
+    where
+        PropositionId: 'p,
+        C: IntoIterator<Item = &'p Proposition<PropositionId>>,
+        E: IntoIterator<Item = &'p Proposition<PropositionId>>,
+    {
+        self.conditional_effects.push(ConditionalEffect {
+            conditions: conditions.into_iter().cloned().collect(),
+            effects: effects.into_iter().cloned().collect(),
+        });
+        self
+    }
+
+    /// Returns the effects of taking the action in `state` including
+    /// the conditional effects whose conditions hold
+    pub fn effects_in(&self, state: &HashSet<Proposition<PropositionId>>) -> HashSet<Proposition<PropositionId>> {
+        let mut effects = self.effects.clone();
+        for ce in self.conditional_effects.iter().filter(|ce| ce.conditions.is_subset(state)) {
+            for e in &ce.effects {
+                effects.remove(&e.negate());
+                effects.insert(e.clone());
+            }
+        }
+        effects
+    }
+
+    /// Returns the id of the action or an error if this is a
+    /// maintenance action
+    pub fn get_action(&self) -> Result<&ActionId, Error> {
         match &self.id {
-            ActionType::Action(action) => action,
-            _ => panic!("Attempted to get an action from a maintenance action type"),
+            ActionType::Action(action) => Ok(action),
+            ActionType::Maintenance(_) => Err(Error::MaintenanceAction),
         }
     }
 }
 
+/// Requirements and effects of one way an action can play out
+pub(crate) type Variant<PropositionId> = (BTreeSet<Proposition<PropositionId>>, BTreeSet<Proposition<PropositionId>>);
+
+impl<ActionId: Hash + Clone, PropositionId: Display + Hash + Ord + Clone> Action<ActionId, PropositionId> {
+    /// Returns the requirements and effects of each way the action
+    /// can play out, compiling its conditional effects away. Each
+    /// conditional effect either fires, requiring its conditions, or
+    /// doesn't, requiring the negation of one of them. An action
+    /// without conditional effects has a single variant.
+    pub(crate) fn variants(&self) -> Vec<Variant<PropositionId>> {
+        let mut variants: BTreeSet<Variant<PropositionId>> = BTreeSet::new();
+        variants.insert((self.reqs.iter().cloned().collect(), self.effects.iter().cloned().collect()));
+
+        for ce in &self.conditional_effects {
+            let mut expanded = BTreeSet::new();
+            for (reqs, effects) in variants {
+                // Skip the variants that require a proposition and
+                // its negation since they can never be taken
+                let consistent = |p: &Proposition<PropositionId>| !reqs.contains(&p.negate());
+
+                if ce.conditions.iter().all(consistent) {
+                    let mut fired = (reqs.clone(), effects.clone());
+                    fired.0.extend(ce.conditions.iter().cloned());
+                    for e in &ce.effects {
+                        fired.1.remove(&e.negate());
+                        fired.1.insert(e.clone());
+                    }
+                    expanded.insert(fired);
+                }
+
+                for c in ce.conditions.iter().filter(|c| !reqs.contains(*c)) {
+                    let not_c = c.negate();
+                    if consistent(&not_c) {
+                        let mut reqs = reqs.clone();
+                        reqs.insert(not_c);
+                        expanded.insert((reqs, effects.clone()));
+                    }
+                }
+            }
+            variants = expanded;
+        }
+
+        variants.into_iter().collect()
+    }
+}
+
 #[cfg(test)]
 mod test_action {
     use super::*;
@@ -110,6 +221,41 @@ mod test_action {
         assert_eq!(set.len(), 1);
     }
 
+    #[test]
+    fn conditional_effects_compile_into_variants() {
+        let p1 = Proposition::from("truck at depot");
+        let p2 = Proposition::from("box loaded");
+        let p3 = Proposition::from("box at shop");
+        let not_p2 = p2.negate();
+        let a: Action<TestActionId, &str> = Action::new(TestActionId::A, fragset!{[&p1]}, fragset!{})
+            .when(fragset!{[&p2]}, fragset!{[&p3]})
+            .clone();
+
+        let variants = a.variants();
+        assert_eq!(variants, vec![
+            (btreeset!{p1.clone(), p2.clone()}, btreeset!{p3.clone()}),
+            (btreeset!{p1.clone(), not_p2.clone()}, btreeset!{}),
+        ]);
+
+        let fired = a.effects_in(&fragset!{[p1.clone(), p2]});
+        assert_eq!(fired, fragset!{[p3]});
+        assert!(a.effects_in(&fragset!{[p1, not_p2]}).is_empty());
+    }
+
+    #[test]
+    fn conditional_effects_override_unconditional_ones() {
+        let p1 = Proposition::from("lights on");
+        let not_p1 = p1.negate();
+        let p2 = Proposition::from("override");
+        let a: Action<TestActionId, &str> = Action::new(TestActionId::A, fragset!{}, fragset!{[&not_p1]})
+            .when(fragset!{[&p2]}, fragset!{[&p1]})
+            .clone();
+
+        assert_eq!(a.effects_in(&fragset!{[p2.clone()]}), fragset!{[p1.clone()]});
+        let (reqs, effects) = &a.variants()[0];
+        assert_eq!((reqs, effects), (&btreeset!{p2}, &btreeset!{p1}));
+    }
+
     #[test]
     fn maintenance_action_works() {
         let p1 = Proposition::from("test");
@@ -121,4 +267,14 @@ mod test_action {
         let m3 = Action::new_maintenance(&p2);
         assert_ne!(m2, m3);
     }
+
+    #[test]
+    fn costs_default_to_one() {
+        let p1 = Proposition::from("test");
+        let a: Action<TestActionId, &str> = Action::new(TestActionId::A, fragset!{}, fragset!{[&p1]});
+        assert_eq!(a.cost, 1);
+        assert_eq!(a.clone().set_cost(3).cost, 3);
+        let m: Action<TestActionId, &str> = Action::new_maintenance(&p1);
+        assert_eq!(m.cost, 0);
+    }
 }
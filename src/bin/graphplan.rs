@@ -0,0 +1,414 @@
+use std::env;
+use std::error::Error as StdError;
+use std::fmt::Write;
+use std::fs;
+use std::process;
+use std::time::Duration;
+use graphplan::{pddl, DotOptions, Error, GraphPlan, GraphPlanSolver, HeuristicSearchSolver, Limit,
+                SatSolver, SearchLimits, SearchStats, SimpleSolver, Solution};
+
+
+const USAGE: &str = "\
+Usage: graphplan [OPTIONS] <DOMAIN> <PROBLEM>
+
+Searches for a plan for a PDDL domain and problem file and prints it
+as numbered steps. Actions that share a number can run in parallel.
+
+Options:
+    --solver <NAME>      Solver to search the plangraph with [default: simple]
+                         Solvers: simple, sat, heuristic
+    --max-depth <N>      Give up rather than extend the plangraph past depth N
+    --timeout <SECS>     Give up after this many seconds
+    --max-nodes <N>      Give up after the solver expands N search nodes
+    --max-backtracks <N> Give up after the solver backtracks N times
+    --dot <FILE>         Write the plangraph and its mutexes to FILE in DOT format
+    --dimacs <FILE>      Write the SAT encoding of the plangraph to FILE in DIMACS format
+    --json               Print the result as JSON
+    --stats              Print the size of the plangraph and what the search did
+    -h, --help           Print this message
+
+Exit codes:
+    0    A plan was found
+    1    The problem is proven unsolvable
+    2    The search timed out
+    3    The search stopped at another limit or gave up
+    4    The arguments or input files are invalid
+";
+
+const EXIT_SOLVED: i32 = 0;
+const EXIT_UNSOLVABLE: i32 = 1;
+const EXIT_TIMEOUT: i32 = 2;
+const EXIT_GAVE_UP: i32 = 3;
+const EXIT_INVALID: i32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SolverKind {
+    Simple,
+    Sat,
+    Heuristic,
+}
+
+impl SolverKind {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "simple" => Some(SolverKind::Simple),
+            "sat" => Some(SolverKind::Sat),
+            "heuristic" => Some(SolverKind::Heuristic),
+            _ => None,
+        }
+    }
+
+    fn solver(self) -> Box<dyn GraphPlanSolver<String, String>> {
+        match self {
+            SolverKind::Simple => Box::new(SimpleSolver::default()),
+            SolverKind::Sat => Box::new(SatSolver),
+            SolverKind::Heuristic => Box::new(HeuristicSearchSolver::default()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Options {
+    domain: String,
+    problem: String,
+    solver: SolverKind,
+    max_depth: Option<usize>,
+    timeout: Option<Duration>,
+    max_nodes: Option<usize>,
+    max_backtracks: Option<usize>,
+    dot: Option<String>,
+    dimacs: Option<String>,
+    json: bool,
+    stats: bool,
+}
+
+fn count(flag: &str, value: String) -> Result<usize, String> {
+    value.parse().map_err(|_| format!("invalid {} '{}'", flag, value))
+}
+
+/// Returns the parsed options, `Ok(None)` if help was requested
+fn parse_args<I: IntoIterator<Item=String>>(args: I) -> Result<Option<Options>, String> {
+    let mut files = Vec::new();
+    let mut solver = SolverKind::Simple;
+    let mut max_depth = None;
+    let mut timeout = None;
+    let mut max_nodes = None;
+    let mut max_backtracks = None;
+    let mut dot = None;
+    let mut dimacs = None;
+    let mut json = false;
+    let mut stats = false;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| args.next()
+            .ok_or_else(|| format!("{} requires a value", flag));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--solver" => {
+                let name = value("--solver")?;
+                solver = SolverKind::from_name(&name)
+                    .ok_or_else(|| format!("unknown solver '{}'", name))?;
+            },
+            "--max-depth" => max_depth = Some(count("--max-depth", value("--max-depth")?)?),
+            "--max-nodes" => max_nodes = Some(count("--max-nodes", value("--max-nodes")?)?),
+            "--max-backtracks" => max_backtracks = Some(count("--max-backtracks", value("--max-backtracks")?)?),
+            "--timeout" => {
+                let secs = value("--timeout")?;
+                let secs: f64 = secs.parse()
+                    .ok().filter(|s: &f64| s.is_finite() && *s >= 0.0)
+                    .ok_or_else(|| format!("invalid timeout '{}'", secs))?;
+                timeout = Some(Duration::from_secs_f64(secs));
+            },
+            "--dot" => dot = Some(value("--dot")?),
+            "--dimacs" => dimacs = Some(value("--dimacs")?),
+            "--json" => json = true,
+            "--stats" => stats = true,
+            flag if flag.starts_with('-') && flag.len() > 1 => {
+                return Err(format!("unknown option '{}'", flag));
+            },
+            _ => files.push(arg),
+        }
+    }
+
+    if files.len() != 2 {
+        return Err(String::from("expected a domain file and a problem file"));
+    }
+    let problem = files.pop().unwrap();
+    let domain = files.pop().unwrap();
+
+    Ok(Some(Options {
+        domain, problem, solver, max_depth, timeout, max_nodes, max_backtracks, dot, dimacs, json, stats
+    }))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Stats {
+    layers: usize,
+    depth: usize,
+    mutex_props: usize,
+    mutex_actions: usize,
+    search: SearchStats,
+}
+
+impl Stats {
+    fn new(pg: &GraphPlan<String, String>, search: SearchStats) -> Self {
+        let plangraph = pg.plangraph();
+        Stats {
+            layers: plangraph.layers.len(),
+            depth: plangraph.depth(),
+            mutex_props: plangraph.mutex_props.values().map(|m| m.len()).sum(),
+            mutex_actions: plangraph.mutex_actions.values().map(|m| m.len()).sum(),
+            search,
+        }
+    }
+}
+
+/// Returns the action names of each step sorted so the output is
+/// stable
+fn plan_steps(solution: Solution<String, String>) -> Vec<Vec<String>> {
+    GraphPlan::format_plan(solution).into_iter()
+        .map(|step| {
+            let mut names: Vec<String> = step.iter()
+                .filter_map(|a| a.get_action().ok().cloned())
+                .collect();
+            names.sort();
+            names
+        })
+        .collect()
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn to_json(status: &str, result: &Result<Vec<Vec<String>>, String>, stats: Option<&Stats>) -> String {
+    let mut out = format!("{{\"status\":{}", json_string(status));
+    match result {
+        Ok(steps) => {
+            let steps: Vec<String> = steps.iter()
+                .map(|step| {
+                    let names: Vec<String> = step.iter().map(|a| json_string(a)).collect();
+                    format!("[{}]", names.join(","))
+                })
+                .collect();
+            write!(out, ",\"plan\":[{}]", steps.join(",")).unwrap();
+        },
+        Err(msg) => write!(out, ",\"error\":{}", json_string(msg)).unwrap(),
+    }
+    if let Some(stats) = stats {
+        write!(out, ",\"stats\":{{\"layers\":{},\"depth\":{},\"mutex_props\":{},\"mutex_actions\":{},\
+                     \"nodes\":{},\"memo_hits\":{},\"backtracks\":{},\"max_stack_depth\":{},\
+                     \"extend_secs\":{:.3},\"search_secs\":{:.3}}}",
+               stats.layers, stats.depth, stats.mutex_props, stats.mutex_actions,
+               stats.search.nodes_expanded, stats.search.memo_hits, stats.search.backtracks,
+               stats.search.max_stack_depth, stats.search.extend_time.as_secs_f64(),
+               stats.search.search_time.as_secs_f64()).unwrap();
+    }
+    out.push('}');
+    out
+}
+
+fn exit_code(error: &Error) -> i32 {
+    match error {
+        Error::LeveledOff | Error::GoalsUnreachable | Error::GoalsMutex | Error::StateSpaceExhausted => EXIT_UNSOLVABLE,
+        Error::LimitReached(Limit::Deadline) => EXIT_TIMEOUT,
+        Error::LimitReached(_) | Error::SearchExhausted => EXIT_GAVE_UP,
+        _ => EXIT_INVALID,
+    }
+}
+
+fn status(code: i32) -> &'static str {
+    match code {
+        EXIT_SOLVED => "solved",
+        EXIT_UNSOLVABLE => "unsolvable",
+        EXIT_TIMEOUT => "timeout",
+        EXIT_GAVE_UP => "gave_up",
+        _ => "error",
+    }
+}
+
+fn load(options: &Options) -> Result<GraphPlan<String, String>, Box<dyn StdError>> {
+    let domain_src = fs::read_to_string(&options.domain)
+        .map_err(|e| format!("{}: {}", options.domain, e))?;
+    let problem_src = fs::read_to_string(&options.problem)
+        .map_err(|e| format!("{}: {}", options.problem, e))?;
+    let domain = pddl::parse_domain(&options.domain, &domain_src)?;
+    let problem = pddl::parse_problem(&options.problem, &problem_src, &domain)?;
+    let ground = pddl::ground(&domain, &problem);
+
+    Ok(GraphPlan::from_domain(&ground.domain()))
+}
+
+fn limits(options: &Options) -> SearchLimits {
+    let mut limits = SearchLimits::new();
+    if let Some(timeout) = options.timeout {
+        limits.timeout(timeout);
+    }
+    if let Some(depth) = options.max_depth {
+        limits.max_depth(depth);
+    }
+    if let Some(nodes) = options.max_nodes {
+        limits.max_nodes(nodes);
+    }
+    if let Some(backtracks) = options.max_backtracks {
+        limits.max_backtracks(backtracks);
+    }
+    limits
+}
+
+fn run(options: &Options) -> i32 {
+    let mut pg = match load(options) {
+        Ok(pg) => pg,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return EXIT_INVALID;
+        },
+    };
+    pg.set_limits(limits(options));
+    let (result, search_stats) = pg.search_with_stats(&mut *options.solver.solver());
+
+    if let Some(path) = &options.dot {
+        let dot = pg.plangraph().to_dot_with(DotOptions::new().mutexes(true));
+        if let Err(e) = fs::write(path, dot) {
+            eprintln!("error: {}: {}", path, e);
+            return EXIT_INVALID;
+        }
+    }
+
+    if let Some(path) = &options.dimacs {
+        let written = SatSolver::to_dimacs(pg.plangraph())
+            .map_err(|e| e.to_string())
+            .and_then(|dimacs| fs::write(path, dimacs).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            eprintln!("error: {}: {}", path, e);
+            return EXIT_INVALID;
+        }
+    }
+
+    let code = result.as_ref().map(|_| EXIT_SOLVED).unwrap_or_else(exit_code);
+    let result = result.map(plan_steps).map_err(|e| e.to_string());
+    let stats = if options.stats { Some(Stats::new(&pg, search_stats)) } else { None };
+
+    if options.json {
+        println!("{}", to_json(status(code), &result, stats.as_ref()));
+    } else {
+        match &result {
+            Ok(steps) => {
+                for (idx, step) in steps.iter().enumerate() {
+                    for action in step {
+                        println!("{}. {}", idx + 1, action);
+                    }
+                }
+            },
+            Err(msg) => eprintln!("No plan: {}", msg),
+        }
+        if let Some(stats) = stats {
+            println!("layers: {}", stats.layers);
+            println!("depth: {}", stats.depth);
+            println!("proposition mutexes: {}", stats.mutex_props);
+            println!("action mutexes: {}", stats.mutex_actions);
+            println!("nodes expanded: {}", stats.search.nodes_expanded);
+            println!("memo hits: {}", stats.search.memo_hits);
+            println!("backtracks: {}", stats.search.backtracks);
+            println!("max stack depth: {}", stats.search.max_stack_depth);
+            println!("extend time: {:.3}s", stats.search.extend_time.as_secs_f64());
+            println!("search time: {:.3}s", stats.search.search_time.as_secs_f64());
+        }
+    }
+
+    code
+}
+
+fn main() {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return;
+        },
+        Err(msg) => {
+            eprintln!("error: {}\n\n{}", msg, USAGE);
+            process::exit(EXIT_INVALID);
+        },
+    };
+    process::exit(run(&options));
+}
+
+#[cfg(test)]
+mod cli_test {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_flags() {
+        let options = parse_args(args(&[
+            "--max-depth", "5", "domain.pddl", "--timeout", "1.5", "--solver", "sat",
+            "--max-nodes", "100", "--max-backtracks", "10",
+            "--dot", "graph.dot", "--dimacs", "graph.cnf", "--json", "--stats", "problem.pddl",
+        ])).unwrap().unwrap();
+        assert_eq!(options, Options {
+            domain: String::from("domain.pddl"),
+            problem: String::from("problem.pddl"),
+            solver: SolverKind::Sat,
+            max_depth: Some(5),
+            timeout: Some(Duration::from_millis(1500)),
+            max_nodes: Some(100),
+            max_backtracks: Some(10),
+            dot: Some(String::from("graph.dot")),
+            dimacs: Some(String::from("graph.cnf")),
+            json: true,
+            stats: true,
+        });
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(parse_args(args(&["domain.pddl"])).is_err());
+        assert!(parse_args(args(&["--solver", "magic", "d.pddl", "p.pddl"])).is_err());
+        assert!(parse_args(args(&["--max-depth", "deep", "d.pddl", "p.pddl"])).is_err());
+        assert!(parse_args(args(&["--timeout", "-1", "d.pddl", "p.pddl"])).is_err());
+        assert!(parse_args(args(&["d.pddl", "p.pddl", "--dot"])).is_err());
+        assert_eq!(parse_args(args(&["--help"])), Ok(None));
+    }
+
+    #[test]
+    fn writes_json() {
+        let steps = Ok(vec![vec![String::from("a \"b\"")], vec![String::from("c"), String::from("d")]]);
+        let mut search = SearchStats::new();
+        search.nodes_expanded = 4;
+        search.memo_hits = 1;
+        search.backtracks = 2;
+        search.max_stack_depth = 3;
+        search.extend_time = Duration::from_millis(1500);
+        search.search_time = Duration::from_millis(20);
+        let stats = Stats { layers: 5, depth: 2, mutex_props: 1, mutex_actions: 3, search };
+        assert_eq!(
+            to_json("solved", &steps, Some(&stats)),
+            "{\"status\":\"solved\",\"plan\":[[\"a \\\"b\\\"\"],[\"c\",\"d\"]],\
+             \"stats\":{\"layers\":5,\"depth\":2,\"mutex_props\":1,\"mutex_actions\":3,\
+             \"nodes\":4,\"memo_hits\":1,\"backtracks\":2,\"max_stack_depth\":3,\
+             \"extend_secs\":1.500,\"search_secs\":0.020}}"
+        );
+        assert_eq!(
+            to_json("unsolvable", &Err(String::from("goals are unreachable")), None),
+            "{\"status\":\"unsolvable\",\"error\":\"goals are unreachable\"}"
+        );
+    }
+}
@@ -0,0 +1,393 @@
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+use crate::domain::{ActionIndex, PropositionIndex};
+use crate::pairset::PairSet;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+
+const WORD_BITS: usize = 64;
+
+/// Dense ids that can be stored in an `IndexSet`
+pub trait Index: Copy + Ord {
+    fn from_index(idx: usize) -> Self;
+    fn index(self) -> usize;
+}
+
+impl Index for PropositionIndex {
+    fn from_index(idx: usize) -> Self {
+        PropositionIndex(idx)
+    }
+
+    fn index(self) -> usize {
+        self.0
+    }
+}
+
+impl Index for ActionIndex {
+    fn from_index(idx: usize) -> Self {
+        ActionIndex(idx)
+    }
+
+    fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// A set of dense ids stored one bit per id. The set grows as ids
+/// are inserted and trailing empty words are ignored when comparing
+/// so sets built in different orders are equal.
+pub struct IndexSet<I> {
+    words: Vec<u64>,
+    _index: PhantomData<I>,
+}
+
+impl<I: Index> IndexSet<I> {
+    pub fn new() -> Self {
+        IndexSet { words: Vec::new(), _index: PhantomData }
+    }
+
+    /// Words up to and including the last non empty one
+    fn trimmed(&self) -> &[u64] {
+        let len = self.words.iter().rposition(|w| *w != 0).map_or(0, |i| i + 1);
+        &self.words[..len]
+    }
+
+    pub fn insert(&mut self, item: I) -> bool {
+        let (word, bit) = (item.index() / WORD_BITS, item.index() % WORD_BITS);
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        let missing = self.words[word] & (1 << bit) == 0;
+        self.words[word] |= 1 << bit;
+        missing
+    }
+
+    pub fn remove(&mut self, item: I) -> bool {
+        let (word, bit) = (item.index() / WORD_BITS, item.index() % WORD_BITS);
+        match self.words.get_mut(word) {
+            Some(w) if *w & (1 << bit) != 0 => {
+                *w &= !(1 << bit);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    pub fn contains(&self, item: I) -> bool {
+        let (word, bit) = (item.index() / WORD_BITS, item.index() % WORD_BITS);
+        matches!(self.words.get(word), Some(w) if w & (1 << bit) != 0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|w| *w == 0)
+    }
+
+    /// Iterates over the ids in ascending order
+    pub fn iter(&self) -> Iter<'_, I> {
+        Iter { words: &self.words, word: 0, bits: self.words.first().copied().unwrap_or(0), _index: PhantomData }
+    }
+
+    /// Returns the smallest id in the set
+    pub fn first(&self) -> Option<I> {
+        self.iter().next()
+    }
+
+    pub fn is_subset(&self, other: &IndexSet<I>) -> bool {
+        self.words.iter().enumerate()
+            .all(|(i, w)| w & !other.words.get(i).copied().unwrap_or(0) == 0)
+    }
+
+    /// Returns true if the sets have an id in common
+    pub fn intersects(&self, other: &IndexSet<I>) -> bool {
+        self.words.iter().zip(&other.words).any(|(a, b)| a & b != 0)
+    }
+
+    pub fn union_with(&mut self, other: &IndexSet<I>) {
+        if other.words.len() > self.words.len() {
+            self.words.resize(other.words.len(), 0);
+        }
+        for (a, b) in self.words.iter_mut().zip(&other.words) {
+            *a |= b;
+        }
+    }
+
+    pub fn intersect_with(&mut self, other: &IndexSet<I>) {
+        for (i, a) in self.words.iter_mut().enumerate() {
+            *a &= other.words.get(i).copied().unwrap_or(0);
+        }
+    }
+}
+
+impl<I: Index> Default for IndexSet<I> {
+    fn default() -> Self {
+        IndexSet::new()
+    }
+}
+
+impl<I> Clone for IndexSet<I> {
+    fn clone(&self) -> Self {
+        IndexSet { words: self.words.clone(), _index: PhantomData }
+    }
+}
+
+impl<I: Index> PartialEq for IndexSet<I> {
+    fn eq(&self, other: &Self) -> bool {
+        self.trimmed() == other.trimmed()
+    }
+}
+
+impl<I: Index> Eq for IndexSet<I> {}
+
+impl<I: Index> Hash for IndexSet<I> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.trimmed().hash(state);
+    }
+}
+
+impl<I: Index + fmt::Debug> fmt::Debug for IndexSet<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<I: Index> FromIterator<I> for IndexSet<I> {
+    fn from_iter<T: IntoIterator<Item = I>>(iter: T) -> Self {
+        let mut set = IndexSet::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl<'a, I: Index + 'a> FromIterator<&'a I> for IndexSet<I> {
+    fn from_iter<T: IntoIterator<Item = &'a I>>(iter: T) -> Self {
+        iter.into_iter().copied().collect()
+    }
+}
+
+impl<I: Index> Extend<I> for IndexSet<I> {
+    fn extend<T: IntoIterator<Item = I>>(&mut self, iter: T) {
+        for item in iter {
+            self.insert(item);
+        }
+    }
+}
+
+impl<'a, I: Index> IntoIterator for &'a IndexSet<I> {
+    type Item = I;
+    type IntoIter = Iter<'a, I>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct Iter<'a, I> {
+    words: &'a [u64],
+    word: usize,
+    bits: u64,
+    _index: PhantomData<I>,
+}
+
+impl<'a, I: Index> Iterator for Iter<'a, I> {
+    type Item = I;
+
+    fn next(&mut self) -> Option<I> {
+        while self.bits == 0 {
+            self.word += 1;
+            self.bits = *self.words.get(self.word)?;
+        }
+        let bit = self.bits.trailing_zeros() as usize;
+        self.bits &= self.bits - 1;
+        Some(I::from_index(self.word * WORD_BITS + bit))
+    }
+}
+
+/// Saved as a list of ids like a `BTreeSet`
+#[cfg(feature = "serde")]
+impl<I: Index + Serialize> Serialize for IndexSet<I> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, I: Index + Deserialize<'de>> Deserialize<'de> for IndexSet<I> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Vec::<I>::deserialize(deserializer)?.into_iter().collect())
+    }
+}
+
+/// Symmetric pairs of dense ids stored as a bit matrix with a row
+/// per id. Rows are only as long as the largest id they hold.
+pub struct MutexMatrix<I> {
+    rows: Vec<IndexSet<I>>,
+    len: usize,
+}
+
+impl<I: Index> MutexMatrix<I> {
+    pub fn new() -> Self {
+        MutexMatrix { rows: Vec::new(), len: 0 }
+    }
+
+    /// Records `a` and `b` as mutex. Returns false if they already
+    /// were or are the same id.
+    pub fn insert(&mut self, PairSet(a, b): PairSet<I>) -> bool {
+        if a == b || self.is_mutex(a, b) {
+            return false;
+        }
+        let len = a.index().max(b.index()) + 1;
+        if self.rows.len() < len {
+            self.rows.resize_with(len, IndexSet::new);
+        }
+        self.rows[a.index()].insert(b);
+        self.rows[b.index()].insert(a);
+        self.len += 1;
+        true
+    }
+
+    pub fn contains(&self, PairSet(a, b): &PairSet<I>) -> bool {
+        self.is_mutex(*a, *b)
+    }
+
+    pub fn is_mutex(&self, a: I, b: I) -> bool {
+        matches!(self.rows.get(a.index()), Some(row) if row.contains(b))
+    }
+
+    /// Returns the ids mutex with `a`
+    pub fn row(&self, a: I) -> Option<&IndexSet<I>> {
+        self.rows.get(a.index())
+    }
+
+    /// Returns true if `a` is mutex with any id in `others`
+    pub fn any_mutex(&self, a: I, others: &IndexSet<I>) -> bool {
+        matches!(self.row(a), Some(row) if row.intersects(others))
+    }
+
+    /// Returns true if any two ids in `items` are mutex
+    pub fn any_pair_mutex(&self, items: &IndexSet<I>) -> bool {
+        items.iter().any(|a| self.any_mutex(a, items))
+    }
+
+    /// Returns the number of mutex pairs
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Iterates over each pair once with the smaller id first
+    pub fn iter(&self) -> impl Iterator<Item = PairSet<I>> + '_ {
+        self.rows.iter()
+            .enumerate()
+            .flat_map(|(a, row)| {
+                let a = I::from_index(a);
+                row.iter().filter(move |b| a < *b).map(move |b| PairSet(a, b))
+            })
+    }
+}
+
+impl<I: Index> Default for MutexMatrix<I> {
+    fn default() -> Self {
+        MutexMatrix::new()
+    }
+}
+
+impl<I> Clone for MutexMatrix<I> {
+    fn clone(&self) -> Self {
+        MutexMatrix { rows: self.rows.clone(), len: self.len }
+    }
+}
+
+impl<I: Index> PartialEq for MutexMatrix<I> {
+    fn eq(&self, other: &Self) -> bool {
+        let empty = IndexSet::new();
+        let rows = self.rows.len().max(other.rows.len());
+        self.len == other.len && (0..rows).all(|i| {
+            self.rows.get(i).unwrap_or(&empty) == other.rows.get(i).unwrap_or(&empty)
+        })
+    }
+}
+
+impl<I: Index> Eq for MutexMatrix<I> {}
+
+impl<I: Index + fmt::Debug> fmt::Debug for MutexMatrix<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<I: Index> FromIterator<PairSet<I>> for MutexMatrix<I> {
+    fn from_iter<T: IntoIterator<Item = PairSet<I>>>(iter: T) -> Self {
+        let mut mutexes = MutexMatrix::new();
+        for pair in iter {
+            mutexes.insert(pair);
+        }
+        mutexes
+    }
+}
+
+/// Saved as a list of pairs like a `HashSet<PairSet>`
+#[cfg(feature = "serde")]
+impl<I: Index + Serialize> Serialize for MutexMatrix<I> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, I: Index + Deserialize<'de>> Deserialize<'de> for MutexMatrix<I> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Vec::<PairSet<I>>::deserialize(deserializer)?.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod bitset_test {
+    use super::*;
+
+    #[test]
+    fn index_set_works() {
+        let mut set: IndexSet<ActionIndex> = vec![ActionIndex(130), ActionIndex(3), ActionIndex(64)]
+            .into_iter()
+            .collect();
+        assert!(set.contains(ActionIndex(64)));
+        assert!(!set.contains(ActionIndex(65)));
+        assert!(!set.insert(ActionIndex(3)));
+        assert_eq!(set.len(), 3);
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![ActionIndex(3), ActionIndex(64), ActionIndex(130)]);
+
+        // Removing the largest id leaves an empty word behind
+        assert!(set.remove(ActionIndex(130)));
+        let other: IndexSet<ActionIndex> = vec![ActionIndex(3), ActionIndex(64)].into_iter().collect();
+        assert_eq!(set, other);
+        assert!(other.is_subset(&set));
+        assert!(set.intersects(&other));
+        assert!(!IndexSet::new().intersects(&other));
+    }
+
+    #[test]
+    fn mutex_matrix_is_symmetric() {
+        let (a, b, c) = (PropositionIndex(0), PropositionIndex(70), PropositionIndex(2));
+        let mut mutexes = MutexMatrix::new();
+        assert!(mutexes.insert(PairSet(b, a)));
+        assert!(!mutexes.insert(PairSet(a, b)));
+        assert!(mutexes.contains(&PairSet(a, b)));
+        assert!(mutexes.is_mutex(b, a));
+        assert!(!mutexes.is_mutex(a, c));
+        assert_eq!(mutexes.len(), 1);
+        assert_eq!(mutexes.iter().collect::<Vec<_>>(), vec![PairSet(a, b)]);
+
+        assert!(mutexes.any_pair_mutex(&[a, b, c].iter().collect()));
+        assert!(!mutexes.any_pair_mutex(&[a, c].iter().collect()));
+        assert_ne!(mutexes, MutexMatrix::new());
+    }
+}
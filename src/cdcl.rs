@@ -0,0 +1,445 @@
+use std::fmt::Write;
+use crate::error::Error;
+use crate::limits::SearchBudget;
+
+
+/// A literal as written in DIMACS: variable `v` (numbered from 1) is
+/// `v` and its negation is `-v`
+pub type Literal = i32;
+
+/// A formula in conjunctive normal form
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cnf {
+    pub num_vars: usize,
+    pub clauses: Vec<Vec<Literal>>,
+}
+
+impl Cnf {
+    pub fn new() -> Self {
+        Cnf::default()
+    }
+
+    /// Returns the positive literal of a fresh variable
+    pub fn new_var(&mut self) -> Literal {
+        self.num_vars += 1;
+        self.num_vars as Literal
+    }
+
+    pub fn add_clause<I: IntoIterator<Item=Literal>>(&mut self, clause: I) {
+        self.clauses.push(clause.into_iter().collect());
+    }
+
+    /// Renders the formula in DIMACS CNF format
+    pub fn to_dimacs(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "p cnf {} {}", self.num_vars, self.clauses.len()).unwrap();
+        for clause in &self.clauses {
+            for lit in clause {
+                write!(out, "{} ", lit).unwrap();
+            }
+            writeln!(out, "0").unwrap();
+        }
+        out
+    }
+
+    /// Solves the formula with a conflict driven clause learning
+    /// solver. Returns a satisfying assignment where `model[v - 1]`
+    /// is the value of variable `v` or `None` if the formula is
+    /// unsatisfiable.
+    pub fn solve(&self) -> Option<Vec<bool>> {
+        self.solve_within(&SearchBudget::default())
+            .expect("Unlimited search reached a limit")
+    }
+
+    /// Same as `solve` but counts decisions as nodes and conflicts as
+    /// backtracks against the budget
+    pub fn solve_within(&self, budget: &SearchBudget) -> Result<Option<Vec<bool>>, Error> {
+        match CdclSolver::new(self) {
+            Some(mut solver) => solver.solve(budget),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Literals are stored as `2 * var + sign` with variables from 0 so
+/// the negation of a literal is `lit ^ 1`
+type Lit = usize;
+type ClauseIdx = usize;
+
+fn var(lit: Lit) -> usize {
+    lit >> 1
+}
+
+fn to_lit(lit: Literal) -> Lit {
+    (lit.unsigned_abs() as usize - 1) * 2 + (lit < 0) as usize
+}
+
+fn lit_value(assigns: &[Option<bool>], lit: Lit) -> Option<bool> {
+    assigns[var(lit)].map(|value| value != (lit & 1 == 1))
+}
+
+/// Restart after `luby(i) * RESTART_UNIT` conflicts
+const RESTART_UNIT: usize = 100;
+const ACTIVITY_DECAY: f64 = 0.95;
+
+/// Returns the ith element (from 1) of the Luby sequence
+/// 1, 1, 2, 1, 1, 2, 4, ...
+fn luby(mut i: usize) -> usize {
+    loop {
+        let mut k = 1;
+        while (1 << k) - 1 < i {
+            k += 1;
+        }
+        if i == (1 << k) - 1 {
+            return 1 << (k - 1);
+        }
+        i -= (1 << (k - 1)) - 1;
+    }
+}
+
+struct CdclSolver {
+    clauses: Vec<Vec<Lit>>,
+    /// Clauses watching each literal, visited when it becomes false
+    watches: Vec<Vec<ClauseIdx>>,
+    assigns: Vec<Option<bool>>,
+    level: Vec<usize>,
+    reason: Vec<Option<ClauseIdx>>,
+    trail: Vec<Lit>,
+    trail_lim: Vec<usize>,
+    queue_head: usize,
+    activity: Vec<f64>,
+    var_inc: f64,
+    seen: Vec<bool>,
+}
+
+impl CdclSolver {
+    /// Returns `None` if the clauses are trivially unsatisfiable
+    fn new(cnf: &Cnf) -> Option<Self> {
+        let num_vars = cnf.num_vars;
+        let mut solver = CdclSolver {
+            clauses: Vec::new(),
+            watches: vec![Vec::new(); num_vars * 2],
+            assigns: vec![None; num_vars],
+            level: vec![0; num_vars],
+            reason: vec![None; num_vars],
+            trail: Vec::new(),
+            trail_lim: Vec::new(),
+            queue_head: 0,
+            activity: vec![0.0; num_vars],
+            var_inc: 1.0,
+            seen: vec![false; num_vars],
+        };
+
+        for clause in &cnf.clauses {
+            let mut lits: Vec<Lit> = clause.iter().map(|l| to_lit(*l)).collect();
+            lits.sort_unstable();
+            lits.dedup();
+            // Skip tautologies
+            if lits.windows(2).any(|w| w[0] ^ 1 == w[1]) {
+                continue;
+            }
+            match lits.len() {
+                0 => return None,
+                1 => match lit_value(&solver.assigns, lits[0]) {
+                    Some(false) => return None,
+                    Some(true) => {},
+                    None => solver.enqueue(lits[0], None),
+                },
+                _ => {
+                    solver.add_clause(lits);
+                },
+            }
+        }
+        Some(solver)
+    }
+
+    fn add_clause(&mut self, lits: Vec<Lit>) -> ClauseIdx {
+        let idx = self.clauses.len();
+        self.watches[lits[0]].push(idx);
+        self.watches[lits[1]].push(idx);
+        self.clauses.push(lits);
+        idx
+    }
+
+    fn decision_level(&self) -> usize {
+        self.trail_lim.len()
+    }
+
+    fn enqueue(&mut self, lit: Lit, reason: Option<ClauseIdx>) {
+        let v = var(lit);
+        self.assigns[v] = Some(lit & 1 == 0);
+        self.level[v] = self.decision_level();
+        self.reason[v] = reason;
+        self.trail.push(lit);
+    }
+
+    /// Assigns the literals implied by unit clauses and returns the
+    /// first clause that becomes false
+    fn propagate(&mut self) -> Option<ClauseIdx> {
+        while self.queue_head < self.trail.len() {
+            let false_lit = self.trail[self.queue_head] ^ 1;
+            self.queue_head += 1;
+
+            let mut watching = std::mem::take(&mut self.watches[false_lit]);
+            let mut i = 0;
+            while i < watching.len() {
+                let idx = watching[i];
+                let clause = &mut self.clauses[idx];
+                // Keep the false literal in the second watch
+                if clause[0] == false_lit {
+                    clause.swap(0, 1);
+                }
+                if lit_value(&self.assigns, clause[0]) == Some(true) {
+                    i += 1;
+                    continue;
+                }
+
+                let assigns = &self.assigns;
+                let replacement = (2..clause.len())
+                    .find(|k| lit_value(assigns, clause[*k]) != Some(false));
+                if let Some(k) = replacement {
+                    clause.swap(1, k);
+                    self.watches[clause[1]].push(idx);
+                    watching.swap_remove(i);
+                    continue;
+                }
+
+                let unit = clause[0];
+                if lit_value(&self.assigns, unit) == Some(false) {
+                    self.watches[false_lit] = watching;
+                    self.queue_head = self.trail.len();
+                    return Some(idx);
+                }
+                self.enqueue(unit, Some(idx));
+                i += 1;
+            }
+            self.watches[false_lit] = watching;
+        }
+        None
+    }
+
+    fn bump(&mut self, v: usize) {
+        self.activity[v] += self.var_inc;
+        if self.activity[v] > 1e100 {
+            for a in self.activity.iter_mut() {
+                *a *= 1e-100;
+            }
+            self.var_inc *= 1e-100;
+        }
+    }
+
+    /// Learns a clause from the conflict by resolving back to the
+    /// first unique implication point. Returns the clause with the
+    /// asserting literal first and the level to backjump to.
+    fn analyze(&mut self, conflict: ClauseIdx) -> (Vec<Lit>, usize) {
+        let mut learnt = vec![0];
+        let mut pending = 0;
+        let mut implied = None;
+        let mut idx = self.trail.len();
+        let mut clause = conflict;
+
+        loop {
+            // The first literal of a reason clause is the one it implied
+            let skip = if implied.is_some() { 1 } else { 0 };
+            for k in skip..self.clauses[clause].len() {
+                let lit = self.clauses[clause][k];
+                let v = var(lit);
+                if !self.seen[v] && self.level[v] > 0 {
+                    self.seen[v] = true;
+                    self.bump(v);
+                    if self.level[v] == self.decision_level() {
+                        pending += 1;
+                    } else {
+                        learnt.push(lit);
+                    }
+                }
+            }
+
+            loop {
+                idx -= 1;
+                if self.seen[var(self.trail[idx])] {
+                    break;
+                }
+            }
+            let lit = self.trail[idx];
+            self.seen[var(lit)] = false;
+            pending -= 1;
+            if pending == 0 {
+                learnt[0] = lit ^ 1;
+                break;
+            }
+            implied = Some(lit);
+            clause = self.reason[var(lit)].expect("Implied literal without a reason");
+        }
+
+        for lit in &learnt[1..] {
+            self.seen[var(*lit)] = false;
+        }
+
+        // Watch the literal from the highest level after the
+        // asserting one so the clause is correct after backjumping
+        let mut backjump = 0;
+        if learnt.len() > 1 {
+            let max = (1..learnt.len())
+                .max_by_key(|k| self.level[var(learnt[*k])])
+                .unwrap();
+            learnt.swap(1, max);
+            backjump = self.level[var(learnt[1])];
+        }
+        (learnt, backjump)
+    }
+
+    fn backtrack(&mut self, level: usize) {
+        if self.decision_level() <= level {
+            return;
+        }
+        let start = self.trail_lim[level];
+        for lit in self.trail.drain(start..) {
+            let v = var(lit);
+            self.assigns[v] = None;
+            self.reason[v] = None;
+        }
+        self.trail_lim.truncate(level);
+        self.queue_head = self.queue_head.min(self.trail.len());
+    }
+
+    /// Returns the unassigned variable with the highest activity
+    fn pick_branch(&self) -> Option<usize> {
+        (0..self.assigns.len())
+            .filter(|v| self.assigns[*v].is_none())
+            .fold(None, |best: Option<usize>, v| match best {
+                Some(b) if self.activity[b] >= self.activity[v] => Some(b),
+                _ => Some(v),
+            })
+    }
+
+    fn solve(&mut self, budget: &SearchBudget) -> Result<Option<Vec<bool>>, Error> {
+        let mut conflicts = 0;
+        let mut restarts = 1;
+        let mut restart_limit = luby(restarts) * RESTART_UNIT;
+
+        loop {
+            if let Some(conflict) = self.propagate() {
+                if self.decision_level() == 0 {
+                    return Ok(None);
+                }
+                budget.backtrack()?;
+                let (learnt, backjump) = self.analyze(conflict);
+                self.backtrack(backjump);
+                if learnt.len() == 1 {
+                    self.enqueue(learnt[0], None);
+                } else {
+                    let asserting = learnt[0];
+                    let idx = self.add_clause(learnt);
+                    self.enqueue(asserting, Some(idx));
+                }
+                self.var_inc /= ACTIVITY_DECAY;
+
+                conflicts += 1;
+                if conflicts >= restart_limit {
+                    conflicts = 0;
+                    restarts += 1;
+                    restart_limit = luby(restarts) * RESTART_UNIT;
+                    self.backtrack(0);
+                }
+            } else {
+                match self.pick_branch() {
+                    Some(v) => {
+                        budget.expand()?;
+                        self.trail_lim.push(self.trail.len());
+                        // Try false first so models stay small
+                        self.enqueue(v * 2 + 1, None);
+                    },
+                    None => return Ok(Some(self.assigns.iter().map(|a| a.unwrap_or(false)).collect())),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod cdcl_test {
+    use super::*;
+
+    fn satisfies(cnf: &Cnf, model: &[bool]) -> bool {
+        cnf.clauses.iter().all(|clause| {
+            clause.iter().any(|lit| model[lit.unsigned_abs() as usize - 1] == (*lit > 0))
+        })
+    }
+
+    /// Every one of `pigeons` pigeons sits in one of `holes` holes
+    /// and no two share a hole
+    fn pigeonhole(pigeons: usize, holes: usize) -> Cnf {
+        let mut cnf = Cnf::new();
+        let vars: Vec<Vec<Literal>> = (0..pigeons)
+            .map(|_| (0..holes).map(|_| cnf.new_var()).collect())
+            .collect();
+        for p in &vars {
+            cnf.add_clause(p.clone());
+        }
+        for h in 0..holes {
+            for (p1, first) in vars.iter().enumerate() {
+                for second in &vars[p1 + 1..] {
+                    cnf.add_clause(vec![-first[h], -second[h]]);
+                }
+            }
+        }
+        cnf
+    }
+
+    #[test]
+    fn luby_sequence() {
+        let seq: Vec<usize> = (1..=9).map(luby).collect();
+        assert_eq!(seq, vec![1, 1, 2, 1, 1, 2, 4, 1, 1]);
+    }
+
+    #[test]
+    fn solves_satisfiable_formulas() {
+        let cnf = pigeonhole(4, 4);
+        let model = cnf.solve().unwrap();
+        assert!(satisfies(&cnf, &model));
+
+        let mut cnf = Cnf::new();
+        let a = cnf.new_var();
+        let b = cnf.new_var();
+        let c = cnf.new_var();
+        cnf.add_clause(vec![a, b]);
+        cnf.add_clause(vec![-a, c]);
+        cnf.add_clause(vec![-b, c]);
+        cnf.add_clause(vec![-c, -a]);
+        let model = cnf.solve().unwrap();
+        assert!(satisfies(&cnf, &model));
+        assert_eq!(model, vec![false, true, true]);
+    }
+
+    #[test]
+    fn proves_unsatisfiable_formulas() {
+        assert_eq!(pigeonhole(5, 4).solve(), None);
+
+        let mut cnf = Cnf::new();
+        let a = cnf.new_var();
+        cnf.add_clause(vec![a]);
+        cnf.add_clause(vec![-a]);
+        assert_eq!(cnf.solve(), None);
+
+        cnf.clauses = vec![vec![]];
+        assert_eq!(cnf.solve(), None);
+    }
+
+    #[test]
+    fn stops_within_budget() {
+        use crate::limits::{Limit, SearchLimits};
+        let budget = SearchBudget::new(SearchLimits::new().max_backtracks(3).clone());
+        assert_eq!(pigeonhole(6, 5).solve_within(&budget), Err(Error::LimitReached(Limit::Backtracks)));
+    }
+
+    #[test]
+    fn writes_dimacs() {
+        let mut cnf = Cnf::new();
+        let a = cnf.new_var();
+        let b = cnf.new_var();
+        cnf.add_clause(vec![a, -b]);
+        cnf.add_clause(vec![b]);
+        assert_eq!(cnf.to_dimacs(), "p cnf 2 2\n1 -2 0\n2 0\n");
+    }
+}
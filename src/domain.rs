@@ -0,0 +1,461 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+use crate::proposition::Proposition;
+use crate::action::{Action, ActionType};
+use crate::bitset::IndexSet;
+use crate::mutex::StaticMutexes;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+
+/// Stable id of a proposition within a `Domain`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PropositionIndex(pub usize);
+
+/// Stable id of an action within a `Domain`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ActionIndex(pub usize);
+
+/// A planning domain that owns its propositions and actions. Each
+/// proposition and action is assigned a stable index so the
+/// plangraph and solvers can refer to them without borrowing.
+/// Indices follow the `Ord` of propositions and actions which keeps
+/// search deterministic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Domain<ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    propositions: Vec<Proposition<PropositionId>>,
+    proposition_ids: HashMap<Proposition<PropositionId>, PropositionIndex>,
+    negations: Vec<Option<PropositionIndex>>,
+    actions: Vec<Action<ActionId, PropositionId>>,
+    action_ids: HashMap<ActionType<ActionId, PropositionId>, ActionIndex>,
+    reqs: Vec<BTreeSet<PropositionIndex>>,
+    effects: Vec<BTreeSet<PropositionIndex>>,
+    initial_props: BTreeSet<PropositionIndex>,
+    goals: BTreeSet<PropositionIndex>,
+    // Bitsets of the above and their inverses for building layers
+    // and mutexes
+    req_sets: Vec<IndexSet<PropositionIndex>>,
+    effect_sets: Vec<IndexSet<PropositionIndex>>,
+    negated_reqs: Vec<IndexSet<PropositionIndex>>,
+    negated_effects: Vec<IndexSet<PropositionIndex>>,
+    adders: Vec<IndexSet<ActionIndex>>,
+    consumers: Vec<IndexSet<ActionIndex>>,
+    static_mutexes: StaticMutexes,
+}
+
+impl<ActionId, PropositionId> Domain<ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    /// Returns a new domain from the given actions as is. Note: you
+    /// probably want `GraphPlan::create_domain` which also adds the
+    /// maintenance actions.
+    pub fn new<I, G, A>(initial_props: I, goals: G, actions: A) -> Self
+    where
+        I: IntoIterator<Item = Proposition<PropositionId>>,
+        G: IntoIterator<Item = Proposition<PropositionId>>,
+        A: IntoIterator<Item = Action<ActionId, PropositionId>>,
+    {
+        let initial_props: BTreeSet<_> = initial_props.into_iter().collect();
+        let goals: BTreeSet<_> = goals.into_iter().collect();
+        let actions: BTreeSet<_> = actions.into_iter().collect();
+
+        // Actions with conditional effects are compiled into one
+        // variant for each way they can play out. Variants keep the
+        // original action so solutions still refer to it.
+        let variants: Vec<(&Action<ActionId, PropositionId>, BTreeSet<_>, BTreeSet<_>)> = actions.iter()
+            .flat_map(|a| a.variants().into_iter().map(move |(reqs, effects)| (a, reqs, effects)))
+            .collect();
+
+        let propositions: Vec<Proposition<PropositionId>> = initial_props.iter()
+            .chain(goals.iter())
+            .chain(variants.iter().flat_map(|(_, reqs, effects)| reqs.iter().chain(effects.iter())))
+            .cloned()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let proposition_ids: HashMap<_, _> = propositions.iter()
+            .enumerate()
+            .map(|(idx, p)| (p.clone(), PropositionIndex(idx)))
+            .collect();
+        let negations: Vec<Option<PropositionIndex>> = propositions.iter()
+            .map(|p| proposition_ids.get(&p.negate()).copied())
+            .collect();
+
+        let index = |props: &BTreeSet<Proposition<PropositionId>>| props.iter()
+            .map(|p| proposition_ids[p])
+            .collect::<BTreeSet<_>>();
+        let reqs: Vec<BTreeSet<PropositionIndex>> = variants.iter().map(|(_, r, _)| index(r)).collect();
+        let effects: Vec<BTreeSet<PropositionIndex>> = variants.iter().map(|(_, _, e)| index(e)).collect();
+        let actions: Vec<Action<ActionId, PropositionId>> = variants.into_iter()
+            .map(|(a, _, _)| a.clone())
+            .collect();
+        let mut action_ids = HashMap::new();
+        for (idx, a) in actions.iter().enumerate() {
+            action_ids.entry(a.id.clone()).or_insert(ActionIndex(idx));
+        }
+
+        let negated = |props: &BTreeSet<PropositionIndex>| props.iter()
+            .filter_map(|p| negations[p.0])
+            .collect::<IndexSet<_>>();
+        let mut adders = vec![IndexSet::new(); propositions.len()];
+        let mut consumers = vec![IndexSet::new(); propositions.len()];
+        for idx in 0..actions.len() {
+            for p in &effects[idx] {
+                adders[p.0].insert(ActionIndex(idx));
+            }
+            for p in &reqs[idx] {
+                consumers[p.0].insert(ActionIndex(idx));
+            }
+        }
+
+        let mut domain = Domain {
+            initial_props: initial_props.iter().map(|p| proposition_ids[p]).collect(),
+            goals: goals.iter().map(|p| proposition_ids[p]).collect(),
+            req_sets: reqs.iter().map(|r| r.iter().collect()).collect(),
+            effect_sets: effects.iter().map(|e| e.iter().collect()).collect(),
+            negated_reqs: reqs.iter().map(negated).collect(),
+            negated_effects: effects.iter().map(negated).collect(),
+            adders,
+            consumers,
+            propositions,
+            proposition_ids,
+            negations,
+            actions,
+            action_ids,
+            reqs,
+            effects,
+            static_mutexes: StaticMutexes::default(),
+        };
+        domain.static_mutexes = StaticMutexes::new(&domain);
+        domain
+    }
+
+    pub fn propositions(&self) -> &[Proposition<PropositionId>] {
+        &self.propositions
+    }
+
+    pub fn proposition(&self, idx: PropositionIndex) -> &Proposition<PropositionId> {
+        &self.propositions[idx.0]
+    }
+
+    pub fn proposition_index(&self, prop: &Proposition<PropositionId>) -> Option<PropositionIndex> {
+        self.proposition_ids.get(prop).copied()
+    }
+
+    /// Returns the index of the negation of a proposition if it is
+    /// part of the domain
+    pub fn negation(&self, idx: PropositionIndex) -> Option<PropositionIndex> {
+        self.negations[idx.0]
+    }
+
+    /// Returns the action at each index. An action with conditional
+    /// effects appears once for each of its variants.
+    pub fn actions(&self) -> &[Action<ActionId, PropositionId>] {
+        &self.actions
+    }
+
+    pub fn action(&self, idx: ActionIndex) -> &Action<ActionId, PropositionId> {
+        &self.actions[idx.0]
+    }
+
+    /// Returns the index of the action or of its first variant if it
+    /// has conditional effects
+    pub fn action_index(&self, action: &Action<ActionId, PropositionId>) -> Option<ActionIndex> {
+        self.action_ids.get(&action.id).copied()
+    }
+
+    /// Returns the index of every action in the domain
+    pub fn action_indices(&self) -> impl Iterator<Item = ActionIndex> {
+        (0..self.actions.len()).map(ActionIndex)
+    }
+
+    /// Returns the indices of the other variants of the same action
+    pub(crate) fn sibling_variants(&self, idx: ActionIndex) -> impl Iterator<Item = ActionIndex> + '_ {
+        let id = &self.actions[idx.0].id;
+        let before = self.actions[..idx.0].iter().rev().take_while(move |a| a.id == *id).count();
+        let after = self.actions[idx.0 + 1..].iter().take_while(move |a| a.id == *id).count();
+        (idx.0 - before..=idx.0 + after)
+            .filter(move |i| *i != idx.0)
+            .map(ActionIndex)
+    }
+
+    pub fn reqs(&self, idx: ActionIndex) -> &BTreeSet<PropositionIndex> {
+        &self.reqs[idx.0]
+    }
+
+    pub fn effects(&self, idx: ActionIndex) -> &BTreeSet<PropositionIndex> {
+        &self.effects[idx.0]
+    }
+
+    pub(crate) fn req_set(&self, idx: ActionIndex) -> &IndexSet<PropositionIndex> {
+        &self.req_sets[idx.0]
+    }
+
+    pub(crate) fn effect_set(&self, idx: ActionIndex) -> &IndexSet<PropositionIndex> {
+        &self.effect_sets[idx.0]
+    }
+
+    /// Returns the negations of an action's preconditions that are
+    /// part of the domain
+    pub(crate) fn negated_reqs(&self, idx: ActionIndex) -> &IndexSet<PropositionIndex> {
+        &self.negated_reqs[idx.0]
+    }
+
+    /// Returns the negations of an action's effects that are part of
+    /// the domain
+    pub(crate) fn negated_effects(&self, idx: ActionIndex) -> &IndexSet<PropositionIndex> {
+        &self.negated_effects[idx.0]
+    }
+
+    /// Returns the actions with the proposition as an effect
+    pub(crate) fn adders(&self, idx: PropositionIndex) -> &IndexSet<ActionIndex> {
+        &self.adders[idx.0]
+    }
+
+    /// Returns the actions with the proposition as a precondition
+    pub(crate) fn consumers(&self, idx: PropositionIndex) -> &IndexSet<ActionIndex> {
+        &self.consumers[idx.0]
+    }
+
+    /// Mutexes that hold in every layer of the plangraph
+    pub(crate) fn static_mutexes(&self) -> &StaticMutexes {
+        &self.static_mutexes
+    }
+
+    /// Returns the bitsets that layers and their mutexes are built
+    /// from
+    pub(crate) fn sets(&self) -> DomainSets<'_> {
+        DomainSets {
+            req_sets: &self.req_sets,
+            adders: &self.adders,
+            consumers: &self.consumers,
+            static_mutexes: &self.static_mutexes,
+        }
+    }
+
+    /// Returns true if the propositions can never both be true, e.g.
+    /// a proposition and its negation or an object in two places
+    pub fn props_always_mutex(&self, p: PropositionIndex, q: PropositionIndex) -> bool {
+        self.static_mutexes.props.is_mutex(p, q)
+    }
+
+    /// Returns true if the actions can never be taken in the same
+    /// step
+    pub fn actions_always_mutex(&self, a: ActionIndex, b: ActionIndex) -> bool {
+        self.static_mutexes.actions.is_mutex(a, b)
+    }
+
+    pub fn is_maintenance(&self, idx: ActionIndex) -> bool {
+        matches!(self.actions[idx.0].id, ActionType::Maintenance(_))
+    }
+
+    pub fn initial_props(&self) -> &BTreeSet<PropositionIndex> {
+        &self.initial_props
+    }
+
+    pub fn goals(&self) -> &BTreeSet<PropositionIndex> {
+        &self.goals
+    }
+}
+
+/// The bitsets of a `Domain` by index. Unlike the domain they don't
+/// depend on the id types so they can be shared between threads.
+#[derive(Clone, Copy)]
+pub(crate) struct DomainSets<'a> {
+    req_sets: &'a [IndexSet<PropositionIndex>],
+    adders: &'a [IndexSet<ActionIndex>],
+    consumers: &'a [IndexSet<ActionIndex>],
+    pub static_mutexes: &'a StaticMutexes,
+}
+
+impl<'a> DomainSets<'a> {
+    pub fn req_set(&self, idx: ActionIndex) -> &'a IndexSet<PropositionIndex> {
+        &self.req_sets[idx.0]
+    }
+
+    pub fn adders(&self, idx: PropositionIndex) -> &'a IndexSet<ActionIndex> {
+        &self.adders[idx.0]
+    }
+
+    pub fn consumers(&self, idx: PropositionIndex) -> &'a IndexSet<ActionIndex> {
+        &self.consumers[idx.0]
+    }
+}
+
+/// Options for `GraphPlan::create_domain_with` that complete the
+/// initial state. Propositions are only false when their negation is
+/// true so without these an action that requires ¬p can't be applied
+/// unless ¬p is listed in the initial state.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DomainOptions {
+    /// Assume every proposition used by an action or goal that isn't
+    /// initially true is false and add its negation to the initial
+    /// state
+    pub closed_world: bool,
+    /// Like `closed_world` but only for propositions whose negation
+    /// is a precondition or goal. This is how PDDL's
+    /// `:negative-preconditions` is compiled to STRIPS: ¬p stands for
+    /// a new proposition that is added whenever p is deleted and
+    /// deleted whenever p is added, and that is only needed where
+    /// something requires it.
+    pub compile_negative_preconditions: bool,
+}
+
+impl DomainOptions {
+    pub fn new() -> Self {
+        DomainOptions::default()
+    }
+
+    pub fn closed_world(&mut self, closed_world: bool) -> &mut Self {
+        self.closed_world = closed_world;
+        self
+    }
+
+    pub fn compile_negative_preconditions(&mut self, compile: bool) -> &mut Self {
+        self.compile_negative_preconditions = compile;
+        self
+    }
+
+    /// Returns the negations to add to the initial state. Atoms in
+    /// the conditions of conditional effects are completed whatever
+    /// the options since the variant where an effect doesn't fire
+    /// requires a negated condition.
+    pub(crate) fn assumed_false<ActionId, PropositionId>(&self,
+                                                         initial_props: &HashSet<&Proposition<PropositionId>>,
+                                                         goals: &HashSet<&Proposition<PropositionId>>,
+                                                         actions: &HashSet<&Action<ActionId, PropositionId>>)
+                                                         -> BTreeSet<Proposition<PropositionId>>
+    where
+        ActionId: Debug + Hash + Ord + Clone,
+        PropositionId: Debug + Display + Hash + Ord + Clone,
+    {
+        let reqs = actions.iter().flat_map(|a| a.reqs.iter());
+        let mut used: Vec<&Proposition<PropositionId>> = if self.closed_world {
+            let effects = actions.iter().flat_map(|a| {
+                a.effects.iter().chain(a.conditional_effects.iter().flat_map(|ce| ce.effects.iter()))
+            });
+            goals.iter().copied().chain(reqs).chain(effects).collect()
+        } else if self.compile_negative_preconditions {
+            goals.iter().copied().chain(reqs).filter(|p| p.negation).collect()
+        } else {
+            Vec::new()
+        };
+        // Conditional effects that don't fire require the negation
+        // of one of their conditions
+        used.extend(actions.iter().flat_map(|a| a.conditional_effects.iter().flat_map(|ce| ce.conditions.iter())));
+
+        used.into_iter()
+            .map(|p| Proposition::new(p.id.clone(), false))
+            .filter(|p| !initial_props.contains(p))
+            .map(|p| p.negate())
+            .filter(|not_p| !initial_props.contains(not_p))
+            .collect()
+    }
+}
+
+/// Serialized form of a `Domain`. The indexes are rebuilt on load
+/// and since indices follow the ordering of propositions and actions
+/// they come out the same.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "Domain")]
+struct DomainData<ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    initial_props: Vec<Proposition<PropositionId>>,
+    goals: Vec<Proposition<PropositionId>>,
+    actions: Vec<Action<ActionId, PropositionId>>,
+}
+
+#[cfg(feature = "serde")]
+impl<ActionId, PropositionId> Serialize for Domain<ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone + Serialize,
+    PropositionId: Debug + Display + Hash + Ord + Clone + Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let props = |indices: &BTreeSet<PropositionIndex>| indices.iter()
+            .map(|p| self.proposition(*p).clone())
+            .collect();
+        DomainData {
+            initial_props: props(&self.initial_props),
+            goals: props(&self.goals),
+            // Variants are expanded again on load
+            actions: {
+                let mut actions = self.actions.clone();
+                actions.dedup();
+                actions
+            },
+        }.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, ActionId, PropositionId> Deserialize<'de> for Domain<ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone + Deserialize<'de>,
+    PropositionId: Debug + Display + Hash + Ord + Clone + Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = DomainData::deserialize(deserializer)?;
+        Ok(Domain::new(data.initial_props, data.goals, data.actions))
+    }
+}
+
+#[cfg(test)]
+mod domain_test {
+    use super::*;
+
+    #[test]
+    fn indexes_propositions_and_actions() {
+        let p1 = Proposition::from("tired");
+        let not_p1 = p1.negate();
+        let p2 = Proposition::from("caffeinated");
+        let a1 = Action::new("drink coffee", fragset!{[&p1]}, fragset!{[&not_p1, &p2]});
+
+        let domain: Domain<&str, &str> = Domain::new(
+            vec![p1.clone()],
+            vec![p2.clone()],
+            vec![a1.clone()],
+        );
+
+        assert_eq!(domain.propositions().len(), 3);
+        let idx = domain.action_index(&a1).unwrap();
+        assert_eq!(domain.action(idx), &a1);
+
+        let tired = domain.proposition_index(&p1).unwrap();
+        let not_tired = domain.proposition_index(&not_p1).unwrap();
+        let caffeinated = domain.proposition_index(&p2).unwrap();
+        assert_eq!(domain.proposition(tired), &p1);
+        assert_eq!(domain.negation(tired), Some(not_tired));
+        assert_eq!(domain.negation(caffeinated), None);
+
+        assert_eq!(domain.reqs(idx), &btreeset!{tired});
+        assert_eq!(domain.effects(idx), &btreeset!{not_tired, caffeinated});
+        assert_eq!(domain.initial_props(), &btreeset!{tired});
+        assert_eq!(domain.goals(), &btreeset!{caffeinated});
+    }
+
+    #[test]
+    fn indices_follow_ordering() {
+        let p1 = Proposition::from("b");
+        let p2 = Proposition::from("a");
+        let a1 = Action::new("z", fragset!{[&p1]}, fragset!{});
+        let a2 = Action::new("y", fragset!{[&p2]}, fragset!{});
+        let domain: Domain<&str, &str> = Domain::new(vec![], vec![], vec![a1, a2]);
+
+        assert_eq!(domain.proposition(PropositionIndex(0)), &p2);
+        assert_eq!(domain.action(ActionIndex(0)).id, ActionType::Action("y"));
+    }
+}
@@ -0,0 +1,203 @@
+use std::fmt::{Debug, Display, Write};
+use std::hash::Hash;
+use std::ops::Range;
+use crate::action::ActionType;
+use crate::domain::{ActionIndex, PropositionIndex};
+use crate::layer::Layer;
+use crate::pairset::PairSet;
+use crate::plangraph::PlanGraph;
+
+
+/// Options for `PlanGraph::to_dot_with`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DotOptions {
+    /// Draw mutex pairs as dashed red edges
+    pub mutexes: bool,
+    /// Only draw layers in this range. Layer 0 is the initial
+    /// proposition layer followed by alternating action and
+    /// proposition layers.
+    pub layers: Option<Range<usize>>,
+}
+
+impl DotOptions {
+    pub fn new() -> Self {
+        DotOptions::default()
+    }
+
+    pub fn mutexes(&mut self, mutexes: bool) -> &mut Self {
+        self.mutexes = mutexes;
+        self
+    }
+
+    pub fn layers(&mut self, layers: Range<usize>) -> &mut Self {
+        self.layers = Some(layers);
+        self
+    }
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn prop_node(layer: usize, prop: PropositionIndex) -> String {
+    format!("l{}_p{}", layer, prop.0)
+}
+
+fn action_node(layer: usize, action: ActionIndex) -> String {
+    format!("l{}_a{}", layer, action.0)
+}
+
+impl<ActionId, PropositionId> PlanGraph<ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    /// Renders the plangraph in Graphviz DOT format with every layer
+    /// and without mutexes
+    pub fn to_dot(&self) -> String {
+        self.to_dot_with(&DotOptions::default())
+    }
+
+    /// Renders the plangraph in Graphviz DOT format. Each layer is a
+    /// ranked column with precondition edges into actions and effect
+    /// edges out of them. Maintenance actions are drawn as small gray
+    /// nodes.
+    pub fn to_dot_with(&self, options: &DotOptions) -> String {
+        let domain = self.domain();
+        let range = options.layers.clone().unwrap_or(0..self.layers.len());
+        let shown = |idx: usize| range.contains(&idx) && idx < self.layers.len();
+        let prop_label = |p: PropositionIndex| {
+            let prop = domain.proposition(p);
+            escape(&format!("{}{}", if prop.negation {"¬"} else {""}, prop.id))
+        };
+
+        let mut out = String::new();
+        writeln!(out, "digraph plangraph {{").unwrap();
+        writeln!(out, "  rankdir=LR;").unwrap();
+
+        for (idx, layer) in self.layers.iter().enumerate().filter(|(idx, _)| shown(*idx)) {
+            writeln!(out, "  subgraph layer{} {{", idx).unwrap();
+            writeln!(out, "    rank=same;").unwrap();
+            match layer {
+                Layer::PropositionLayer(props) => {
+                    for p in props {
+                        writeln!(out, "    {} [label=\"{}\", shape=ellipse];",
+                                 prop_node(idx, p), prop_label(p)).unwrap();
+                    }
+                },
+                Layer::ActionLayer(actions) => {
+                    for a in actions {
+                        match &domain.action(a).id {
+                            ActionType::Action(id) => writeln!(
+                                out, "    {} [label=\"{}\", shape=box];",
+                                action_node(idx, a), escape(&format!("{:?}", id))
+                            ),
+                            ActionType::Maintenance(_) => writeln!(
+                                out, "    {} [label=\"noop\", shape=box, style=dashed, color=gray, fontcolor=gray, fontsize=8];",
+                                action_node(idx, a)
+                            ),
+                        }.unwrap();
+                    }
+                },
+            }
+            writeln!(out, "  }}").unwrap();
+        }
+
+        for (idx, layer) in self.layers.iter().enumerate().filter(|(idx, _)| shown(*idx)) {
+            if let Layer::ActionLayer(actions) = layer {
+                for a in actions {
+                    let style = if domain.is_maintenance(a) { " [color=gray]" } else { "" };
+                    if shown(idx - 1) {
+                        for p in domain.reqs(a) {
+                            writeln!(out, "  {} -> {}{};",
+                                     prop_node(idx - 1, *p), action_node(idx, a), style).unwrap();
+                        }
+                    }
+                    if shown(idx + 1) {
+                        for p in domain.effects(a) {
+                            writeln!(out, "  {} -> {}{};",
+                                     action_node(idx, a), prop_node(idx + 1, *p), style).unwrap();
+                        }
+                    }
+                }
+            }
+        }
+
+        if options.mutexes {
+            let mutex_style = "[style=dashed, color=red, dir=none, constraint=false]";
+            // Sort the pairs so the output is stable
+            let mut mutex_props: Vec<_> = self.mutex_props.iter()
+                .filter(|(idx, _)| shown(**idx))
+                .flat_map(|(idx, pairs)| pairs.iter().map(move |PairSet(p, q)| (*idx, p, q)))
+                .collect();
+            mutex_props.sort();
+            for (idx, p, q) in mutex_props {
+                writeln!(out, "  {} -> {} {};", prop_node(idx, p), prop_node(idx, q), mutex_style).unwrap();
+            }
+
+            let mut mutex_actions: Vec<_> = self.mutex_actions.iter()
+                .filter(|(idx, _)| shown(**idx))
+                .flat_map(|(idx, pairs)| pairs.iter().map(move |PairSet(a, b)| (*idx, a, b)))
+                .collect();
+            mutex_actions.sort();
+            for (idx, a, b) in mutex_actions {
+                writeln!(out, "  {} -> {} {};", action_node(idx, a), action_node(idx, b), mutex_style).unwrap();
+            }
+        }
+
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
+
+#[cfg(test)]
+mod dot_test {
+    use super::*;
+    use crate::proposition::Proposition;
+    use crate::action::Action;
+    use crate::GraphPlan;
+
+    fn plangraph() -> PlanGraph<&'static str, &'static str> {
+        let p1 = Proposition::from("tired");
+        let not_p1 = p1.negate();
+        let a1 = Action::new("drink coffee", fragset!{[&p1]}, fragset!{[&not_p1]});
+        let domain = GraphPlan::create_domain(fragset!{[&p1]}, fragset!{[&not_p1]}, fragset!{[&a1]});
+        let mut pg = PlanGraph::from_domain(domain);
+        pg.extend().unwrap();
+        pg
+    }
+
+    #[test]
+    fn renders_layers_and_edges() {
+        let pg = plangraph();
+        let domain = pg.domain();
+        let tired = domain.proposition_index(&Proposition::from("tired")).unwrap();
+        let not_tired = domain.negation(tired).unwrap();
+        let coffee = domain.action_indices().find(|a| !domain.is_maintenance(*a)).unwrap();
+
+        let dot = pg.to_dot();
+        assert!(dot.starts_with("digraph plangraph {"));
+        assert!(dot.contains(&format!("l0_p{} [label=\"tired\", shape=ellipse];", tired.0)));
+        assert!(dot.contains(&format!("l2_p{} [label=\"¬tired\", shape=ellipse];", not_tired.0)));
+        assert!(dot.contains(&format!("l1_a{} [label=\"\\\"drink coffee\\\"\", shape=box];", coffee.0)));
+        assert!(dot.contains(&format!("l0_p{} -> l1_a{};", tired.0, coffee.0)));
+        assert!(dot.contains(&format!("l1_a{} -> l2_p{};", coffee.0, not_tired.0)));
+        assert!(dot.contains("label=\"noop\""));
+        assert!(!dot.contains("color=red"));
+    }
+
+    #[test]
+    fn escapes_labels() {
+        assert_eq!(escape("say \"hi\\\""), "say \\\"hi\\\\\\\"");
+    }
+
+    #[test]
+    fn renders_mutexes_within_layer_range() {
+        let pg = plangraph();
+        let dot = pg.to_dot_with(DotOptions::new().mutexes(true).layers(1..2));
+        assert!(!dot.contains("l0_p"));
+        assert!(!dot.contains("l2_p"));
+        // The noop for "tired" interferes with drinking coffee
+        assert_eq!(dot.matches("color=red").count(), 1);
+    }
+}
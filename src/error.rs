@@ -0,0 +1,57 @@
+use std::error;
+use std::fmt;
+use crate::limits::Limit;
+
+
+/// Errors returned by the public API instead of panicking
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Error {
+    /// `Action::get_action` was called on a maintenance action
+    MaintenanceAction,
+    /// The plangraph has no layers. Use `PlanGraph::new` rather than
+    /// building it as a struct.
+    Uninitialized,
+    /// The plangraph has not been extended so there are no actions
+    /// to search
+    NotExtended,
+    /// The layer at this index is missing
+    MissingLayer(usize),
+    /// The layer at this index is not of the expected kind
+    UnexpectedLayer(usize),
+    /// The graph leveled off and the unsolvable goal sets at that
+    /// layer stopped changing so no plan exists
+    LeveledOff,
+    /// The graph leveled off and some goals never appear in it
+    GoalsUnreachable,
+    /// The graph leveled off and some goals are always mutex
+    GoalsMutex,
+    /// Every state reachable from the initial state was searched
+    /// without meeting the goals so no plan exists
+    StateSpaceExhausted,
+    /// The search gave up after a bound on the number of extensions
+    /// because the solver does not record unsolvable goal sets
+    SearchExhausted,
+    /// The search stopped at one of its `SearchLimits` before it
+    /// could find a plan or prove there is none
+    LimitReached(Limit),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::MaintenanceAction => write!(f, "maintenance actions have no action id"),
+            Error::Uninitialized => write!(f, "plangraph has no layers"),
+            Error::NotExtended => write!(f, "plangraph has no action layers to search"),
+            Error::MissingLayer(idx) => write!(f, "layer {} does not exist", idx),
+            Error::UnexpectedLayer(idx) => write!(f, "layer {} is not the expected kind of layer", idx),
+            Error::LeveledOff => write!(f, "plangraph leveled off and no plan exists"),
+            Error::GoalsUnreachable => write!(f, "goals are unreachable"),
+            Error::GoalsMutex => write!(f, "goals are mutually exclusive"),
+            Error::StateSpaceExhausted => write!(f, "every reachable state was searched and no plan exists"),
+            Error::SearchExhausted => write!(f, "search gave up without finding a plan"),
+            Error::LimitReached(limit) => write!(f, "search stopped because {}", limit),
+        }
+    }
+}
+
+impl error::Error for Error {}
@@ -0,0 +1,85 @@
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+use crate::proposition::Proposition;
+use crate::action::Action;
+use crate::domain::Domain;
+use crate::GraphPlan;
+
+
+/// Coffee and walking the dog, the dog can only be walked once
+/// coffee made you no longer tired
+pub fn morning_actions<Id>() -> Vec<Action<Id, Id>>
+where
+    Id: From<&'static str> + Debug + Display + Hash + Ord + Clone,
+{
+    let p1 = Proposition::new(Id::from("tired"), false);
+    let not_p1 = p1.negate();
+    let p2 = Proposition::new(Id::from("dog needs to pee"), false);
+    let not_p2 = p2.negate();
+    let a1 = Action::new(Id::from("coffee"), fragset!{[&p1]}, fragset!{[&not_p1]});
+    let a2 = Action::new(Id::from("walk dog"), fragset!{[&p2, &not_p1]}, fragset!{[&not_p2]});
+    vec![a1, a2]
+}
+
+/// Starts tired with the dog needing to pee and ends with neither,
+/// solved by `morning_actions` in two steps
+pub fn morning() -> Domain<&'static str, &'static str> {
+    morning_as()
+}
+
+/// Same as `morning` with ids of another type such as `String`
+pub fn morning_as<Id>() -> Domain<Id, Id>
+where
+    Id: From<&'static str> + Debug + Display + Hash + Ord + Clone,
+{
+    let p1 = Proposition::new(Id::from("tired"), false);
+    let not_p1 = p1.negate();
+    let p2 = Proposition::new(Id::from("dog needs to pee"), false);
+    let not_p2 = p2.negate();
+    let actions = morning_actions();
+    GraphPlan::create_domain(
+        fragset!{[&p1, &p2]},
+        fragset!{[&not_p1, &not_p2]},
+        actions.iter().collect(),
+    )
+}
+
+/// The kitchen problem from `examples/pddl`, moving both cups to the
+/// plate takes nine steps
+pub fn kitchen() -> Domain<String, String> {
+    let domain = crate::pddl::parse_domain(
+        "kitchen-domain.pddl",
+        include_str!("../examples/pddl/kitchen-domain.pddl"),
+    ).unwrap();
+    let problem = crate::pddl::parse_problem(
+        "kitchen-problem.pddl",
+        include_str!("../examples/pddl/kitchen-problem.pddl"),
+        &domain,
+    ).unwrap();
+    crate::pddl::ground(&domain, &problem).domain()
+}
+
+/// Any two of a, b and c can be made by spending the token but not
+/// all three, no pair of the goals is ever mutex
+pub fn token_actions() -> Vec<Action<&'static str, &'static str>> {
+    let (a, b, c) = (Proposition::from("a"), Proposition::from("b"), Proposition::from("c"));
+    let token = Proposition::from("token");
+    let spent = token.negate();
+    let make_ab = Action::new("make ab", fragset!{[&token]}, fragset!{[&a, &b, &spent]});
+    let make_bc = Action::new("make bc", fragset!{[&token]}, fragset!{[&b, &c, &spent]});
+    let make_ac = Action::new("make ac", fragset!{[&token]}, fragset!{[&a, &c, &spent]});
+    vec![make_ab, make_bc, make_ac]
+}
+
+/// Starts with the token and the goals a, b and c, unsolvable with
+/// `token_actions`
+pub fn token() -> Domain<&'static str, &'static str> {
+    let (a, b, c) = (Proposition::from("a"), Proposition::from("b"), Proposition::from("c"));
+    let token = Proposition::from("token");
+    let actions = token_actions();
+    GraphPlan::create_domain(
+        fragset!{[&token]},
+        fragset!{[&a, &b, &c]},
+        actions.iter().collect(),
+    )
+}
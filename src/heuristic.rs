@@ -0,0 +1,607 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap, HashMap, HashSet};
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+use log::debug;
+use crate::domain::{ActionIndex, Domain, PropositionIndex};
+use crate::error::Error;
+use crate::limits::SearchBudget;
+use crate::observer::SearchObserver;
+use crate::plangraph::{PlanGraph, Solution};
+use crate::solver::GraphPlanSolver;
+
+
+type State = BTreeSet<PropositionIndex>;
+
+/// Estimates of the number of actions needed to reach the goals from
+/// a state. `None` means the goals can't be reached from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Heuristic {
+    /// h_max: the first layer of the relaxed plangraph, which ignores
+    /// deletes and mutexes, containing every goal. Admissible.
+    LevelCost,
+    /// h_add: the sum of the relaxed costs of each goal
+    Additive,
+    /// The first layer of the plangraph from the state where the
+    /// goals are present and no pair of them is in `mutex_props`.
+    /// Admissible but slow since every state builds a plangraph.
+    SetLevel,
+    /// FF: the number of actions in a plan extracted from the relaxed
+    /// plangraph
+    RelaxedPlan,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SearchStrategy {
+    /// Expands the state with the lowest steps plus heuristic. Plans
+    /// are optimal with an admissible heuristic.
+    AStar,
+    /// Expands the state with the lowest heuristic
+    GreedyBestFirst,
+}
+
+/// Searches forward from the initial state through world states
+/// scored by a heuristic. Plans are linear with one action per step.
+/// By default it runs greedy best-first search with the relaxed plan
+/// heuristic.
+///
+/// As a `GraphPlanSolver` its plans have at most one step per layer
+/// of the plangraph, and once every reachable state was searched it
+/// returns `Error::StateSpaceExhausted` rather than waiting for the
+/// plangraph to level off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HeuristicSearchSolver {
+    pub heuristic: Heuristic,
+    pub strategy: SearchStrategy,
+}
+
+impl Default for HeuristicSearchSolver {
+    fn default() -> Self {
+        HeuristicSearchSolver::new(Heuristic::RelaxedPlan, SearchStrategy::GreedyBestFirst)
+    }
+}
+
+/// The first layer of the relaxed plangraph at which each proposition
+/// and action appears
+struct RelaxedPlanGraph {
+    prop_levels: Vec<Option<usize>>,
+    action_levels: Vec<Option<usize>>,
+}
+
+impl RelaxedPlanGraph {
+    /// Extends the relaxed plangraph from the state until the goals
+    /// are present or no new propositions are added
+    fn new<ActionId, PropositionId>(domain: &Domain<ActionId, PropositionId>,
+                                    actions: &[ActionIndex],
+                                    state: &State) -> Self
+    where
+        ActionId: Debug + Hash + Ord + Clone,
+        PropositionId: Debug + Display + Hash + Ord + Clone,
+    {
+        let mut prop_levels = vec![None; domain.propositions().len()];
+        let mut action_levels = vec![None; domain.actions().len()];
+        for p in state {
+            prop_levels[p.0] = Some(0);
+        }
+
+        let mut level = 0;
+        while !domain.goals().iter().all(|g| prop_levels[g.0].is_some()) {
+            let mut added: State = BTreeSet::new();
+            for a in actions {
+                if action_levels[a.0].is_none() && domain.reqs(*a).iter().all(|r| prop_levels[r.0].is_some()) {
+                    action_levels[a.0] = Some(level);
+                    added.extend(domain.effects(*a).iter().filter(|e| prop_levels[e.0].is_none()));
+                }
+            }
+            if added.is_empty() {
+                break;
+            }
+            level += 1;
+            for p in added {
+                prop_levels[p.0] = Some(level);
+            }
+        }
+
+        RelaxedPlanGraph { prop_levels, action_levels }
+    }
+
+    fn goal_levels<'g, I>(&self, goals: I) -> Option<Vec<usize>>
+    where
+        I: IntoIterator<Item=&'g PropositionIndex>,
+    {
+        goals.into_iter().map(|g| self.prop_levels[g.0]).collect()
+    }
+}
+
+fn level_cost<ActionId, PropositionId>(domain: &Domain<ActionId, PropositionId>,
+                                       actions: &[ActionIndex],
+                                       state: &State) -> Option<usize>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    RelaxedPlanGraph::new(domain, actions, state)
+        .goal_levels(domain.goals())
+        .map(|levels| levels.into_iter().max().unwrap_or(0))
+}
+
+fn additive_cost<ActionId, PropositionId>(domain: &Domain<ActionId, PropositionId>,
+                                          actions: &[ActionIndex],
+                                          state: &State) -> Option<usize>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    let mut costs: Vec<Option<usize>> = vec![None; domain.propositions().len()];
+    for p in state {
+        costs[p.0] = Some(0);
+    }
+
+    // Relax the costs until they stop improving
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for a in actions {
+            let reqs_cost: Option<usize> = domain.reqs(*a).iter().map(|r| costs[r.0]).sum();
+            if let Some(cost) = reqs_cost.map(|c| c + 1) {
+                for e in domain.effects(*a) {
+                    let improved = match costs[e.0] {
+                        Some(c) => cost < c,
+                        None => true,
+                    };
+                    if improved {
+                        costs[e.0] = Some(cost);
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+
+    domain.goals().iter().map(|g| costs[g.0]).sum()
+}
+
+fn relaxed_plan_length<ActionId, PropositionId>(domain: &Domain<ActionId, PropositionId>,
+                                                actions: &[ActionIndex],
+                                                state: &State) -> Option<usize>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    let graph = RelaxedPlanGraph::new(domain, actions, state);
+    let levels = graph.goal_levels(domain.goals())?;
+    let depth = levels.iter().copied().max().unwrap_or(0);
+
+    let mut goals: Vec<BTreeSet<PropositionIndex>> = vec![BTreeSet::new(); depth + 1];
+    for (g, level) in domain.goals().iter().zip(levels) {
+        goals[level].insert(*g);
+    }
+
+    // Work back from the last layer choosing the achiever of each goal
+    // whose requirements appear earliest. Effects of chosen actions
+    // are marked true so they aren't achieved again.
+    let mut plan = BTreeSet::new();
+    let mut marked: HashSet<(usize, PropositionIndex)> = HashSet::new();
+    for level in (1..=depth).rev() {
+        for g in std::mem::take(&mut goals[level]) {
+            if marked.contains(&(level, g)) {
+                continue;
+            }
+            let achiever = actions.iter()
+                .filter(|a| graph.action_levels[a.0] == Some(level - 1))
+                .filter(|a| domain.effects(**a).contains(&g))
+                .min_by_key(|a| {
+                    let difficulty: usize = domain.reqs(**a).iter()
+                        .filter_map(|r| graph.prop_levels[r.0])
+                        .sum();
+                    (difficulty, **a)
+                })
+                .expect("Relaxed plangraph proposition without an achiever");
+
+            plan.insert(*achiever);
+            for r in domain.reqs(*achiever) {
+                let req_level = graph.prop_levels[r.0].unwrap_or(0);
+                if req_level > 0 && !marked.contains(&(level - 1, *r)) {
+                    goals[req_level].insert(*r);
+                }
+            }
+            for e in domain.effects(*achiever) {
+                marked.insert((level, *e));
+                marked.insert((level - 1, *e));
+            }
+        }
+    }
+
+    Some(plan.len())
+}
+
+/// Returns the set level and hands back the domain which is moved
+/// into the plangraph to avoid cloning it for every state
+fn set_level<ActionId, PropositionId>(domain: Domain<ActionId, PropositionId>,
+                                      state: &State)
+                                      -> (Option<usize>, Domain<ActionId, PropositionId>)
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    let mut plangraph = PlanGraph::from_state(domain, state.iter().collect());
+    let level = loop {
+        if plangraph.has_possible_solution().unwrap_or(false) {
+            break Some(plangraph.depth());
+        }
+        if plangraph.has_leveled_off() || plangraph.extend().is_err() {
+            break None;
+        }
+    };
+    (level, plangraph.into_domain())
+}
+
+/// Evaluates the heuristic for states of one domain
+struct Evaluator<'d, ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    heuristic: Heuristic,
+    domain: &'d Domain<ActionId, PropositionId>,
+    actions: &'d [ActionIndex],
+    /// A copy of the domain for the set level plangraphs
+    owned: Option<Domain<ActionId, PropositionId>>,
+}
+
+impl<'d, ActionId, PropositionId> Evaluator<'d, ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    fn new(heuristic: Heuristic,
+           domain: &'d Domain<ActionId, PropositionId>,
+           actions: &'d [ActionIndex]) -> Self {
+        let owned = if heuristic == Heuristic::SetLevel { Some(domain.clone()) } else { None };
+        Evaluator { heuristic, domain, actions, owned }
+    }
+
+    fn evaluate(&mut self, state: &State) -> Option<usize> {
+        match self.heuristic {
+            Heuristic::LevelCost => level_cost(self.domain, self.actions, state),
+            Heuristic::Additive => additive_cost(self.domain, self.actions, state),
+            Heuristic::RelaxedPlan => relaxed_plan_length(self.domain, self.actions, state),
+            Heuristic::SetLevel => {
+                let domain = self.owned.take().expect("Set level evaluator without a domain");
+                let (level, domain) = set_level(domain, state);
+                self.owned = Some(domain);
+                level
+            },
+        }
+    }
+}
+
+struct Node {
+    state: State,
+    steps: usize,
+    estimate: usize,
+    parent: Option<(usize, ActionIndex)>,
+}
+
+impl HeuristicSearchSolver {
+    pub fn new(heuristic: Heuristic, strategy: SearchStrategy) -> Self {
+        HeuristicSearchSolver { heuristic, strategy }
+    }
+
+    /// Returns the heuristic estimate for the initial state of the
+    /// domain or `None` if the goals are unreachable
+    pub fn estimate<ActionId, PropositionId>(&self, domain: &Domain<ActionId, PropositionId>) -> Option<usize>
+    where
+        ActionId: Debug + Hash + Ord + Clone,
+        PropositionId: Debug + Display + Hash + Ord + Clone,
+    {
+        let actions: Vec<ActionIndex> = domain.action_indices()
+            .filter(|a| !domain.is_maintenance(*a))
+            .collect();
+        Evaluator::new(self.heuristic, domain, &actions).evaluate(domain.initial_props())
+    }
+
+    /// Searches forward from the initial state of the domain. Returns
+    /// `None` if every reachable state was explored without meeting
+    /// the goals.
+    pub fn solve<ActionId, PropositionId>(&self, domain: &Domain<ActionId, PropositionId>)
+                                          -> Option<Solution<ActionId, PropositionId>>
+    where
+        ActionId: Debug + Hash + Ord + Clone,
+        PropositionId: Debug + Display + Hash + Ord + Clone,
+    {
+        self.solve_within(domain, &SearchBudget::default())
+            .expect("Unlimited search reached a limit")
+    }
+
+    /// Same as `solve` but counts expanded states against the budget
+    pub fn solve_within<ActionId, PropositionId>(&self,
+                                                 domain: &Domain<ActionId, PropositionId>,
+                                                 budget: &SearchBudget)
+                                                 -> Result<Option<Solution<ActionId, PropositionId>>, Error>
+    where
+        ActionId: Debug + Hash + Ord + Clone,
+        PropositionId: Debug + Display + Hash + Ord + Clone,
+    {
+        match self.solve_bounded(domain, budget, None) {
+            Err(Error::StateSpaceExhausted) => Ok(None),
+            result => result,
+        }
+    }
+
+    /// Searches for a plan of at most `max_steps` steps. Returns
+    /// `None` if there is none but the bound kept some states from
+    /// being searched, and `Error::StateSpaceExhausted` if every
+    /// reachable state was searched.
+    fn solve_bounded<ActionId, PropositionId>(&self,
+                                              domain: &Domain<ActionId, PropositionId>,
+                                              budget: &SearchBudget,
+                                              max_steps: Option<usize>)
+                                              -> Result<Option<Solution<ActionId, PropositionId>>, Error>
+    where
+        ActionId: Debug + Hash + Ord + Clone,
+        PropositionId: Debug + Display + Hash + Ord + Clone,
+    {
+        let actions: Vec<ActionIndex> = domain.action_indices()
+            .filter(|a| !domain.is_maintenance(*a))
+            .collect();
+        let mut evaluator = Evaluator::new(self.heuristic, domain, &actions);
+        let priority = |node: &Node| match self.strategy {
+            SearchStrategy::AStar => node.steps + node.estimate,
+            SearchStrategy::GreedyBestFirst => node.estimate,
+        };
+
+        let initial = domain.initial_props().clone();
+        let estimate = match evaluator.evaluate(&initial) {
+            Some(estimate) => estimate,
+            None => return Err(Error::StateSpaceExhausted),
+        };
+        let mut nodes = vec![Node { state: initial.clone(), steps: 0, estimate, parent: None }];
+        // The best node found for each state
+        let mut seen: HashMap<State, usize> = HashMap::new();
+        seen.insert(initial, 0);
+        // Ties are broken by estimate then insertion order so the
+        // search is deterministic
+        let mut open = BinaryHeap::new();
+        open.push(Reverse((priority(&nodes[0]), estimate, 0)));
+        let mut bounded = false;
+
+        while let Some(Reverse((_, _, idx))) = open.pop() {
+            if seen.get(&nodes[idx].state) != Some(&idx) {
+                // A shorter path to this state was found after it was
+                // queued
+                continue;
+            }
+            if domain.goals().is_subset(&nodes[idx].state) {
+                debug!("Found plan after generating {} states", nodes.len());
+                return Ok(Some(Self::plan(domain, &nodes, idx)));
+            }
+            if let Some(max) = max_steps {
+                if nodes[idx].steps >= max {
+                    // States not seen yet need more steps than allowed
+                    bounded = bounded || actions.iter()
+                        .filter_map(|a| Self::successor(domain, &nodes[idx].state, *a))
+                        .any(|state| !seen.contains_key(&state));
+                    continue;
+                }
+            }
+            budget.expand()?;
+
+            for a in &actions {
+                let state = match Self::successor(domain, &nodes[idx].state, *a) {
+                    Some(state) => state,
+                    None => continue,
+                };
+
+                let steps = nodes[idx].steps + 1;
+                let estimate = match seen.get(&state) {
+                    // Greedy search never reopens a state unless it
+                    // is bounded, where a shorter path may be the
+                    // only one within the bound
+                    Some(_) if self.strategy == SearchStrategy::GreedyBestFirst && max_steps.is_none() => continue,
+                    Some(prev) if nodes[*prev].steps <= steps => continue,
+                    Some(prev) => nodes[*prev].estimate,
+                    None => match evaluator.evaluate(&state) {
+                        Some(estimate) => estimate,
+                        None => continue,
+                    },
+                };
+
+                let node = Node { state: state.clone(), steps, estimate, parent: Some((idx, *a)) };
+                let next = nodes.len();
+                open.push(Reverse((priority(&node), estimate, next)));
+                nodes.push(node);
+                seen.insert(state, next);
+            }
+        }
+
+        debug!("Explored {} states without meeting the goals", seen.len());
+        if bounded {
+            Ok(None)
+        } else {
+            Err(Error::StateSpaceExhausted)
+        }
+    }
+
+    /// Returns the state after applying the action or `None` if its
+    /// preconditions don't hold
+    fn successor<ActionId, PropositionId>(domain: &Domain<ActionId, PropositionId>,
+                                          state: &State,
+                                          action: ActionIndex) -> Option<State>
+    where
+        ActionId: Debug + Hash + Ord + Clone,
+        PropositionId: Debug + Display + Hash + Ord + Clone,
+    {
+        if !domain.reqs(action).is_subset(state) {
+            return None;
+        }
+        let mut next = state.clone();
+        for e in domain.effects(action) {
+            if let Some(negation) = domain.negation(*e) {
+                next.remove(&negation);
+            }
+        }
+        next.extend(domain.effects(action).iter().copied());
+        Some(next)
+    }
+
+    fn plan<ActionId, PropositionId>(domain: &Domain<ActionId, PropositionId>,
+                                     nodes: &[Node],
+                                     goal: usize) -> Solution<ActionId, PropositionId>
+    where
+        ActionId: Debug + Hash + Ord + Clone,
+        PropositionId: Debug + Display + Hash + Ord + Clone,
+    {
+        let mut plan = Vec::new();
+        let mut idx = goal;
+        while let Some((parent, action)) = nodes[idx].parent {
+            let mut step = HashSet::new();
+            step.insert(domain.action(action).clone());
+            plan.push(step);
+            idx = parent;
+        }
+        plan.reverse();
+        plan
+    }
+}
+
+impl<ActionId, PropositionId> GraphPlanSolver<ActionId, PropositionId> for HeuristicSearchSolver
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    fn search(&mut self, plangraph: &PlanGraph<ActionId, PropositionId>) -> Result<Option<Solution<ActionId, PropositionId>>, Error> {
+        self.search_within(plangraph, &SearchBudget::default(), &mut ())
+    }
+
+    fn search_within(&mut self,
+                     plangraph: &PlanGraph<ActionId, PropositionId>,
+                     budget: &SearchBudget,
+                     _observer: &mut dyn SearchObserver)
+                     -> Result<Option<Solution<ActionId, PropositionId>>, Error> {
+        self.solve_bounded(plangraph.domain(), budget, Some(plangraph.depth()))
+    }
+}
+
+#[cfg(test)]
+mod heuristic_test {
+    use super::*;
+    use crate::proposition::Proposition;
+    use crate::action::Action;
+    use crate::fixtures::{kitchen, morning, token};
+    use crate::validate::validate_plan;
+    use crate::GraphPlan;
+
+    fn estimate(heuristic: Heuristic) -> Option<usize> {
+        HeuristicSearchSolver::new(heuristic, SearchStrategy::AStar).estimate(&morning())
+    }
+
+    #[test]
+    fn estimates_from_initial_state() {
+        // Coffee comes before walking the dog
+        assert_eq!(estimate(Heuristic::LevelCost), Some(2));
+        assert_eq!(estimate(Heuristic::SetLevel), Some(2));
+        assert_eq!(estimate(Heuristic::RelaxedPlan), Some(2));
+        // Not tired is counted again for walking the dog
+        assert_eq!(estimate(Heuristic::Additive), Some(3));
+    }
+
+    #[test]
+    fn set_level_waits_for_mutexes() {
+        let r = Proposition::from("resource");
+        let not_r = r.negate();
+        let a = Proposition::from("a");
+        let b = Proposition::from("b");
+        let a1 = Action::new("a", fragset!{[&r]}, fragset!{[&a, &not_r]});
+        let a2 = Action::new("b", fragset!{[&r]}, fragset!{[&b, &not_r]});
+        let domain = GraphPlan::create_domain(fragset!{[&r]}, fragset!{[&a, &b]}, fragset!{[&a1, &a2]});
+        let estimate = |heuristic| HeuristicSearchSolver::new(heuristic, SearchStrategy::AStar).estimate(&domain);
+        // Both goals appear after one step but are mutex forever
+        assert_eq!(estimate(Heuristic::LevelCost), Some(1));
+        assert_eq!(estimate(Heuristic::SetLevel), None);
+    }
+
+    #[test]
+    fn finds_optimal_plans_with_astar() {
+        let domain = kitchen();
+        for heuristic in [Heuristic::LevelCost, Heuristic::SetLevel] {
+            let solver = HeuristicSearchSolver::new(heuristic, SearchStrategy::AStar);
+            let plan = solver.solve(&domain).unwrap();
+            assert_eq!(plan.len(), 9, "{:?} plan is not optimal", heuristic);
+            assert!(plan.iter().all(|step| step.len() == 1));
+            assert_eq!(validate_plan(&domain, &plan), Ok(()));
+        }
+    }
+
+    #[test]
+    fn finds_plans_with_greedy_search() {
+        let domain = kitchen();
+        for heuristic in [Heuristic::Additive, Heuristic::RelaxedPlan] {
+            let solver = HeuristicSearchSolver::new(heuristic, SearchStrategy::GreedyBestFirst);
+            let plan = solver.solve(&domain).unwrap();
+            assert_eq!(validate_plan(&domain, &plan), Ok(()));
+            assert_eq!(solver.solve(&domain), Some(plan));
+        }
+    }
+
+    #[test]
+    fn exhausts_unsolvable_problems() {
+        let r = Proposition::from("resource");
+        let not_r = r.negate();
+        let a = Proposition::from("a");
+        let b = Proposition::from("b");
+        let c = Proposition::from("c");
+        let a1 = Action::new("ab", fragset!{[&r]}, fragset!{[&a, &b, &not_r]});
+        let a2 = Action::new("bc", fragset!{[&r]}, fragset!{[&b, &c, &not_r]});
+        let domain = GraphPlan::create_domain(fragset!{[&r]}, fragset!{[&a, &b, &c]}, fragset!{[&a1, &a2]});
+        let solver = HeuristicSearchSolver::default();
+        assert_eq!(solver.estimate(&domain), Some(2));
+        assert_eq!(solver.solve(&domain), None);
+    }
+
+    #[test]
+    fn plans_fit_in_the_plangraph() {
+        // Both goals appear after one layer but take two steps one
+        // action at a time
+        let (a, b) = (Proposition::from("a"), Proposition::from("b"));
+        let make_a = Action::new("make a", fragset!{}, fragset!{[&a]});
+        let make_b = Action::new("make b", fragset!{}, fragset!{[&b]});
+        let domain = GraphPlan::create_domain(fragset!{}, fragset!{[&a, &b]}, fragset!{[&make_a, &make_b]});
+        let mut pg = PlanGraph::from_domain(domain.clone());
+        let mut solver = HeuristicSearchSolver::default();
+        pg.extend().unwrap();
+        assert_eq!(solver.search(&pg), Ok(None));
+        pg.extend().unwrap();
+        let plan = solver.search(&pg).unwrap().unwrap();
+        assert_eq!(plan.len(), 2);
+        assert_eq!(validate_plan(&domain, &plan), Ok(()));
+    }
+
+    #[test]
+    fn proves_unsolvable_as_graphplan_solver() {
+        // Any two of a, b and c can be made with the token but not
+        // all three, which the plangraph can't tell from pairs
+        let domain = token();
+        let mut pg = GraphPlan::from_domain(&domain);
+        assert_eq!(pg.search::<HeuristicSearchSolver>(), Err(Error::StateSpaceExhausted));
+        assert_eq!(pg.plangraph.depth(), 1);
+    }
+
+    #[test]
+    fn stops_within_budget() {
+        use crate::limits::{Limit, SearchLimits};
+        let solver = HeuristicSearchSolver::new(Heuristic::LevelCost, SearchStrategy::AStar);
+        let budget = SearchBudget::new(SearchLimits::new().max_nodes(3).clone());
+        assert_eq!(solver.solve_within(&kitchen(), &budget), Err(Error::LimitReached(Limit::Nodes)));
+        assert_eq!(budget.nodes(), 4);
+    }
+
+    #[test]
+    fn solves_as_graphplan_solver() {
+        let domain = morning();
+        let mut pg = GraphPlan::from_domain(&domain);
+        let plan = pg.search::<HeuristicSearchSolver>().unwrap();
+        assert_eq!(plan.len(), 2);
+        assert_eq!(validate_plan(&domain, &plan), Ok(()));
+    }
+}
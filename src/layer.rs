@@ -1,151 +1,105 @@
 use std::fmt::{Debug, Display};
 use std::hash::Hash;
-use std::collections::{HashSet};
-use crate::proposition::Proposition;
-use crate::action::Action;
-use crate::pairset::{PairSet, pairs, pairs_from_borrowed_sets};
+use crate::bitset::{IndexSet, MutexMatrix};
+use crate::domain::{ActionIndex, Domain, PropositionIndex};
+use crate::pairset::PairSet;
+use crate::parallel;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 
-pub type ActionLayerData<'a, ActionId, PropositionId> = HashSet<&'a Action<'a, ActionId, PropositionId>>;
-pub type PropositionLayerData<'a, PropositionId> = HashSet<&'a Proposition<PropositionId>>;
+pub type ActionLayerData = IndexSet<ActionIndex>;
+pub type PropositionLayerData = IndexSet<PropositionIndex>;
 
 #[derive(Eq, PartialEq, Clone, Debug)]
-pub enum Layer<'a,
-               ActionId: Eq + Hash + Ord + PartialOrd + Clone + Debug,
-               PropositionId: Eq + Hash + Ord + PartialOrd + Clone + Debug + Display> {
-    ActionLayer(ActionLayerData<'a, ActionId, PropositionId>),
-    PropositionLayer(PropositionLayerData<'a, PropositionId>),
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Layer {
+    ActionLayer(ActionLayerData),
+    PropositionLayer(PropositionLayerData),
 }
 
-pub type MutexPairs<T> = HashSet<PairSet<T>>;
+pub type MutexPairs<T> = MutexMatrix<T>;
 
-impl<'a,
-     ActionId: Eq + Hash + Ord + PartialOrd + Clone + Debug,
-     PropositionId: Eq + Hash + Ord + PartialOrd + Clone + Debug + Display>
-    Layer<'a, ActionId, PropositionId> {
+impl Layer {
     /// Create a new layer from another. ActionLayer returns a
     /// PropositionLayer and PropositionLayer returns an ActionLayer
-    pub fn from_layer<'b>(all_actions: &'b HashSet<&'a Action<ActionId, PropositionId>>,
-                          layer: &'b Layer<'a, ActionId, PropositionId>) -> Layer<'a, ActionId, PropositionId> {
+    pub fn from_layer<ActionId, PropositionId>(domain: &Domain<ActionId, PropositionId>,
+                                               all_actions: &ActionLayerData,
+                                               layer: &Layer) -> Layer
+    where
+        ActionId: Debug + Hash + Ord + Clone,
+        PropositionId: Debug + Display + Hash + Ord + Clone,
+    {
         match layer {
             Layer::ActionLayer(actions) => {
                 let mut layer_data = PropositionLayerData::new();
                 for a in actions {
-                    for e in a.effects.iter() {
-                        layer_data.insert(e);
-                    }
+                    layer_data.union_with(domain.effect_set(a));
                 }
 
                 Layer::PropositionLayer(layer_data)
             },
             Layer::PropositionLayer(props) => {
-                let mut layer_data = ActionLayerData::new();
-
-                for a in all_actions {
-                    // Include action if it satisfies one or more props
-                    if a.reqs.is_subset(props) {
-                        layer_data.insert(a);
-                    }
-                }
-
-                // TODO: Move creation of maintenance actions out of
-                // here otherwise it will conflict with the lifetime
-                // 'a
-                // Add in maintenance actions for all props
-                // for p in props {
-                //     layer_data.insert(Action::new_maintenance(p.to_owned()));
-                // }
+                let sets = domain.sets();
+                // Include action if all of its preconditions are in
+                // the layer
+                let layer_data = parallel::filter(all_actions.iter().collect(), |a| {
+                    sets.req_set(*a).is_subset(props)
+                });
+                let layer_data = layer_data.into_iter().collect();
 
                 Layer::ActionLayer(layer_data)
             },
         }
     }
 
-    pub fn action_mutexes(actions: &HashSet<&'a Action<ActionId, PropositionId>>,
-                          mutex_props: Option<&MutexPairs<&Proposition<PropositionId>>>)
-                          -> MutexPairs<&'a Action<'a, ActionId, PropositionId>> {
-        let mut mutexes = MutexPairs::new();
-
-        for PairSet(a1, a2) in pairs(&actions) {
-            // Inconsistent effects: The effect of one action is the
-            // negation of another
-            // - Negate the effects of one of the actions then check
-            //   the overlap
-            // - If there is any overlap the two actions
-            //   are mutex
-            let negated_fx: HashSet<Proposition<PropositionId>> = a1.effects
-                .iter()
-                .map(|e| e.negate())
-                .collect();
-            let inconsistent_fx = a2.effects
-                .intersection(&negated_fx.iter().collect())
-                .map(|i| i.to_owned())
-                .next()
-                .is_some();
-            if inconsistent_fx {
-                mutexes.insert(PairSet(a1, a2));
-                continue
-            }
-
-            // Interference: One action deletes the precondition of
-            // another action (they can't be done in parallel then)
-            let left_interference = a2.reqs
-                .intersection(&negated_fx.iter().collect())
-                .map(|i| i.to_owned())
-                .next()
-                .is_some();
-
-            if left_interference {
-                mutexes.insert(PairSet(a1, a2));
-                continue
-            }
-
-            // Since actions are not symetrical (they may have different
-            // reqs) we need to check if the right hand side action
-            // interferes with left hand side too
-            let right_negated_fx: HashSet<Proposition<PropositionId>> = a2.effects
-                .iter()
-                .map(|e| e.negate())
-                .collect();
-            let right_interference = a1.reqs
-                .intersection(&right_negated_fx.iter().collect())
-                .map(|i| i.to_owned())
-                .next()
-                .is_some();
-
-            if right_interference {
-                mutexes.insert(PairSet(a1, a2));
-                continue
-            }
+    /// Returns the pairs of actions that are mutually exclusive
+    ///
+    /// Inconsistent effects, interference and competing needs on
+    /// propositions that are always mutex are looked up in the static
+    /// mutexes of the domain. Only competing needs on the mutexes of
+    /// the previous proposition layer are found per layer.
+    pub fn action_mutexes<ActionId, PropositionId>(domain: &Domain<ActionId, PropositionId>,
+                                                   actions: &ActionLayerData,
+                                                   mutex_props: Option<&MutexPairs<PropositionIndex>>)
+                                                   -> MutexPairs<ActionIndex>
+    where
+        ActionId: Debug + Hash + Ord + Clone,
+        PropositionId: Debug + Display + Hash + Ord + Clone,
+    {
+        let sets = domain.sets();
+        let static_mutexes = &sets.static_mutexes.actions;
+
+        // The actions each action is mutex with don't depend on the
+        // other actions' so they are found independently
+        let candidates: Vec<ActionIndex> = actions.iter().collect();
+        let conflicts = parallel::map(&candidates, |a1| {
+            let mut conflicts = static_mutexes.row(*a1).cloned().unwrap_or_default();
 
             // Competing needs: Action has precondition that is
-            // mutex
-            // - Generate pairs of Action.reqs and compare them
-            //   to proposition mutees
-            // - Check for intersection with mutex props
+            // mutex with a precondition of the other action
             if let Some(mx_props) = mutex_props {
-                let req_pairs = pairs_from_borrowed_sets(&a1.reqs, &a2.reqs);
-                let competing_needs = req_pairs
-                    .intersection(mx_props)
-                    .next()
-                    .is_some();
-
-                if competing_needs {
-                    mutexes.insert(PairSet(a1, a2));
+                let mut mutex_reqs = PropositionLayerData::new();
+                for p in sets.req_set(*a1) {
+                    if let Some(row) = mx_props.row(p) {
+                        mutex_reqs.union_with(row);
+                    }
+                }
+                for q in &mutex_reqs {
+                    conflicts.union_with(sets.consumers(q));
                 }
             }
 
-            // Conflicting requirements: Actions have preconditions
-            // that are negations of each other
-            // Note: moved this here rather than calculating in
-            // `proposition_mutexes` because we need to create owned
-            // structs via `Proposition.negate()`
-            for p in a1.reqs.iter() {
-                let not_p = p.negate();
-                if a2.reqs.contains(&not_p) {
-                    mutexes.insert(PairSet(a1, a2));
-                    break
-                }
+            conflicts.intersect_with(actions);
+            conflicts
+        });
+
+        let mut mutexes = MutexPairs::new();
+        for (a1, conflicts) in candidates.into_iter().zip(conflicts) {
+            // Every condition is symmetric so each pair only needs
+            // to be recorded once
+            for a2 in conflicts.iter().filter(|a2| *a2 > a1) {
+                mutexes.insert(PairSet(a1, a2));
             }
         }
 
@@ -155,46 +109,75 @@ impl<'a,
     /// Returns a set of propositions that are mutually exclusive
     ///
     /// Propositions are mutex if
-    /// - They are negations of one another
+    /// - They are always mutex, e.g. negations of one another
     /// - All ways of achieving the propositions at are pairwise mutex
-    pub fn proposition_mutexes(
-        props: &HashSet<&'a Proposition<PropositionId>>,
-        actions: &HashSet<&Action<ActionId, PropositionId>>,
-        mutex_actions: Option<MutexPairs<&Action<ActionId, PropositionId>>>,
-    ) -> MutexPairs<&'a Proposition<PropositionId>> {
+    pub fn proposition_mutexes<ActionId, PropositionId>(
+        domain: &Domain<ActionId, PropositionId>,
+        props: &PropositionLayerData,
+        actions: &ActionLayerData,
+        mutex_actions: Option<&MutexPairs<ActionIndex>>,
+    ) -> MutexPairs<PropositionIndex>
+    where
+        ActionId: Debug + Hash + Ord + Clone,
+        PropositionId: Debug + Display + Hash + Ord + Clone,
+    {
         let mut mutexes = MutexPairs::new();
 
-        // Find mutexes due to negation
-        for p in props.iter() {
-            let not_p = p.negate();
-            if props.contains(&not_p) {
-                // TODO: this won't work because this is a reference
-                // to a owned prop in this scope
-                // mutexes.insert(PairSet(*p, &not_p));
+        // Propositions that are always mutex such as negations of
+        // one another
+        let static_mutexes = &domain.static_mutexes().props;
+        for p1 in props {
+            if let Some(row) = static_mutexes.row(p1) {
+                for p2 in row.iter().filter(|p2| *p2 > p1 && props.contains(*p2)) {
+                    mutexes.insert(PairSet(p1, p2));
+                }
             }
         }
 
         // Find mutexes where all ways of achieving p are mutex
-        // - Get all uniq pairs of propositions
-        // - For each pair, get all uniq action pairs that could output the prop pair
-        // - Set difference with the mutex_actions
-        // - If there is no difference then the props are mutex
+        // - For each proposition, get the actions that achieve it
+        //   and the actions that are mutex with all of them
+        // - If an action achieves both they can't be mutex since
+        //   no action is mutex with itself
+        // - If every achiever of the other proposition is among
+        //   them then the props are mutex
         if let Some(mx_actions) = mutex_actions {
-            for PairSet(p1, p2) in pairs(&props) {
-                let viable_acts = actions.iter()
-                    .filter(|a| a.effects.contains(&p1) || a.effects.contains(&p2))
-                    .copied()
-                    .collect();
-
-                let viable_act_pairs = pairs(&viable_acts);
-
-                let no_diff = viable_act_pairs
-                    .difference(&mx_actions)
-                    .next()
-                    .is_none();
+            let sets = domain.sets();
+            let props: Vec<PropositionIndex> = props.iter().collect();
+            let achievers: Vec<(PropositionIndex, ActionLayerData)> = parallel::map(&props, |p| {
+                let mut achievers = sets.adders(*p).clone();
+                achievers.intersect_with(actions);
+                (*p, achievers)
+            });
+
+            // Each proposition is compared with the ones after it,
+            // independently of the others
+            let no_mutexes = ActionLayerData::new();
+            let mutex_with = parallel::map(&achievers, |(p1, achievers1)| {
+                // None when p1 has no achievers in which case every
+                // action is mutex with all of them
+                let mut mutex_with_all: Option<ActionLayerData> = None;
+                for a in achievers1 {
+                    let row = mx_actions.row(a).unwrap_or(&no_mutexes);
+                    match &mut mutex_with_all {
+                        Some(mutex) => mutex.intersect_with(row),
+                        None => mutex_with_all = Some(row.clone()),
+                    }
+                }
 
-                if no_diff && mx_actions.iter().next().is_some() {
-                    mutexes.insert(PairSet(p1, p2));
+                let later = achievers.partition_point(|(p, _)| p <= p1);
+                achievers[later..].iter()
+                    .filter(|(_, achievers2)| match &mutex_with_all {
+                        Some(mutex) => achievers2.is_subset(mutex),
+                        None => true,
+                    })
+                    .map(|(p2, _)| *p2)
+                    .collect::<Vec<_>>()
+            });
+
+            for ((p1, _), mutex_with) in achievers.iter().zip(mutex_with) {
+                for p2 in mutex_with {
+                    mutexes.insert(PairSet(*p1, p2));
                 }
             }
         }
@@ -205,15 +188,21 @@ impl<'a,
 #[cfg(test)]
 mod from_layer_test {
     use super::*;
+    use crate::proposition::Proposition;
+    use crate::action::Action;
 
     #[test]
     fn action_layer_from_proposition_layer() {
         let prop = Proposition::from("test");
         let action = Action::new_maintenance(&prop);
-        let layer = Layer::<&str, &str>::PropositionLayer(fragset!{[&prop]});
-        let actions = fragset!{[&action]};
-        let actual = Layer::from_layer(&actions, &layer);
-        let expected = Layer::ActionLayer(fragset!{[&action]});
+        let domain: Domain<&str, &str> = Domain::new(vec![], vec![], vec![action.clone()]);
+        let prop_idx = domain.proposition_index(&prop).unwrap();
+        let action_idx = domain.action_index(&action).unwrap();
+
+        let layer = Layer::PropositionLayer([prop_idx].iter().collect());
+        let actions: ActionLayerData = [action_idx].iter().collect();
+        let actual = Layer::from_layer(&domain, &actions, &layer);
+        let expected = Layer::ActionLayer(actions.clone());
         assert_eq!(expected, actual);
     }
 }
@@ -221,13 +210,18 @@ mod from_layer_test {
 #[cfg(test)]
 mod mutex_test {
     use super::*;
+    use crate::proposition::Proposition;
+    use crate::action::Action;
+
+    fn domain<'a>(actions: &[&Action<&'a str, &'a str>]) -> Domain<&'a str, &'a str> {
+        Domain::new(vec![], vec![], actions.iter().map(|a| (*a).clone()))
+    }
 
     #[test]
     fn proposition_mutexes_due_to_mutex_actions() {
         let p1 = Proposition::from("caffeinated");
         let p2 = Proposition::from("coffee");
         let p3 = p2.negate();
-        let actions = fragset!{};
         let a1 = Action::new(
             "drink coffee",
             fragset!{[&p2]},
@@ -238,12 +232,53 @@ mod mutex_test {
             fragset!{},
             fragset!{[&p2]},
         );
-        let action_mutexes = fragset!{[PairSet(&a1, &a2)]};
-        let expected = fragset!{[PairSet(&p1, &p2)]};
-        let props = fragset!{[&p1, &p2]};
+        let domain = domain(&[&a1, &a2]);
+        let (a1, a2) = (domain.action_index(&a1).unwrap(), domain.action_index(&a2).unwrap());
+        let (p1, p2) = (domain.proposition_index(&p1).unwrap(), domain.proposition_index(&p2).unwrap());
+
+        let actions: ActionLayerData = [a1, a2].iter().collect();
+        let action_mutexes: MutexPairs<_> = vec![PairSet(a1, a2)].into_iter().collect();
+        let expected: MutexPairs<_> = vec![PairSet(p1, p2)].into_iter().collect();
+        let props: PropositionLayerData = [p1, p2].iter().collect();
+        assert_eq!(
+            expected,
+            Layer::proposition_mutexes(&domain, &props, &actions, Some(&action_mutexes))
+        );
+    }
+
+    #[test]
+    fn proposition_mutexes_not_when_achieved_together() {
+        let p1 = Proposition::from("caffeinated");
+        let p2 = Proposition::from("awake");
+        let a1 = Action::new("drink coffee", fragset!{}, fragset!{[&p1, &p2]});
+        let a2 = Action::new("nap", fragset!{}, fragset!{[&p2]});
+        let domain = domain(&[&a1, &a2]);
+        let (a1, a2) = (domain.action_index(&a1).unwrap(), domain.action_index(&a2).unwrap());
+
+        let props = domain.initial_props().iter()
+            .chain(domain.effects(a1))
+            .copied()
+            .collect();
+        let actions: ActionLayerData = [a1, a2].iter().collect();
+        let action_mutexes: MutexPairs<_> = vec![PairSet(a1, a2)].into_iter().collect();
+        assert!(Layer::proposition_mutexes(&domain, &props, &actions, Some(&action_mutexes)).is_empty());
+    }
+
+    #[test]
+    fn proposition_mutexes_include_negations() {
+        let p1 = Proposition::from("coffee");
+        let p2 = p1.negate();
+        let a1 = Action::new_maintenance(&p1);
+        let a2 = Action::new_maintenance(&p2);
+        let domain = domain(&[&a1, &a2]);
+        let (p1, p2) = (domain.proposition_index(&p1).unwrap(), domain.proposition_index(&p2).unwrap());
+
+        // Mutex even without any action mutexes
+        let props: PropositionLayerData = [p1, p2].iter().collect();
+        let expected: MutexPairs<_> = vec![PairSet(p1, p2)].into_iter().collect();
         assert_eq!(
             expected,
-            Layer::proposition_mutexes(&props, &actions, Some(action_mutexes))
+            Layer::proposition_mutexes(&domain, &props, &ActionLayerData::new(), None)
         );
     }
 
@@ -262,12 +297,14 @@ mod mutex_test {
             fragset!{},
             fragset!{[&prop]}
         );
-        let actions = fragset!{[&a1, &a2]};
+        let domain = domain(&[&a1, &a2]);
+        let (a1, a2) = (domain.action_index(&a1).unwrap(), domain.action_index(&a2).unwrap());
+        let actions: ActionLayerData = [a1, a2].iter().collect();
         let props = MutexPairs::new();
-        let actual = Layer::action_mutexes(&actions, Some(&props));
+        let actual = Layer::action_mutexes(&domain, &actions, Some(&props));
 
         let mut expected = MutexPairs::new();
-        expected.insert(PairSet(&a1, &a2));
+        expected.insert(PairSet(a1, a2));
 
         assert_eq!(expected, actual);
     }
@@ -287,12 +324,14 @@ mod mutex_test {
             fragset!{[&prop]},
             fragset!{[&not_prop]}
         );
-        let actions = fragset!{[&a1, &a2]};
+        let domain = domain(&[&a1, &a2]);
+        let (a1, a2) = (domain.action_index(&a1).unwrap(), domain.action_index(&a2).unwrap());
+        let actions: ActionLayerData = [a1, a2].iter().collect();
         let props = MutexPairs::new();
-        let actual = Layer::action_mutexes(&actions, Some(&props));
+        let actual = Layer::action_mutexes(&domain, &actions, Some(&props));
 
         let mut expected = MutexPairs::new();
-        expected.insert(PairSet(&a1, &a2));
+        expected.insert(PairSet(a1, a2));
 
         assert_eq!(expected, actual);
     }
@@ -312,13 +351,16 @@ mod mutex_test {
             fragset!{[&prop]},
             fragset!{[&not_prop]}
         );
-        let actions = fragset!{[&a1, &a2]};
+        let domain = domain(&[&a1, &a2]);
+        let (a1, a2) = (domain.action_index(&a1).unwrap(), domain.action_index(&a2).unwrap());
+        let (prop, not_prop) = (domain.proposition_index(&prop).unwrap(), domain.proposition_index(&not_prop).unwrap());
+        let actions: ActionLayerData = [a1, a2].iter().collect();
         let mut mutex_props = MutexPairs::new();
-        mutex_props.insert(PairSet(&prop, &not_prop));
-        let actual = Layer::action_mutexes(&actions, Some(&mutex_props));
+        mutex_props.insert(PairSet(prop, not_prop));
+        let actual = Layer::action_mutexes(&domain, &actions, Some(&mutex_props));
 
         let mut expected = MutexPairs::new();
-        expected.insert(PairSet(&a1, &a2));
+        expected.insert(PairSet(a1, a2));
 
         assert_eq!(expected, actual);
     }
@@ -330,11 +372,13 @@ mod mutex_test {
         let a1 = Action::new("eat sandwich", fragset!{[&prop]}, fragset!{});
         let a2 = Action::new("go to work", fragset!{[&not_prop]}, fragset!{});
 
-        let actions = fragset!{[&a1, &a2]};
-        let actual = Layer::action_mutexes(&actions, None);
+        let domain = domain(&[&a1, &a2]);
+        let (a1, a2) = (domain.action_index(&a1).unwrap(), domain.action_index(&a2).unwrap());
+        let actions: ActionLayerData = [a1, a2].iter().collect();
+        let actual = Layer::action_mutexes(&domain, &actions, None);
 
         let mut expected = MutexPairs::new();
-        expected.insert(PairSet(&a1, &a2));
+        expected.insert(PairSet(a1, a2));
 
         assert_eq!(expected, actual);
     }
@@ -1,41 +1,65 @@
 use std::collections::{HashSet};
+use std::convert::TryFrom;
 use std::hash::Hash;
 use std::fmt;
 use std::fmt::{Debug, Display, Formatter};
+use std::time::Instant;
 use log::{debug};
 
 #[macro_use] pub mod macros;
 pub mod proposition;
 pub mod action;
+pub mod cdcl;
+pub mod domain;
+pub mod dot;
+pub mod error;
+pub mod heuristic;
+pub mod limits;
+pub mod observer;
+pub mod ordering;
 pub mod plangraph;
+pub mod plans;
+pub mod portfolio;
 pub mod solver;
+pub mod sat;
+pub mod validate;
+pub mod schema;
+pub mod pddl;
+mod bitset;
 mod layer;
+mod mutex;
 mod pairset;
+mod parallel;
+#[cfg(test)]
+mod fixtures;
 
 pub use crate::proposition::Proposition;
-pub use crate::action::{Action, ActionType};
-pub use crate::plangraph::{PlanGraph, Solution};
-pub use crate::solver::{GraphPlanSolver, SimpleSolver};
-
-
-/// Represents a planning domain that can generate a GraphPlan via
-/// `from_domain`. Serves as a helper object to prevent lifetime
-/// issues when auto generating things like maintenance actions
-pub struct Domain<'a,
-                  ActionId: Debug + Hash + Ord + Clone,
-                  PropositionId: Debug + Display + Hash + Ord + Clone> {
-    initial_props: HashSet<&'a Proposition<PropositionId>>,
-    goals: HashSet<&'a Proposition<PropositionId>>,
-    actions: HashSet<Action<'a, ActionId, PropositionId>>
-}
+pub use crate::action::{Action, ActionType, ConditionalEffect};
+pub use crate::domain::{ActionIndex, Domain, DomainOptions, PropositionIndex};
+pub use crate::dot::DotOptions;
+pub use crate::error::Error;
+pub use crate::heuristic::{Heuristic, HeuristicSearchSolver, SearchStrategy};
+pub use crate::limits::{Limit, SearchBudget, SearchLimits};
+pub use crate::observer::{ExtendedLayer, SearchObserver, SearchStats};
+pub use crate::ordering::{ActionOrder, Comparator, GoalOrder, SearchHeuristics};
+pub use crate::plangraph::{CostOptimalPlan, PlanGraph, Solution, SolutionCost};
+pub use crate::plans::Plans;
+pub use crate::portfolio::PortfolioSolver;
+pub use crate::sat::SatSolver;
+pub use crate::solver::{GraphPlanSolver, Nogoods, PlanCursor, SimpleSolver};
+pub use crate::validate::{validate_plan, ValidationError, ValidationFailure};
 
-pub struct GraphPlan<'a,
-                     ActionId: Debug + Hash + Ord + Clone,
-                     PropositionId: Debug + Display + Hash + Ord + Clone> {
-    plangraph: PlanGraph<'a, ActionId, PropositionId>,
+
+pub struct GraphPlan<ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    plangraph: PlanGraph<ActionId, PropositionId>,
+    limits: SearchLimits,
 }
 
-impl<'a, ActionId, PropositionId> Display for GraphPlan<'a, ActionId, PropositionId>
+impl<ActionId, PropositionId> Display for GraphPlan<ActionId, PropositionId>
 where
     ActionId: Debug + Hash + Ord + Clone,
     PropositionId: Debug + Display + Hash + Ord + Clone,
@@ -50,40 +74,72 @@ where
     }
 }
 
-impl<'a,
-     ActionId: Debug + Hash + Ord + Clone,
-     PropositionId: Debug + Display + Hash + Ord + Clone> GraphPlan<'a, ActionId, PropositionId> {
-
+impl<ActionId, PropositionId> GraphPlan<ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
     /// Returns a new GraphPlan. Note: you probably want to use
     /// `from_domain` instead.
-    pub fn new(initial_props: HashSet<&'a Proposition<PropositionId>>,
-               goals: HashSet<&'a Proposition<PropositionId>>,
-               actions: HashSet<&'a Action<'a, ActionId, PropositionId>>)
-               -> GraphPlan<'a, ActionId, PropositionId> {
+    pub fn new(initial_props: HashSet<&Proposition<PropositionId>>,
+               goals: HashSet<&Proposition<PropositionId>>,
+               actions: HashSet<&Action<ActionId, PropositionId>>)
+               -> GraphPlan<ActionId, PropositionId> {
         let plangraph = PlanGraph::new(
             initial_props,
             goals,
             actions,
         );
-        GraphPlan { plangraph }
+        GraphPlan::from_plangraph(plangraph)
     }
 
-    pub fn from_domain(domain: &'a Domain<'a, ActionId, PropositionId>)
-               -> GraphPlan<'a, ActionId, PropositionId> {
-        let plangraph = PlanGraph::new(
-            domain.initial_props.clone(),
-            domain.goals.clone(),
-            domain.actions.iter().collect(),
-        );
-        GraphPlan { plangraph }
+    /// Returns a GraphPlan that continues from an existing
+    /// plangraph such as one loaded from a snapshot
+    pub fn from_plangraph(plangraph: PlanGraph<ActionId, PropositionId>)
+                          -> GraphPlan<ActionId, PropositionId> {
+        GraphPlan { plangraph, limits: SearchLimits::default() }
     }
 
-    /// Returns a domain with all maintenance actions.automatically
-    /// created. This is needed to avoid lifetime issues with PlanGraph
-    pub fn create_domain(initial_props: HashSet<&'a Proposition<PropositionId>>,
-                         goals: HashSet<&'a Proposition<PropositionId>>,
-                         actions: HashSet<&'a Action<'a, ActionId, PropositionId>>)
-                         -> Domain<'a, ActionId, PropositionId> {
+    pub fn from_domain(domain: &Domain<ActionId, PropositionId>)
+               -> GraphPlan<ActionId, PropositionId> {
+        let plangraph = PlanGraph::from_domain(domain.clone());
+        GraphPlan::from_plangraph(plangraph)
+    }
+
+    /// Limits every later search. When one is hit `search` returns
+    /// `Error::LimitReached` rather than continuing.
+    pub fn set_limits(&mut self, limits: SearchLimits) -> &mut Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Returns the plangraph as extended by the last search
+    pub fn plangraph(&self) -> &PlanGraph<ActionId, PropositionId> {
+        &self.plangraph
+    }
+
+    /// Returns a domain with all maintenance actions automatically
+    /// created
+    pub fn create_domain(initial_props: HashSet<&Proposition<PropositionId>>,
+                         goals: HashSet<&Proposition<PropositionId>>,
+                         actions: HashSet<&Action<ActionId, PropositionId>>)
+                         -> Domain<ActionId, PropositionId> {
+        GraphPlan::create_domain_with(initial_props, goals, actions, &DomainOptions::default())
+    }
+
+    /// Same as `create_domain` but first completes the initial state
+    /// as set in `options`, e.g. under the closed world assumption
+    pub fn create_domain_with(initial_props: HashSet<&Proposition<PropositionId>>,
+                              goals: HashSet<&Proposition<PropositionId>>,
+                              actions: HashSet<&Action<ActionId, PropositionId>>,
+                              options: &DomainOptions)
+                              -> Domain<ActionId, PropositionId> {
+        let assumed_false = options.assumed_false(&initial_props, &goals, &actions);
+        debug!("Assuming {} propositions are initially false", assumed_false.len());
+        let initial_props: HashSet<&Proposition<PropositionId>> = initial_props.into_iter()
+            .chain(assumed_false.iter())
+            .collect();
+
         let mut all_actions = HashSet::new();
 
         for p in &initial_props {
@@ -100,46 +156,228 @@ impl<'a,
             for p in &a.effects {
                 all_actions.insert(Action::new_maintenance(p));
             }
-        }
 
-        Domain {
-            initial_props,
-            goals,
-            actions: all_actions,
+            // Conditional effects that don't fire require the
+            // negation of one of their conditions
+            for ce in &a.conditional_effects {
+                for p in &ce.conditions {
+                    all_actions.insert(Action::new_maintenance(p));
+                    all_actions.insert(Action::new_maintenance(&p.negate()));
+                }
+                for p in &ce.effects {
+                    all_actions.insert(Action::new_maintenance(p));
+                }
+            }
         }
+
+        Domain::new(
+            initial_props.into_iter().cloned(),
+            goals.into_iter().cloned(),
+            all_actions,
+        )
     }
 
-    pub fn search<Solver>(&mut self) -> Option<Solution<'a, ActionId, PropositionId>>
-        where Solver: GraphPlanSolver<'a, ActionId, PropositionId> {
+    /// Extends the plangraph and searches it until a plan is found
+    /// or the problem is proven unsolvable.
+    ///
+    /// Follows the termination test from Blum and Furst. Once the
+    /// graph levels off at layer n, every later layer is identical.
+    /// If the solver records unsolvable goal sets (see
+    /// `GraphPlanSolver::nogoods`) and the number recorded at layer n
+    /// is the same after two consecutive failed searches, no plan
+    /// exists. Solvers that don't record goal sets stop after a bound
+    /// on the number of extensions instead.
+    ///
+    /// The limits set with `set_limits` are checked while extending
+    /// the plangraph and while searching it.
+    pub fn search<Solver>(&mut self) -> Result<Solution<ActionId, PropositionId>, Error>
+        where Solver: GraphPlanSolver<ActionId, PropositionId> + Default {
+        self.search_with(&mut Solver::default())
+    }
+
+    /// Same as `search` but with a solver that was already created,
+    /// e.g. with options or picked at runtime. The solver is reset
+    /// first and keeps what it learns during the search, such as its
+    /// nogoods, until it is used again.
+    pub fn search_with(&mut self, solver: &mut dyn GraphPlanSolver<ActionId, PropositionId>)
+                       -> Result<Solution<ActionId, PropositionId>, Error> {
+        self.search_with_observer(solver, &mut ())
+    }
+
+    /// Same as `search_with` but also returns statistics about the
+    /// search, whether or not it found a plan
+    pub fn search_with_stats(&mut self, solver: &mut dyn GraphPlanSolver<ActionId, PropositionId>)
+                             -> (Result<Solution<ActionId, PropositionId>, Error>, SearchStats) {
+        let mut stats = SearchStats::new();
+        let start = Instant::now();
+        let result = self.search_with_observer(solver, &mut stats);
+        stats.search_time = start.elapsed().saturating_sub(stats.extend_time);
+        (result, stats)
+    }
+
+    /// Same as `search_with` but reports each layer extended and each
+    /// step of the solver to `observer`
+    pub fn search_with_observer(&mut self,
+                                solver: &mut dyn GraphPlanSolver<ActionId, PropositionId>,
+                                observer: &mut dyn SearchObserver)
+                                -> Result<Solution<ActionId, PropositionId>, Error> {
+        let budget = SearchBudget::new(self.limits.clone());
+        self.search_within(solver, &budget, observer)
+    }
 
+    pub(crate) fn search_within(&mut self,
+                                solver: &mut dyn GraphPlanSolver<ActionId, PropositionId>,
+                                budget: &SearchBudget,
+                                observer: &mut dyn SearchObserver)
+                                -> Result<Solution<ActionId, PropositionId>, Error> {
+        // Nogoods from another problem don't hold for this one, the
+        // ones found at each depth below carry over to the next
+        solver.reset();
+        let memo_len = |solver: &dyn GraphPlanSolver<ActionId, PropositionId>| solver.nogoods().map(Nogoods::len);
+        let mut level_off = None;
+        let mut prev_nogoods_count = None;
+        let mut memoizes = true;
         let mut tries = 0;
-        let mut solution = None;
-        let max_tries = self.plangraph.actions.len() + 1;
-
-        while tries < max_tries {
-            self.plangraph.extend();
-            // This doesn't provide early termination for _all_
-            // cases that won't yield a solution.
-            if self.plangraph.has_leveled_off() {
-                break;
+        let max_tries = self.plangraph.domain().actions().len() + 1;
+
+        // A plangraph that is already extended, e.g. one loaded from a
+        // snapshot, is searched as is before extending it further
+        let mut resume = self.plangraph.depth() > 0;
+
+        loop {
+            if resume {
+                resume = false;
+            } else {
+                let start = Instant::now();
+                self.plangraph.extend_within(&self.limits)?;
+                observer.layer_extended(&self.plangraph.extended_layer(start.elapsed()));
             }
+            tries += 1;
 
-            if !self.plangraph.has_possible_solution() {
+            if level_off.is_none() && self.plangraph.has_leveled_off() {
+                let layer = self.plangraph.layers.len() - 3;
+                debug!("Leveled off at layer {}", layer);
+                level_off = Some(layer);
+            }
+
+            if self.plangraph.has_possible_solution()? {
+                let before = memo_len(solver);
+                if let Some(result) = solver.search_within(&self.plangraph, budget, observer)? {
+                    observer.solution_found(result.len());
+                    return Ok(result);
+                }
+                debug!("No solution found at depth {}", self.plangraph.depth());
+                // A solver that memoizes always records the goals it
+                // failed to meet at the last layer
+                memoizes = memoizes && memo_len(solver) > before;
+            } else {
                 debug!("No solution exists at depth {}", self.plangraph.depth());
-                tries += 1;
-                continue
+                // Nothing changes after leveling off so the goals
+                // will never be present and non mutex
+                if level_off.is_some() {
+                    return if !self.plangraph.goals_present()? {
+                        Err(Error::GoalsUnreachable)
+                    } else {
+                        Err(Error::GoalsMutex)
+                    };
+                }
+            }
+
+            if let Some(layer) = level_off {
+                if memoizes {
+                    let count = solver.nogoods().map_or(0, |nogoods| nogoods.count(layer));
+                    if prev_nogoods_count == Some(count) {
+                        return Err(Error::LeveledOff);
+                    }
+                    prev_nogoods_count = Some(count);
+                }
+            }
+
+            if !memoizes && tries >= max_tries {
+                return Err(Error::SearchExhausted);
+            }
+        }
+    }
+
+    /// Searches for the plan with the lowest total cost rather than
+    /// the fewest steps. Once `search` finds a plan, the solver keeps
+    /// looking for cheaper ones at that depth and deeper until no
+    /// cheaper plan can exist within the bound:
+    ///
+    /// - When every action other than maintenance actions costs at
+    ///   least 1, a plan cheaper than cost c has fewer than c actions
+    ///   so it takes at most c - 1 steps
+    /// - Otherwise only plans up to the maximum depth of the limits,
+    ///   or as deep as the first plan without one, are compared
+    ///
+    /// The maximum depth of the limits always bounds the search.
+    /// Only solvers that implement `GraphPlanSolver::search_cheapest`
+    /// such as `SimpleSolver` are guaranteed to find the cheapest
+    /// plan.
+    pub fn search_cheapest<Solver>(&mut self) -> Result<CostOptimalPlan<ActionId, PropositionId>, Error>
+        where Solver: GraphPlanSolver<ActionId, PropositionId> + Default {
+        self.search_cheapest_with(&mut Solver::default())
+    }
+
+    /// Same as `search_cheapest` but with a solver that was already
+    /// created
+    pub fn search_cheapest_with(&mut self, solver: &mut dyn GraphPlanSolver<ActionId, PropositionId>)
+                                -> Result<CostOptimalPlan<ActionId, PropositionId>, Error> {
+        let budget = SearchBudget::new(self.limits.clone());
+        let first = self.search_within(solver, &budget, &mut ())?;
+        let first_depth = self.plangraph.depth();
+        let free_actions = self.plangraph.domain().actions().iter()
+            .any(|a| a.cost == 0 && a.get_action().is_ok());
+
+        let mut best = first;
+        loop {
+            let bound = best.cost();
+            if let Some(cheaper) = solver.search_cheapest(&self.plangraph, &budget, &mut (), Some(bound))? {
+                debug!("Found a plan that costs {} at depth {}", cheaper.cost(), self.plangraph.depth());
+                best = cheaper;
             }
 
-            if let Some(result) = Solver::search(&self.plangraph) {
-                solution = Some(result);
+            let horizon = match (free_actions, self.limits.max_depth) {
+                (false, max_depth) => {
+                    let by_cost = usize::try_from(best.cost().saturating_sub(1)).unwrap_or(usize::MAX);
+                    max_depth.map_or(by_cost, |max| max.min(by_cost))
+                },
+                (true, Some(max_depth)) => max_depth,
+                (true, None) => first_depth,
+            };
+            if self.plangraph.depth() >= horizon {
                 break;
-            } else {
-                debug!("No solution found at depth {}", self.plangraph.depth());
-                tries += 1
             }
-        };
+            self.plangraph.extend_within(&self.limits)?;
+        }
+
+        Ok(CostOptimalPlan::new(best))
+    }
 
-        solution
+    /// Returns an iterator over every distinct plan at the shortest
+    /// depth with a plan, then at the depths after it. Plans that
+    /// only differ in maintenance actions or in steps without any
+    /// actions are the same plan and are yielded once, so a deeper
+    /// plan is only yielded if it isn't an earlier one with steps
+    /// that wait.
+    ///
+    /// Iteration ends at the maximum depth of the limits, searching
+    /// every depth up to it, or without one after a depth without any
+    /// new plans. It also ends with the first error. Plans
+    /// that take an action no goal needs at that step are left out.
+    /// Only solvers that implement
+    /// `GraphPlanSolver::next_plan` such as `SimpleSolver` yield more
+    /// than one plan per depth.
+    pub fn plans<Solver>(&mut self) -> Plans<'_, ActionId, PropositionId, Solver>
+        where Solver: GraphPlanSolver<ActionId, PropositionId> + Default {
+        self.plans_with(Solver::default())
+    }
+
+    /// Same as `plans` but with a solver that was already created.
+    /// Pass `&mut solver` to keep using it afterwards.
+    pub fn plans_with<Solver>(&mut self, solver: Solver) -> Plans<'_, ActionId, PropositionId, Solver>
+        where Solver: GraphPlanSolver<ActionId, PropositionId> {
+        Plans::new(self, solver)
     }
 
     /// Takes a solution and filters out maintenance actions
@@ -157,7 +395,9 @@ impl<'a,
 
 #[cfg(test)]
 mod integration_test {
-    use crate::GraphPlan;
+    use std::collections::BTreeSet;
+    use crate::{Domain, Error, ExtendedLayer, GraphPlan, GraphPlanSolver, Limit, PlanGraph,
+                PropositionIndex, SearchLimits, SearchObserver, Solution, SolutionCost};
     use crate::proposition::Proposition;
     use crate::action::Action;
     use crate::solver::SimpleSolver;
@@ -192,6 +432,538 @@ mod integration_test {
         );
 
         let mut pg = GraphPlan::<&str, &str>::from_domain(&domain);
-        assert!(pg.search::<SimpleSolver>() != None, "Solution should not be None");
+        assert!(pg.search::<SimpleSolver>().is_ok(), "Solution should not be an error");
+    }
+
+    #[test]
+    fn reports_unreachable_goals() {
+        let p1 = Proposition::from("tired");
+        let not_p1 = p1.negate();
+        let p2 = Proposition::from("rested");
+        let a1 = Action::new("coffee", fragset!{[&p1]}, fragset!{[&not_p1]});
+
+        let domain = GraphPlan::create_domain(fragset!{[&p1]}, fragset!{[&p2]}, fragset!{[&a1]});
+        let mut pg = GraphPlan::<&str, &str>::from_domain(&domain);
+        assert_eq!(pg.search::<SimpleSolver>(), Err(Error::GoalsUnreachable));
+    }
+
+    #[test]
+    fn reports_mutex_goals() {
+        let p1 = Proposition::from("tired");
+        let not_p1 = p1.negate();
+        let a1 = Action::new("coffee", fragset!{[&p1]}, fragset!{[&not_p1]});
+
+        let domain = GraphPlan::create_domain(
+            fragset!{[&p1]},
+            fragset!{[&p1, &not_p1]},
+            fragset!{[&a1]},
+        );
+        let mut pg = GraphPlan::<&str, &str>::from_domain(&domain);
+        assert_eq!(pg.search::<SimpleSolver>(), Err(Error::GoalsMutex));
+    }
+
+    /// Each action achieves two of the three goals but uses up the
+    /// one resource so the goals are pairwise reachable and never
+    /// mutex but can't all be met
+    fn pairwise_reachable() -> Domain<&'static str, &'static str> {
+        let r = Proposition::from("resource");
+        let not_r = r.negate();
+        let a = Proposition::from("a");
+        let b = Proposition::from("b");
+        let c = Proposition::from("c");
+
+        let a1 = Action::new("ab", fragset!{[&r]}, fragset!{[&a, &b, &not_r]});
+        let a2 = Action::new("bc", fragset!{[&r]}, fragset!{[&b, &c, &not_r]});
+        let a3 = Action::new("ac", fragset!{[&r]}, fragset!{[&a, &c, &not_r]});
+
+        GraphPlan::create_domain(
+            fragset!{[&r]},
+            fragset!{[&a, &b, &c]},
+            fragset!{[&a1, &a2, &a3]},
+        )
+    }
+
+    #[test]
+    fn proves_unsolvable_after_leveling_off() {
+        let mut pg = GraphPlan::from_domain(&pairwise_reachable());
+        assert_eq!(pg.search::<SimpleSolver>(), Err(Error::LeveledOff));
+    }
+
+    #[derive(Default)]
+    struct ForgetfulSolver;
+
+    impl GraphPlanSolver<&'static str, &'static str> for ForgetfulSolver {
+        fn search(&mut self, plangraph: &PlanGraph<&'static str, &'static str>)
+                  -> Result<Option<Solution<&'static str, &'static str>>, Error> {
+            SimpleSolver::default().search(plangraph)
+        }
+    }
+
+    #[test]
+    fn bounds_solvers_without_nogoods() {
+        let mut pg = GraphPlan::from_domain(&pairwise_reachable());
+        assert_eq!(pg.search::<ForgetfulSolver>(), Err(Error::SearchExhausted));
+    }
+
+    #[test]
+    fn searches_with_a_solver_picked_at_runtime() {
+        let domain = breakfast();
+        for name in ["simple", "sat", "heuristic"] {
+            let mut solver: Box<dyn GraphPlanSolver<&str, &str>> = match name {
+                "sat" => Box::new(crate::SatSolver),
+                "heuristic" => Box::new(crate::HeuristicSearchSolver::default()),
+                _ => Box::new(SimpleSolver::default()),
+            };
+            let plan = GraphPlan::from_domain(&domain).search_with(&mut *solver).unwrap();
+            assert_eq!(crate::validate_plan(&domain, &plan), Ok(()));
+        }
+
+        // Nogoods are kept on the solver, which `plans_with` borrows
+        let mut solver = SimpleSolver::default();
+        let mut pg = GraphPlan::from_domain(&pairwise_reachable());
+        assert!(pg.plans_with(&mut solver).next().unwrap().is_err());
+        assert!(!solver.nogoods().is_empty());
+    }
+
+    #[test]
+    fn stops_at_max_depth() {
+        let mut pg = GraphPlan::from_domain(&pairwise_reachable());
+        pg.set_limits(SearchLimits::new().max_depth(1).clone());
+        let mut solver = SimpleSolver::default();
+        assert_eq!(pg.search_with(&mut solver), Err(Error::LimitReached(Limit::Depth)));
+        assert_eq!(pg.plangraph().depth(), 1);
+        assert!(!solver.nogoods().is_empty());
+    }
+
+    #[test]
+    fn stops_at_node_and_backtrack_limits() {
+        let mut pg = GraphPlan::from_domain(&pairwise_reachable());
+        pg.set_limits(SearchLimits::new().max_nodes(2).clone());
+        assert_eq!(pg.search::<SimpleSolver>(), Err(Error::LimitReached(Limit::Nodes)));
+
+        let mut pg = GraphPlan::from_domain(&pairwise_reachable());
+        pg.set_limits(SearchLimits::new().max_backtracks(2).clone());
+        let mut limited = SimpleSolver::default();
+        assert_eq!(pg.search_with(&mut limited), Err(Error::LimitReached(Limit::Backtracks)));
+        // Goal sets cut off by the limit are not recorded as unsolvable
+        let mut unlimited = SimpleSolver::default();
+        assert_eq!(GraphPlan::from_domain(&pairwise_reachable()).search_with(&mut unlimited), Err(Error::LeveledOff));
+        assert!(limited.nogoods().len() < unlimited.nogoods().len());
+    }
+
+    #[test]
+    fn stops_when_cancelled() {
+        use std::sync::Arc;
+        use std::sync::atomic::AtomicBool;
+
+        let cancel = Arc::new(AtomicBool::new(true));
+        let mut pg = GraphPlan::from_domain(&pairwise_reachable());
+        pg.set_limits(SearchLimits::new().cancel(cancel).clone());
+        assert_eq!(pg.search::<SimpleSolver>(), Err(Error::LimitReached(Limit::Cancelled)));
+        assert_eq!(pg.plangraph().depth(), 0);
+    }
+
+    #[test]
+    fn collects_search_stats() {
+        let mut pg = GraphPlan::from_domain(&pairwise_reachable());
+        let mut solver = SimpleSolver::default();
+        let (result, stats) = pg.search_with_stats(&mut solver);
+        assert_eq!(result, Err(Error::LeveledOff));
+        assert_eq!(stats.layers_extended, pg.plangraph().depth());
+        assert_eq!(stats.memoized, solver.nogoods().len());
+        assert!(stats.memo_hits > 0);
+        assert!(stats.nodes_expanded >= stats.memoized);
+        assert_eq!(stats.backtracks, stats.memoized + stats.memo_hits);
+        assert!(stats.max_stack_depth > 1);
+        assert_eq!(stats.solution_steps, None);
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        layers: Vec<ExtendedLayer>,
+        pushed: usize,
+        popped: usize,
+        solution: Option<usize>,
+    }
+
+    impl SearchObserver for Recorder {
+        fn layer_extended(&mut self, layer: &ExtendedLayer) {
+            self.layers.push(*layer);
+        }
+
+        fn goals_pushed(&mut self, _layer: usize, _goals: &BTreeSet<PropositionIndex>) {
+            self.pushed += 1;
+        }
+
+        fn goals_popped(&mut self, _layer: usize, _goals: &BTreeSet<PropositionIndex>) {
+            self.popped += 1;
+        }
+
+        fn solution_found(&mut self, steps: usize) {
+            self.solution = Some(steps);
+        }
+    }
+
+    #[test]
+    fn reports_to_observer() {
+        let p1 = Proposition::from("tired");
+        let not_p1 = p1.negate();
+        let a1 = Action::new("coffee", fragset!{[&p1]}, fragset!{[&not_p1]});
+        let domain = GraphPlan::create_domain(fragset!{[&p1]}, fragset!{[&not_p1]}, fragset!{[&a1]});
+
+        let mut recorder = Recorder::default();
+        let mut pg = GraphPlan::from_domain(&domain);
+        let plan = pg.search_with_observer(&mut SimpleSolver::default(), &mut recorder).unwrap();
+        assert_eq!(recorder.solution, Some(plan.len()));
+        assert_eq!(recorder.layers.len(), 1);
+        let layer = recorder.layers[0];
+        // Coffee and the maintenance action of tired
+        assert_eq!((layer.depth, layer.propositions, layer.actions), (1, 2, 2));
+        assert_eq!((layer.mutex_props, layer.mutex_actions), (1, 1));
+        // The goals are left on the stack to look for more plans
+        assert_eq!((recorder.pushed, recorder.popped), (2, 1));
+    }
+
+    /// A taxi gets to the office in one step but the bus is cheaper
+    fn commute() -> (Domain<&'static str, &'static str>, Action<&'static str, &'static str>) {
+        let home = Proposition::from("at home");
+        let stop = Proposition::from("at bus stop");
+        let office = Proposition::from("at office");
+        let (not_home, not_stop) = (home.negate(), stop.negate());
+        let taxi = Action::new("taxi", fragset!{[&home]}, fragset!{[&office, &not_home]}).set_cost(10).clone();
+        let walk = Action::new("walk", fragset!{[&home]}, fragset!{[&stop, &not_home]});
+        let bus = Action::new("bus", fragset!{[&stop]}, fragset!{[&office, &not_stop]}).set_cost(2).clone();
+        let domain = GraphPlan::create_domain(fragset!{[&home]}, fragset!{[&office]}, fragset!{[&taxi, &walk, &bus]});
+        (domain, taxi)
+    }
+
+    #[test]
+    fn search_cheapest_finds_longer_cheaper_plans() {
+        let (domain, taxi) = commute();
+        let plan = GraphPlan::from_domain(&domain).search::<SimpleSolver>().unwrap();
+        assert_eq!(plan, vec![fragset!{[taxi]}]);
+        assert_eq!((plan.cost(), plan.makespan()), (10, 1));
+
+        let cheapest = GraphPlan::from_domain(&domain).search_cheapest::<SimpleSolver>().unwrap();
+        let steps: Vec<Vec<&str>> = cheapest.solution.iter()
+            .map(|step| step.iter().map(|a| *a.get_action().unwrap()).collect())
+            .collect();
+        assert_eq!(steps, vec![vec!["walk"], vec!["bus"]]);
+        assert_eq!((cheapest.cost, cheapest.makespan), (3, 2));
+    }
+
+    #[test]
+    fn search_cheapest_compares_plans_at_the_same_depth() {
+        let p1 = Proposition::from("caffeinated");
+        let a1 = Action::new("buy latte", fragset!{}, fragset!{[&p1]}).set_cost(5).clone();
+        let a2 = Action::new("brew coffee", fragset!{}, fragset!{[&p1]});
+        let domain = GraphPlan::create_domain(fragset!{}, fragset!{[&p1]}, fragset!{[&a1, &a2]});
+
+        let cheapest = GraphPlan::from_domain(&domain).search_cheapest::<SimpleSolver>().unwrap();
+        assert_eq!(cheapest.solution, vec![fragset!{[a2]}]);
+        assert_eq!((cheapest.cost, cheapest.makespan), (1, 1));
+    }
+
+    #[test]
+    fn search_cheapest_stays_within_max_depth() {
+        let (domain, taxi) = commute();
+        let mut pg = GraphPlan::from_domain(&domain);
+        pg.set_limits(SearchLimits::new().max_depth(1).clone());
+        let cheapest = pg.search_cheapest::<SimpleSolver>().unwrap();
+        assert_eq!(cheapest.solution, vec![fragset!{[taxi]}]);
+        assert_eq!(pg.plangraph().depth(), 1);
+    }
+
+    /// Coffee or tea and a scone or a muffin, in any order
+    fn breakfast() -> Domain<&'static str, &'static str> {
+        let caffeinated = Proposition::from("caffeinated");
+        let full = Proposition::from("full");
+        let coffee = Action::new("coffee", fragset!{}, fragset!{[&caffeinated]});
+        let tea = Action::new("tea", fragset!{}, fragset!{[&caffeinated]});
+        let scone = Action::new("scone", fragset!{}, fragset!{[&full]});
+        let muffin = Action::new("muffin", fragset!{}, fragset!{[&full]});
+        GraphPlan::create_domain(
+            fragset!{},
+            fragset!{[&caffeinated, &full]},
+            fragset!{[&coffee, &tea, &scone, &muffin]},
+        )
+    }
+
+    #[test]
+    fn plans_yields_shortest_plans_first() {
+        let domain = breakfast();
+        let mut pg = GraphPlan::from_domain(&domain);
+        let plans: Vec<Solution<_, _>> = pg.plans::<SimpleSolver>()
+            .take(4)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let steps: BTreeSet<Vec<&str>> = plans.iter()
+            .map(|plan| {
+                assert_eq!(plan.len(), 1);
+                let mut names: Vec<&str> = plan[0].iter().map(|a| *a.get_action().unwrap()).collect();
+                names.sort();
+                names
+            })
+            .collect();
+        let expected = btreeset!{
+            vec!["coffee", "muffin"], vec!["coffee", "scone"],
+            vec!["muffin", "tea"], vec!["scone", "tea"]
+        };
+        assert_eq!(steps, expected);
+    }
+
+    #[test]
+    fn plans_skips_plans_that_only_wait() {
+        let domain = breakfast();
+        let mut pg = GraphPlan::from_domain(&domain);
+        let plans: Vec<Solution<_, _>> = pg.plans::<SimpleSolver>()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        // Every pair at once or one after the other, no plan takes
+        // three steps without one that waits
+        assert_eq!(plans.len(), 4 + 8);
+        assert!(plans[4..].iter().all(|plan| plan.len() == 2 && plan.iter().all(|step| step.len() == 1)));
+        for plan in &plans {
+            assert_eq!(crate::validate_plan(&domain, plan), Ok(()));
+        }
+        assert_eq!(pg.plangraph().depth(), 3);
+    }
+
+    #[test]
+    fn plans_stops_at_max_depth() {
+        let domain = breakfast();
+        let mut pg = GraphPlan::from_domain(&domain);
+        pg.set_limits(SearchLimits::new().max_depth(1).clone());
+        assert_eq!(pg.plans::<SimpleSolver>().count(), 4);
+    }
+
+    #[test]
+    fn plans_searches_every_depth_up_to_max_depth() {
+        // The chain through x and y is a longer way to g and has no
+        // plans at depth 2
+        let (g, x, y) = (Proposition::from("g"), Proposition::from("x"), Proposition::from("y"));
+        let a = Action::new("A", fragset!{}, fragset!{[&g]});
+        let b = Action::new("B", fragset!{}, fragset!{[&x]});
+        let c = Action::new("C", fragset!{[&x]}, fragset!{[&y]});
+        let d = Action::new("D", fragset!{[&y]}, fragset!{[&g]});
+        let domain = GraphPlan::create_domain(fragset!{}, fragset!{[&g]}, fragset!{[&a, &b, &c, &d]});
+
+        let mut pg = GraphPlan::from_domain(&domain);
+        pg.set_limits(SearchLimits::new().max_depth(3).clone());
+        let plans: Vec<Solution<_, _>> = pg.plans::<SimpleSolver>()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(plans, vec![
+            vec![fragset!{[a]}],
+            vec![fragset!{[b]}, fragset!{[c]}, fragset!{[d]}],
+        ]);
+        assert_eq!(pg.plangraph().depth(), 3);
+
+        // Without a maximum depth it stops after depth 2
+        let mut pg = GraphPlan::from_domain(&domain);
+        assert_eq!(pg.plans::<SimpleSolver>().count(), 1);
+        assert_eq!(pg.plangraph().depth(), 2);
+    }
+
+    #[test]
+    fn plans_ends_with_the_error() {
+        let p1 = Proposition::from("caffeinated");
+        let domain: Domain<&str, &str> = GraphPlan::create_domain(fragset!{}, fragset!{[&p1]}, fragset!{});
+        let mut pg = GraphPlan::from_domain(&domain);
+        let results: Vec<_> = pg.plans::<SimpleSolver>().collect();
+        assert_eq!(results, vec![Err(Error::GoalsUnreachable)]);
+    }
+}
+
+#[cfg(test)]
+mod domain_test {
+    use crate::{Domain, DomainOptions, GraphPlan, Solution};
+    use crate::proposition::Proposition;
+    use crate::action::Action;
+    use crate::solver::SimpleSolver;
+    use crate::fixtures::morning;
+
+    #[test]
+    fn domain_outlives_its_inputs() {
+        let solution: Solution<&str, &str> = {
+            let mut pg = GraphPlan::from_domain(&morning());
+            pg.search::<SimpleSolver>().unwrap()
+        };
+        let steps: Vec<_> = solution.iter()
+            .flat_map(|step| step.iter().map(|a| a.get_action().unwrap()))
+            .collect();
+        assert_eq!(steps, vec![&"coffee", &"walk dog"]);
+    }
+
+    #[test]
+    fn solves_with_conditional_effects() {
+        let truck_at_depot = Proposition::from("truck at depot");
+        let truck_at_shop = Proposition::from("truck at shop");
+        let box_at_depot = Proposition::from("box at depot");
+        let box_at_shop = Proposition::from("box at shop");
+        let box_loaded = Proposition::from("box loaded");
+        let (not_truck_at_depot, not_box_at_depot) = (truck_at_depot.negate(), box_at_depot.negate());
+
+        let load = Action::new(
+            "load",
+            fragset!{[&truck_at_depot, &box_at_depot]},
+            fragset!{[&box_loaded, &not_box_at_depot]},
+        );
+        // Driving moves the box too if it's loaded
+        let drive = Action::new(
+            "drive",
+            fragset!{[&truck_at_depot]},
+            fragset!{[&truck_at_shop, &not_truck_at_depot]},
+        ).when(fragset!{[&box_loaded]}, fragset!{[&box_at_shop]}).clone();
+
+        let domain = GraphPlan::create_domain_with(
+            fragset!{[&truck_at_depot, &box_at_depot]},
+            fragset!{[&box_at_shop]},
+            fragset!{[&load, &drive]},
+            DomainOptions::new().closed_world(true),
+        );
+        let drive_idx = domain.action_index(&drive).unwrap();
+        assert_eq!(domain.actions().iter().filter(|a| **a == drive).count(), 2);
+        assert!(domain.actions_always_mutex(drive_idx, crate::ActionIndex(drive_idx.0 + 1)));
+
+        let mut pg = GraphPlan::from_domain(&domain);
+        let plan = GraphPlan::format_plan(pg.search::<SimpleSolver>().unwrap());
+        assert_eq!(plan, vec![fragset!{[load]}, fragset!{[drive.clone()]}]);
+        assert_eq!(crate::validate_plan(&domain, &plan), Ok(()));
+
+        // The box stays behind when driving first
+        let plan = vec![fragset!{[drive]}];
+        assert!(crate::validate_plan(&domain, &plan).is_err());
+    }
+
+    #[test]
+    fn conditions_missing_from_the_initial_state_are_false() {
+        let hungry = Proposition::from("hungry");
+        let raining = Proposition::from("raining");
+        let walked = Proposition::from("walked");
+        let wet = Proposition::from("wet");
+        let walk = Action::new("walk", fragset!{}, fragset!{[&walked]})
+            .when(fragset!{[&raining]}, fragset!{[&wet]})
+            .clone();
+
+        // Neither raining nor ¬raining is in the initial state, which
+        // is created without any options
+        let domain = GraphPlan::create_domain(fragset!{[&hungry]}, fragset!{[&walked]}, fragset!{[&walk]});
+        let not_raining = domain.proposition_index(&raining.negate()).unwrap();
+        assert!(domain.initial_props().contains(&not_raining));
+
+        let mut pg = GraphPlan::from_domain(&domain);
+        let plan = GraphPlan::format_plan(pg.search::<SimpleSolver>().unwrap());
+        assert_eq!(plan, vec![fragset!{[walk]}]);
+        assert_eq!(crate::validate_plan(&domain, &plan), Ok(()));
+    }
+
+    /// Going to work needs ¬at work which isn't in the initial state
+    fn commute(options: &DomainOptions) -> Domain<&'static str, &'static str> {
+        let p1 = Proposition::from("tired");
+        let not_p1 = p1.negate();
+        let p2 = Proposition::from("at work");
+        let not_p2 = p2.negate();
+        let a1 = Action::new("coffee", fragset!{[&p1]}, fragset!{[&not_p1]});
+        let a2 = Action::new("go to work", fragset!{[&not_p1, &not_p2]}, fragset!{[&p2]});
+        GraphPlan::create_domain_with(fragset!{[&p1]}, fragset!{[&p2]}, fragset!{[&a1, &a2]}, options)
+    }
+
+    #[test]
+    fn closed_world_completes_initial_state() {
+        let domain = commute(&DomainOptions::default());
+        let mut pg = GraphPlan::from_domain(&domain);
+        assert!(pg.search::<SimpleSolver>().is_err());
+
+        let domain = commute(DomainOptions::new().closed_world(true));
+        let not_at_work = domain.proposition_index(&Proposition::from("at work").negate()).unwrap();
+        assert!(domain.initial_props().contains(&not_at_work));
+        // Tired is initially true so it stays true
+        let not_tired = domain.proposition_index(&Proposition::from("tired").negate()).unwrap();
+        assert!(!domain.initial_props().contains(&not_tired));
+
+        let mut pg = GraphPlan::from_domain(&domain);
+        assert_eq!(pg.search::<SimpleSolver>().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn compiles_negative_preconditions() {
+        let p1 = Proposition::from("tired");
+        let p2 = Proposition::from("at work");
+        let not_p2 = p2.negate();
+        let p3 = Proposition::from("dressed");
+        let a1 = Action::new("go to work", fragset!{[&not_p2]}, fragset!{[&p2, &p3]});
+        let domain = GraphPlan::create_domain_with(
+            fragset!{[&p1]},
+            fragset!{[&p2]},
+            fragset!{[&a1]},
+            DomainOptions::new().compile_negative_preconditions(true),
+        );
+
+        // Only the negated precondition is assumed false
+        let initial: Vec<_> = domain.initial_props().iter()
+            .map(|p| domain.proposition(*p).clone())
+            .collect();
+        assert_eq!(initial, vec![not_p2, p1]);
+
+        let mut pg = GraphPlan::from_domain(&domain);
+        assert_eq!(pg.search::<SimpleSolver>().unwrap().len(), 1);
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod serde_test {
+    use crate::{Action, Domain, GraphPlan, PlanGraph, Proposition, SimpleSolver, Solution};
+    use crate::fixtures::morning_as;
+
+    #[test]
+    fn domain_round_trips() {
+        let domain = morning_as::<String>();
+        let json = serde_json::to_string(&domain).unwrap();
+        let loaded: Domain<String, String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(domain, loaded);
+    }
+
+    #[test]
+    fn conditional_effects_round_trip() {
+        let p1 = Proposition::from(String::from("dark"));
+        let p2 = Proposition::from(String::from("lamp plugged in"));
+        let p3 = Proposition::from(String::from("light"));
+        let a1 = Action::new(String::from("flip switch"), fragset!{[&p1]}, fragset!{})
+            .when(fragset!{[&p2]}, fragset!{[&p3]})
+            .clone();
+        let domain = GraphPlan::create_domain(fragset!{[&p1, &p2]}, fragset!{[&p3]}, fragset!{[&a1]});
+
+        let json = serde_json::to_string(&domain).unwrap();
+        let loaded: Domain<String, String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(domain, loaded);
+        let idx = loaded.action_index(&a1).unwrap();
+        assert_eq!(loaded.action(idx).conditional_effects, a1.conditional_effects);
+    }
+
+    #[test]
+    fn solution_round_trips() {
+        let mut pg = GraphPlan::from_domain(&morning_as::<String>());
+        let solution = pg.search::<SimpleSolver>().unwrap();
+        let json = serde_json::to_string(&solution).unwrap();
+        let loaded: Solution<String, String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(solution, loaded);
+    }
+
+    #[test]
+    fn resumes_from_plangraph_snapshot() {
+        let mut pg = PlanGraph::from_domain(morning_as::<String>());
+        pg.extend().unwrap();
+        let json = serde_json::to_string(&pg).unwrap();
+
+        let loaded: PlanGraph<String, String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.layers, pg.layers);
+        assert_eq!(loaded.mutex_props, pg.mutex_props);
+        assert_eq!(loaded.mutex_actions, pg.mutex_actions);
+
+        let mut resumed = GraphPlan::from_plangraph(loaded);
+        let expected = GraphPlan::from_domain(&morning_as::<String>()).search::<SimpleSolver>().unwrap();
+        assert_eq!(resumed.search::<SimpleSolver>().unwrap(), expected);
+        assert_eq!(resumed.plangraph().depth(), 2);
     }
 }
@@ -0,0 +1,226 @@
+use std::cell::Cell;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use crate::error::Error;
+
+
+/// The limit that stopped a search
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Limit {
+    Deadline,
+    Depth,
+    Nodes,
+    Backtracks,
+    Cancelled,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Deadline => write!(f, "the deadline passed"),
+            Limit::Depth => write!(f, "the plangraph reached its maximum depth"),
+            Limit::Nodes => write!(f, "the maximum number of nodes were expanded"),
+            Limit::Backtracks => write!(f, "the maximum number of backtracks were made"),
+            Limit::Cancelled => write!(f, "the search was cancelled"),
+        }
+    }
+}
+
+/// Limits on how long a search may run. Every limit is off by
+/// default. When one is hit the search returns
+/// `Error::LimitReached` saying which.
+#[derive(Debug, Clone, Default)]
+pub struct SearchLimits {
+    /// Wall clock time to stop at
+    pub deadline: Option<Instant>,
+    /// Maximum depth the plangraph is extended to
+    pub max_depth: Option<usize>,
+    /// Maximum number of search nodes the solvers expand
+    pub max_nodes: Option<usize>,
+    /// Maximum number of times the solvers backtrack
+    pub max_backtracks: Option<usize>,
+    /// Set to true from another thread to stop the search
+    pub cancel: Option<Arc<AtomicBool>>,
+}
+
+impl SearchLimits {
+    pub fn new() -> Self {
+        SearchLimits::default()
+    }
+
+    pub fn deadline(&mut self, deadline: Instant) -> &mut Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Sets the deadline to `timeout` from now
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.deadline(Instant::now() + timeout)
+    }
+
+    pub fn max_depth(&mut self, depth: usize) -> &mut Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    pub fn max_nodes(&mut self, nodes: usize) -> &mut Self {
+        self.max_nodes = Some(nodes);
+        self
+    }
+
+    pub fn max_backtracks(&mut self, backtracks: usize) -> &mut Self {
+        self.max_backtracks = Some(backtracks);
+        self
+    }
+
+    pub fn cancel(&mut self, cancel: Arc<AtomicBool>) -> &mut Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Returns an error if the search was cancelled or the deadline
+    /// passed
+    pub fn check(&self) -> Result<(), Error> {
+        if matches!(&self.cancel, Some(c) if c.load(Ordering::Relaxed)) {
+            return Err(Error::LimitReached(Limit::Cancelled));
+        }
+        if matches!(self.deadline, Some(d) if Instant::now() >= d) {
+            return Err(Error::LimitReached(Limit::Deadline));
+        }
+        Ok(())
+    }
+
+    /// Returns an error if a plangraph of this depth may not be
+    /// extended further
+    pub fn check_depth(&self, depth: usize) -> Result<(), Error> {
+        if matches!(self.max_depth, Some(max) if depth >= max) {
+            return Err(Error::LimitReached(Limit::Depth));
+        }
+        Ok(())
+    }
+}
+
+/// Counts the nodes expanded and backtracks made by a search and
+/// checks them against its limits. Counts are kept in cells so the
+/// solver can share the budget with the iterators on its stack. Once
+/// a limit is hit every later check reports the same limit.
+#[derive(Debug, Default)]
+pub struct SearchBudget {
+    limits: SearchLimits,
+    nodes: Cell<usize>,
+    backtracks: Cell<usize>,
+    reached: Cell<Option<Limit>>,
+}
+
+impl SearchBudget {
+    pub fn new(limits: SearchLimits) -> Self {
+        SearchBudget { limits, ..SearchBudget::default() }
+    }
+
+    pub fn limits(&self) -> &SearchLimits {
+        &self.limits
+    }
+
+    pub fn nodes(&self) -> usize {
+        self.nodes.get()
+    }
+
+    pub fn backtracks(&self) -> usize {
+        self.backtracks.get()
+    }
+
+    fn reach(&self, limit: Limit) -> Result<(), Error> {
+        self.reached.set(Some(limit));
+        Err(Error::LimitReached(limit))
+    }
+
+    /// Returns an error if any limit has been hit
+    pub fn check(&self) -> Result<(), Error> {
+        if let Some(limit) = self.reached.get() {
+            return Err(Error::LimitReached(limit));
+        }
+        if let Err(Error::LimitReached(limit)) = self.limits.check() {
+            return self.reach(limit);
+        }
+        if matches!(self.limits.max_nodes, Some(max) if self.nodes() > max) {
+            return self.reach(Limit::Nodes);
+        }
+        if matches!(self.limits.max_backtracks, Some(max) if self.backtracks() > max) {
+            return self.reach(Limit::Backtracks);
+        }
+        Ok(())
+    }
+
+    /// Counts an expanded node then checks the limits
+    pub fn expand(&self) -> Result<(), Error> {
+        self.nodes.set(self.nodes() + 1);
+        self.check()
+    }
+
+    /// Counts a backtrack then checks the limits
+    pub fn backtrack(&self) -> Result<(), Error> {
+        self.backtracks.set(self.backtracks() + 1);
+        self.check()
+    }
+
+    /// Counts the nodes and backtracks of a search that ran with
+    /// another budget, e.g. on another thread
+    pub(crate) fn charge(&self, other: &SearchBudget) {
+        self.nodes.set(self.nodes() + other.nodes());
+        self.backtracks.set(self.backtracks() + other.backtracks());
+    }
+
+    /// Returns the limits with the nodes and backtracks left
+    pub(crate) fn remaining(&self) -> SearchLimits {
+        let mut limits = self.limits.clone();
+        limits.max_nodes = limits.max_nodes.map(|max| max.saturating_sub(self.nodes()));
+        limits.max_backtracks = limits.max_backtracks.map(|max| max.saturating_sub(self.backtracks()));
+        limits
+    }
+}
+
+#[cfg(test)]
+mod limits_test {
+    use super::*;
+
+    #[test]
+    fn unlimited_by_default() {
+        let budget = SearchBudget::default();
+        for _ in 0..100 {
+            assert_eq!(budget.expand(), Ok(()));
+            assert_eq!(budget.backtrack(), Ok(()));
+        }
+        assert_eq!(budget.nodes(), 100);
+        assert_eq!(budget.backtracks(), 100);
+        assert_eq!(budget.limits().check_depth(100), Ok(()));
+    }
+
+    #[test]
+    fn reports_the_limit_hit() {
+        let budget = SearchBudget::new(SearchLimits::new().max_nodes(1).max_backtracks(2).clone());
+        assert_eq!(budget.expand(), Ok(()));
+        assert_eq!(budget.expand(), Err(Error::LimitReached(Limit::Nodes)));
+        // The first limit hit is reported from then on
+        assert_eq!(budget.backtrack(), Err(Error::LimitReached(Limit::Nodes)));
+
+        let limits = SearchLimits::new().max_depth(2).clone();
+        assert_eq!(limits.check_depth(1), Ok(()));
+        assert_eq!(limits.check_depth(2), Err(Error::LimitReached(Limit::Depth)));
+
+        let limits = SearchLimits::new().timeout(Duration::from_secs(0)).clone();
+        assert_eq!(limits.check(), Err(Error::LimitReached(Limit::Deadline)));
+    }
+
+    #[test]
+    fn cancels_from_another_thread() {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let budget = SearchBudget::new(SearchLimits::new().cancel(cancel.clone()).clone());
+        assert_eq!(budget.check(), Ok(()));
+
+        let handle = std::thread::spawn(move || cancel.store(true, Ordering::Relaxed));
+        handle.join().unwrap();
+        assert_eq!(budget.check(), Err(Error::LimitReached(Limit::Cancelled)));
+    }
+}
@@ -1,21 +1,3 @@
-#[macro_export]
-/// Create a **HashSet** from a list of elements. Implementation
-/// copied from the maplit library https://github.com/bluss/maplit
-///
-/// ## Example
-///
-/// ```
-/// #[macro_use] extern crate graphplan;
-/// # fn main() {
-///
-/// let set = fragset!{["a", "b"]};
-/// assert!(set.contains("a"));
-/// assert!(set.contains("b"));
-/// assert!(!set.contains("c"));
-/// # }
-/// ```
-
-
 // macro_rules! hashset {
 //     (@single $($x:tt)*) => (());
 //     (@count $($rest:expr),*) => (<[()]>::len(&[$(fragset!(@single $rest)),*]));
@@ -33,6 +15,21 @@
 //     };
 // }
 
+/// Create a **HashSet** from a list of elements. Implementation
+/// copied from the maplit library https://github.com/bluss/maplit
+///
+/// ## Example
+///
+/// ```
+/// #[macro_use] extern crate graphplan;
+/// # fn main() {
+///
+/// let set = fragset!{["a", "b"]};
+/// assert!(set.contains("a"));
+/// assert!(set.contains("b"));
+/// assert!(!set.contains("c"));
+/// # }
+/// ```
 #[macro_export]
 macro_rules! fragset {
     (@single $($x:tt)*) => (());
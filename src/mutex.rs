@@ -0,0 +1,244 @@
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+use log::debug;
+use crate::bitset::{IndexSet, MutexMatrix};
+use crate::domain::{ActionIndex, Domain, PropositionIndex};
+use crate::pairset::PairSet;
+
+
+/// Mutexes that hold in every layer of the plangraph, found once
+/// from the structure of the domain:
+///
+/// - a proposition and its negation
+/// - invariants such as an object being in exactly one place, i.e.
+///   pairs of propositions that are not both true initially and
+///   that no action can make true together
+/// - actions with inconsistent effects, that interfere with each
+///   other, or that require propositions that are always mutex
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StaticMutexes {
+    pub(crate) props: MutexMatrix<PropositionIndex>,
+    pub(crate) actions: MutexMatrix<ActionIndex>,
+}
+
+impl StaticMutexes {
+    pub(crate) fn new<ActionId, PropositionId>(domain: &Domain<ActionId, PropositionId>) -> Self
+    where
+        ActionId: Debug + Hash + Ord + Clone,
+        PropositionId: Debug + Display + Hash + Ord + Clone,
+    {
+        let mut props = invariants(domain);
+        for (idx, _) in domain.propositions().iter().enumerate() {
+            let p = PropositionIndex(idx);
+            if let Some(not_p) = domain.negation(p) {
+                props.insert(PairSet(p, not_p));
+            }
+        }
+        let actions = inconsistent_actions(domain, &props);
+        debug!("Found {} static proposition mutexes and {} static action mutexes",
+               props.len(), actions.len());
+        StaticMutexes { props, actions }
+    }
+}
+
+/// Returns the pairs of propositions that are never true together.
+///
+/// Starts from every pair that isn't true initially and removes the
+/// pairs an action can make true together until nothing changes. An
+/// action that adds p keeps q false if it deletes q, or if it
+/// requires a proposition that is still mutex with q and doesn't add
+/// q. Actions with mutex preconditions can never be applied and are
+/// ignored.
+fn invariants<ActionId, PropositionId>(domain: &Domain<ActionId, PropositionId>) -> MutexMatrix<PropositionIndex>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    let num_props = domain.propositions().len();
+    let all: IndexSet<PropositionIndex> = (0..num_props).map(PropositionIndex).collect();
+    let initial: IndexSet<PropositionIndex> = domain.initial_props().iter().collect();
+
+    let mut candidates: Vec<IndexSet<PropositionIndex>> = (0..num_props)
+        .map(|idx| {
+            let p = PropositionIndex(idx);
+            let mut row = all.clone();
+            row.remove(p);
+            if initial.contains(p) {
+                for q in &initial {
+                    row.remove(q);
+                }
+            }
+            row
+        })
+        .collect();
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for a in domain.action_indices() {
+            let reqs = domain.req_set(a);
+            if reqs.iter().any(|r| candidates[r.0].intersects(reqs)) {
+                continue;
+            }
+
+            // Propositions that are false after applying the action
+            let mut kept_false = domain.negated_effects(a).clone();
+            for r in reqs {
+                kept_false.union_with(&candidates[r.0]);
+            }
+            for p in domain.effect_set(a) {
+                kept_false.remove(p);
+            }
+
+            for p in domain.effect_set(a) {
+                if candidates[p.0].is_subset(&kept_false) {
+                    continue;
+                }
+                let true_together: Vec<PropositionIndex> = candidates[p.0].iter()
+                    .filter(|q| !kept_false.contains(*q))
+                    .collect();
+                for q in true_together {
+                    candidates[p.0].remove(q);
+                    candidates[q.0].remove(p);
+                }
+                changed = true;
+            }
+        }
+    }
+
+    let mut mutexes = MutexMatrix::new();
+    for (idx, row) in candidates.iter().enumerate() {
+        let p = PropositionIndex(idx);
+        for q in row.iter().filter(|q| p < *q) {
+            mutexes.insert(PairSet(p, q));
+        }
+    }
+    mutexes
+}
+
+/// Returns the pairs of actions that are mutex in every layer
+fn inconsistent_actions<ActionId, PropositionId>(domain: &Domain<ActionId, PropositionId>,
+                                                 mutex_props: &MutexMatrix<PropositionIndex>)
+                                                 -> MutexMatrix<ActionIndex>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    let mut mutexes = MutexMatrix::new();
+
+    for a1 in domain.action_indices() {
+        let mut conflicts = IndexSet::new();
+
+        // Inconsistent effects: The effect of one action is the
+        // negation of another
+        // Interference: One action deletes the precondition of
+        // another action (they can't be done in parallel then)
+        for q in domain.negated_effects(a1) {
+            conflicts.union_with(domain.adders(q));
+            conflicts.union_with(domain.consumers(q));
+        }
+
+        // Interference the other way around and conflicting
+        // requirements: Actions have preconditions that are negations
+        // of each other
+        for q in domain.negated_reqs(a1) {
+            conflicts.union_with(domain.adders(q));
+            conflicts.union_with(domain.consumers(q));
+        }
+
+        // Competing needs: Action has a precondition that is always
+        // mutex with a precondition of the other action
+        let mut mutex_reqs = IndexSet::new();
+        for p in domain.req_set(a1) {
+            if let Some(row) = mutex_props.row(p) {
+                mutex_reqs.union_with(row);
+            }
+        }
+        for q in &mutex_reqs {
+            conflicts.union_with(domain.consumers(q));
+        }
+
+        // Variants of an action with conditional effects are the
+        // same action so only one of them can be taken
+        for a2 in domain.sibling_variants(a1) {
+            conflicts.insert(a2);
+        }
+
+        // Every condition is symmetric so each pair only needs to be
+        // recorded once
+        for a2 in conflicts.iter().filter(|a2| *a2 > a1) {
+            mutexes.insert(PairSet(a1, a2));
+        }
+    }
+
+    mutexes
+}
+
+#[cfg(test)]
+mod static_mutex_test {
+    use crate::action::Action;
+    use crate::domain::Domain;
+    use crate::proposition::Proposition;
+    use crate::GraphPlan;
+
+    /// A robot that moves between three rooms
+    fn rooms() -> Domain<String, String> {
+        let at = |room: &str| Proposition::from(format!("at {}", room));
+        let rooms = ["kitchen", "hall", "garden"];
+        let mut moves = Vec::new();
+        for from in &rooms {
+            for to in rooms.iter().filter(|to| *to != from) {
+                let (at_from, at_to) = (at(from), at(to));
+                let not_from = at_from.negate();
+                moves.push(Action::new(
+                    format!("move {} {}", from, to),
+                    fragset!{[&at_from]},
+                    fragset!{[&at_to, &not_from]},
+                ));
+            }
+        }
+        let (kitchen, garden) = (at("kitchen"), at("garden"));
+        GraphPlan::create_domain(
+            fragset!{[&kitchen]},
+            fragset!{[&garden]},
+            moves.iter().collect(),
+        )
+    }
+
+    #[test]
+    fn finds_exactly_one_location() {
+        let domain = rooms();
+        let at = |room: &str| domain.proposition_index(&Proposition::from(format!("at {}", room))).unwrap();
+        let not_at = |room: &str| domain.proposition_index(&Proposition::from(format!("at {}", room)).negate()).unwrap();
+
+        assert!(domain.props_always_mutex(at("kitchen"), at("hall")));
+        assert!(domain.props_always_mutex(at("hall"), at("garden")));
+        assert!(domain.props_always_mutex(at("kitchen"), not_at("kitchen")));
+        // Leaving the kitchen for the hall makes both true
+        assert!(!domain.props_always_mutex(at("hall"), not_at("kitchen")));
+    }
+
+    #[test]
+    fn finds_always_inconsistent_actions() {
+        let domain = rooms();
+        let action = |name: &str| domain.action_index(&Action::new(String::from(name), fragset!{}, fragset!{})).unwrap();
+
+        // Can't leave the kitchen twice or be in two rooms to leave
+        assert!(domain.actions_always_mutex(action("move kitchen hall"), action("move kitchen garden")));
+        assert!(domain.actions_always_mutex(action("move kitchen hall"), action("move hall garden")));
+    }
+
+    #[test]
+    fn keeps_pairs_that_can_hold_together() {
+        let p1 = Proposition::from("coffee");
+        let p2 = Proposition::from("toast");
+        let a1 = Action::new("make coffee", fragset!{}, fragset!{[&p1]});
+        let a2 = Action::new("make toast", fragset!{}, fragset!{[&p2]});
+        let domain = GraphPlan::create_domain(fragset!{}, fragset!{[&p1, &p2]}, fragset!{[&a1, &a2]});
+        let (p1, p2) = (domain.proposition_index(&p1).unwrap(), domain.proposition_index(&p2).unwrap());
+
+        assert!(!domain.props_always_mutex(p1, p2));
+        let (a1, a2) = (domain.action_index(&a1).unwrap(), domain.action_index(&a2).unwrap());
+        assert!(!domain.actions_always_mutex(a1, a2));
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::BTreeSet;
+use std::time::Duration;
+use crate::domain::PropositionIndex;
+
+
+/// Sizes of a layer added to the plangraph and how long it took
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExtendedLayer {
+    /// Depth of the plangraph after extending it
+    pub depth: usize,
+    pub propositions: usize,
+    pub actions: usize,
+    pub mutex_props: usize,
+    pub mutex_actions: usize,
+    pub elapsed: Duration,
+}
+
+/// Callbacks made while `GraphPlan::search_with_observer` extends and
+/// searches the plangraph. Every method does nothing by default so
+/// observers only implement the ones they need. Layers are the index
+/// of the proposition layer the goals are in.
+pub trait SearchObserver {
+    fn layer_extended(&mut self, _layer: &ExtendedLayer) {}
+
+    /// A goal set was pushed onto the solver's stack
+    fn goals_pushed(&mut self, _layer: usize, _goals: &BTreeSet<PropositionIndex>) {}
+
+    /// A goal set was popped from the solver's stack to be expanded
+    fn goals_popped(&mut self, _layer: usize, _goals: &BTreeSet<PropositionIndex>) {}
+
+    /// A goal set was popped from the solver's stack but skipped
+    /// because it contains one already known to be unsolvable
+    fn memo_hit(&mut self, _layer: usize, _goals: &BTreeSet<PropositionIndex>) {}
+
+    /// A goal set was recorded as unsolvable. This may be only the
+    /// part of the goal set that caused it to fail.
+    fn goals_memoized(&mut self, _layer: usize, _goals: &BTreeSet<PropositionIndex>) {}
+
+    /// The solver gave up on the goals at this layer and went back
+    /// to the layer after it
+    fn backtracked(&mut self, _layer: usize) {}
+
+    /// A plan with this many steps was found
+    fn solution_found(&mut self, _steps: usize) {}
+}
+
+/// Observes nothing
+impl SearchObserver for () {}
+
+/// Summary of a search returned by `GraphPlan::search_with_stats`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchStats {
+    /// Number of times the plangraph was extended
+    pub layers_extended: usize,
+    /// Goal sets popped from the solver's stack and expanded
+    pub nodes_expanded: usize,
+    /// Goal sets skipped because they were known to be unsolvable
+    pub memo_hits: usize,
+    /// Goal sets recorded as unsolvable
+    pub memoized: usize,
+    pub backtracks: usize,
+    pub max_stack_depth: usize,
+    /// Time spent extending the plangraph
+    pub extend_time: Duration,
+    /// Time spent searching the plangraph and checking whether to
+    /// keep going
+    pub search_time: Duration,
+    /// Steps in the plan if one was found
+    pub solution_steps: Option<usize>,
+    stack_depth: usize,
+}
+
+impl SearchStats {
+    pub fn new() -> Self {
+        SearchStats::default()
+    }
+}
+
+impl SearchObserver for SearchStats {
+    fn layer_extended(&mut self, layer: &ExtendedLayer) {
+        self.layers_extended += 1;
+        self.extend_time += layer.elapsed;
+    }
+
+    fn goals_pushed(&mut self, _layer: usize, _goals: &BTreeSet<PropositionIndex>) {
+        self.stack_depth += 1;
+        self.max_stack_depth = self.max_stack_depth.max(self.stack_depth);
+    }
+
+    fn goals_popped(&mut self, _layer: usize, _goals: &BTreeSet<PropositionIndex>) {
+        self.stack_depth = self.stack_depth.saturating_sub(1);
+        self.nodes_expanded += 1;
+    }
+
+    fn memo_hit(&mut self, _layer: usize, _goals: &BTreeSet<PropositionIndex>) {
+        self.stack_depth = self.stack_depth.saturating_sub(1);
+        self.memo_hits += 1;
+    }
+
+    fn goals_memoized(&mut self, _layer: usize, _goals: &BTreeSet<PropositionIndex>) {
+        self.memoized += 1;
+    }
+
+    fn backtracked(&mut self, _layer: usize) {
+        self.backtracks += 1;
+    }
+
+    fn solution_found(&mut self, steps: usize) {
+        self.solution_steps = Some(steps);
+    }
+}
+
+#[cfg(test)]
+mod observer_test {
+    use super::*;
+    use crate::action::Action;
+    use crate::limits::SearchBudget;
+    use crate::plangraph::PlanGraph;
+    use crate::proposition::Proposition;
+    use crate::solver::{GraphPlanSolver, PlanCursor, SimpleSolver};
+    use crate::GraphPlan;
+
+    #[test]
+    fn stats_follow_the_stack_across_plans() {
+        // Coffee is bought or brewed then sweetened with sugar or
+        // honey
+        let coffee = Proposition::from("coffee");
+        let sweet = Proposition::from("sweet coffee");
+        let buy = Action::new("buy coffee", fragset!{}, fragset!{[&coffee]});
+        let brew = Action::new("brew coffee", fragset!{}, fragset!{[&coffee]});
+        let sugar = Action::new("add sugar", fragset!{[&coffee]}, fragset!{[&sweet]});
+        let honey = Action::new("add honey", fragset!{[&coffee]}, fragset!{[&sweet]});
+        let domain = GraphPlan::create_domain(fragset!{}, fragset!{[&sweet]}, fragset!{[&buy, &brew, &sugar, &honey]});
+        let mut pg = PlanGraph::from_domain(domain);
+        pg.extend().unwrap();
+        pg.extend().unwrap();
+
+        let mut solver = SimpleSolver::default();
+        let mut stats = SearchStats::new();
+        let mut cursor = PlanCursor::new();
+        let budget = SearchBudget::default();
+        let mut plans = 0;
+        while solver.next_plan(&pg, &budget, &mut stats, &mut cursor).unwrap().is_some() {
+            // The goals of every layer stay on the stack to look for
+            // more plans
+            assert_eq!(stats.stack_depth, pg.depth());
+            plans += 1;
+        }
+        assert!(plans > 1);
+        assert_eq!(stats.stack_depth, 0);
+        assert_eq!(stats.max_stack_depth, pg.depth());
+    }
+}
@@ -0,0 +1,226 @@
+use std::cmp::{Ordering, Reverse};
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::Hash;
+use std::sync::Arc;
+use crate::domain::{ActionIndex, Domain, PropositionIndex};
+use crate::layer::Layer;
+use crate::plangraph::PlanGraph;
+
+
+/// Compares two goals or two actions for a custom ordering
+pub type Comparator<T> = Arc<dyn Fn(T, T) -> Ordering + Send + Sync>;
+
+/// The order `SimpleSolver` meets the goals of a goal set in
+#[derive(Clone, Default)]
+pub enum GoalOrder {
+    /// By `PropositionIndex`
+    #[default]
+    Index,
+    /// Goals that first appear in a later layer first. These are
+    /// the hardest to meet so the search fails sooner when it can't.
+    HardestFirst,
+    Custom(Comparator<PropositionIndex>),
+}
+
+/// The order `SimpleSolver` tries the actions that meet a goal in
+#[derive(Clone, Default)]
+pub enum ActionOrder {
+    /// By `ActionIndex`
+    #[default]
+    Index,
+    /// Maintenance actions first, which leaves the goal to an
+    /// earlier layer rather than adding an action
+    NoopsFirst,
+    FewestPreconditions,
+    /// Actions whose preconditions all appear in an earlier layer
+    /// first since those are easier to meet
+    EarliestPreconditions,
+    Custom(Comparator<ActionIndex>),
+}
+
+impl Debug for GoalOrder {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            GoalOrder::Index => write!(f, "Index"),
+            GoalOrder::HardestFirst => write!(f, "HardestFirst"),
+            GoalOrder::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+impl Debug for ActionOrder {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ActionOrder::Index => write!(f, "Index"),
+            ActionOrder::NoopsFirst => write!(f, "NoopsFirst"),
+            ActionOrder::FewestPreconditions => write!(f, "FewestPreconditions"),
+            ActionOrder::EarliestPreconditions => write!(f, "EarliestPreconditions"),
+            ActionOrder::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+/// How `SimpleSolver` orders goals and actions in the backward
+/// search. Ties are broken by index so a search with the same
+/// heuristics always finds the same plans in the same order.
+#[derive(Debug, Clone, Default)]
+pub struct SearchHeuristics {
+    pub goal_order: GoalOrder,
+    pub action_order: ActionOrder,
+}
+
+impl SearchHeuristics {
+    pub fn new() -> Self {
+        SearchHeuristics::default()
+    }
+
+    pub fn goal_order(&mut self, order: GoalOrder) -> &mut Self {
+        self.goal_order = order;
+        self
+    }
+
+    pub fn action_order(&mut self, order: ActionOrder) -> &mut Self {
+        self.action_order = order;
+        self
+    }
+
+    fn needs_levels(&self) -> bool {
+        matches!(self.goal_order, GoalOrder::HardestFirst)
+            || matches!(self.action_order, ActionOrder::EarliestPreconditions)
+    }
+}
+
+/// `SearchHeuristics` applied to a plangraph
+#[derive(Debug)]
+pub(crate) struct SearchOrder<'a> {
+    heuristics: &'a SearchHeuristics,
+    // The first proposition layer, counting only proposition layers,
+    // each proposition appears in
+    levels: Vec<usize>,
+}
+
+impl<'a> SearchOrder<'a> {
+    pub fn new<ActionId, PropositionId>(heuristics: &'a SearchHeuristics,
+                                        plangraph: &PlanGraph<ActionId, PropositionId>)
+                                        -> Self
+    where
+        ActionId: Debug + Hash + Ord + Clone,
+        PropositionId: Debug + Display + Hash + Ord + Clone,
+    {
+        let mut levels = Vec::new();
+        if heuristics.needs_levels() {
+            levels = vec![usize::MAX; plangraph.domain().propositions().len()];
+            let prop_layers = plangraph.layers.iter().filter_map(|layer| match layer {
+                Layer::PropositionLayer(props) => Some(props),
+                Layer::ActionLayer(_) => None,
+            });
+            for (level, props) in prop_layers.enumerate() {
+                for p in props {
+                    levels[p.0] = levels[p.0].min(level);
+                }
+            }
+        }
+        SearchOrder { heuristics, levels }
+    }
+
+    fn level(&self, p: PropositionIndex) -> usize {
+        self.levels.get(p.0).copied().unwrap_or(usize::MAX)
+    }
+
+    /// Sorts goals in place, keeping ties in their order
+    pub fn sort_goals(&self, goals: &mut [PropositionIndex]) {
+        match &self.heuristics.goal_order {
+            GoalOrder::Index => {},
+            GoalOrder::HardestFirst => goals.sort_by_key(|g| Reverse(self.level(*g))),
+            GoalOrder::Custom(cmp) => goals.sort_by(|a, b| cmp(*a, *b)),
+        }
+    }
+
+    /// Sorts actions in place, keeping ties in their order
+    pub fn sort_actions<ActionId, PropositionId>(&self,
+                                                 domain: &Domain<ActionId, PropositionId>,
+                                                 actions: &mut [ActionIndex])
+    where
+        ActionId: Debug + Hash + Ord + Clone,
+        PropositionId: Debug + Display + Hash + Ord + Clone,
+    {
+        match &self.heuristics.action_order {
+            ActionOrder::Index => {},
+            ActionOrder::NoopsFirst => actions.sort_by_key(|a| !domain.is_maintenance(*a)),
+            ActionOrder::FewestPreconditions => actions.sort_by_key(|a| domain.reqs(*a).len()),
+            ActionOrder::EarliestPreconditions => actions.sort_by_key(|a| {
+                domain.reqs(*a).iter().map(|p| self.level(*p)).max().unwrap_or(0)
+            }),
+            ActionOrder::Custom(cmp) => actions.sort_by(|a, b| cmp(*a, *b)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod ordering_test {
+    use super::*;
+    use crate::action::Action;
+    use crate::proposition::Proposition;
+    use crate::GraphPlan;
+
+    /// Boiling water takes a step before coffee can be made
+    fn coffee() -> PlanGraph<&'static str, &'static str> {
+        let water = Proposition::from("water");
+        let hot = Proposition::from("hot water");
+        let coffee = Proposition::from("coffee");
+        let biscuit = Proposition::from("biscuit");
+        let boil = Action::new("boil", fragset!{[&water]}, fragset!{[&hot]});
+        let brew = Action::new("brew", fragset!{[&hot]}, fragset!{[&coffee]});
+        let domain = GraphPlan::create_domain(
+            fragset!{[&water, &biscuit]},
+            fragset!{[&coffee, &biscuit]},
+            fragset!{[&boil, &brew]},
+        );
+        let mut pg = PlanGraph::from_domain(domain);
+        pg.extend().unwrap();
+        pg.extend().unwrap();
+        pg
+    }
+
+    #[test]
+    fn orders_hardest_goals_first() {
+        let pg = coffee();
+        let domain = pg.domain();
+        let idx = |name: &'static str| domain.proposition_index(&Proposition::from(name)).unwrap();
+        let heuristics = SearchHeuristics::new().goal_order(GoalOrder::HardestFirst).clone();
+        let order = SearchOrder::new(&heuristics, &pg);
+
+        let mut goals = vec![idx("biscuit"), idx("water"), idx("hot water"), idx("coffee")];
+        order.sort_goals(&mut goals);
+        assert_eq!(goals, vec![idx("coffee"), idx("hot water"), idx("biscuit"), idx("water")]);
+    }
+
+    #[test]
+    fn orders_actions() {
+        let pg = coffee();
+        let domain = pg.domain();
+        let action = |name: &'static str| domain.action_index(&Action::new(name, fragset!{}, fragset!{})).unwrap();
+        let noop = |name: &'static str| domain.action_index(&Action::new_maintenance(&Proposition::from(name))).unwrap();
+        let sorted = |order: ActionOrder, mut actions: Vec<ActionIndex>| {
+            let heuristics = SearchHeuristics::new().action_order(order).clone();
+            SearchOrder::new(&heuristics, &pg).sort_actions(domain, &mut actions);
+            actions
+        };
+
+        let actions = vec![action("brew"), noop("biscuit"), action("boil")];
+        assert_eq!(sorted(ActionOrder::Index, actions.clone()), actions);
+        assert_eq!(sorted(ActionOrder::NoopsFirst, actions.clone()),
+                   vec![noop("biscuit"), action("brew"), action("boil")]);
+        assert_eq!(sorted(ActionOrder::EarliestPreconditions, actions.clone()),
+                   vec![noop("biscuit"), action("boil"), action("brew")]);
+
+        let by_name: Comparator<ActionIndex> = {
+            let domain = domain.clone();
+            Arc::new(move |a, b| domain.action(a).id.cmp(&domain.action(b).id))
+        };
+        let mut expected = actions.clone();
+        expected.sort_by_key(|a| domain.action(*a).id.clone());
+        assert_eq!(sorted(ActionOrder::Custom(by_name), actions), expected);
+    }
+}
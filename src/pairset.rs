@@ -1,8 +1,9 @@
-use std::collections::HashSet;
 use std::hash::{Hash, Hasher};
-use std::iter::FromIterator;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, PartialOrd, Eq, Ord, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 /// An unordered two element tuple that such that (a, b) == (b, a)
 pub struct PairSet<T: Ord + PartialEq + Eq + Clone>(pub T, pub T);
 
@@ -62,131 +63,3 @@ mod pair_set_test {
         )
     }
 }
-
-/// Returns the unique pairs of a set of items
-pub fn pairs<T: Eq + Hash + Clone + Ord>(items: &HashSet<T>) -> HashSet<PairSet<T>> {
-    let mut accum = HashSet::new();
-    let mut sorted = Vec::from_iter(items.iter().cloned());
-    sorted.sort();
-
-    for i in sorted.iter() {
-        for j in sorted.iter() {
-            if i != j {
-                accum.insert(PairSet(i.to_owned(), j.to_owned()));
-            }
-        }
-    }
-    accum
-}
-
-/// Returns the pairs of a set of items
-pub fn pairs_from_sets<T: Eq + Hash + Clone + Ord>(
-    items1: HashSet<T>,
-    items2: HashSet<T>,
-) -> HashSet<PairSet<T>> {
-    let mut accum = HashSet::new();
-
-    let mut sorted1 = Vec::from_iter(items1.into_iter());
-    sorted1.sort();
-
-    let mut sorted2 = Vec::from_iter(items2.into_iter());
-    sorted2.sort();
-
-    for i in sorted1.iter() {
-        for j in sorted2.iter() {
-            if i != j {
-                accum.insert(PairSet(i.to_owned(), j.to_owned()));
-            }
-        }
-    }
-    accum
-}
-
-/// Returns the pairs of a set of items
-pub fn pairs_from_borrowed_sets<'p, T: Eq + Hash + Clone + Ord>(
-    items1: &HashSet<&'p T>,
-    items2: &HashSet<&'p T>,
-) -> HashSet<PairSet<&'p T>> {
-    let mut accum = HashSet::new();
-
-    let mut sorted1 = Vec::from_iter(items1.iter());
-    sorted1.sort();
-
-    let mut sorted2 = Vec::from_iter(items2.iter());
-    sorted2.sort();
-
-    for i in sorted1.iter() {
-        for j in sorted2.iter() {
-            if i != j {
-                accum.insert(PairSet(**i, **j));
-            }
-        }
-    }
-    accum
-}
-
-#[cfg(test)]
-mod pairs_test {
-    use super::*;
-    use crate::action::Action;
-    use crate::proposition::Proposition;
-
-    #[test]
-    fn yields_unique_pairs_only() {
-        let p1 = "a";
-        let p2 = "b";
-        let p3 = "c";
-        assert_eq!(
-            fragset! {[PairSet(p1.clone(), p2.clone()),
-            PairSet(p1.clone(), p3.clone()),
-            PairSet(p2.clone(), p3.clone())]},
-            pairs(&fragset! {[p1.clone(), p2.clone(), p3.clone()]})
-        );
-    }
-
-    #[test]
-    fn yields_unique_pairs_from_sets() {
-        assert_eq!(
-            pairs_from_sets(fragset! {[1, 2]}, fragset! {[3]}),
-            fragset! {[PairSet(1, 3), PairSet(2, 3)]}
-        );
-
-        assert_eq!(
-            pairs_from_sets(fragset! {[1]}, fragset! {[1, 2]}),
-            fragset! {[PairSet(1, 2)]}
-        );
-
-        assert_eq!(pairs_from_sets(fragset! {}, fragset! {[1, 2]}), fragset! {});
-    }
-
-    #[test]
-    fn yields_unique_pairs_from_sets_of_actions() {
-        let p1 = Proposition::from("tired");
-        let not_p1 = p1.negate();
-        let p2 = Proposition::from("dog needs to pee");
-        let not_p2 = p2.negate();
-        let p3 = Proposition::from("caffeinated");
-
-        let a1 = Action::new(
-            String::from("coffee"),
-            fragset! {[&p1]},
-            fragset! {[&p3, &not_p1]},
-        );
-        let a2 = Action::new(
-            String::from("walk dog"),
-            fragset! {[&p2, &p3]},
-            fragset! {[&not_p2]},
-        );
-        let a3 = Action::new_maintenance(&not_p1);
-
-        assert_eq!(
-            pairs_from_sets(fragset! {[a1.clone()]}, fragset! {[a2.clone()]}),
-            fragset! {[PairSet(a1.clone(), a2.clone())]}
-        );
-
-        assert_eq!(
-            pairs_from_sets(fragset! {[a3.clone()]}, fragset! {[a2.clone()]}),
-            fragset! {[PairSet(a3, a2)]}
-        );
-    }
-}
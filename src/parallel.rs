@@ -0,0 +1,68 @@
+/// Below this many items the work runs on the current thread since
+/// splitting it costs more than it saves
+#[cfg(feature = "rayon")]
+const MIN_PARALLEL_LEN: usize = 64;
+
+#[cfg(all(test, feature = "rayon"))]
+thread_local! {
+    /// Set by `sequential` to take the path used without the feature
+    static SEQUENTIAL: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+    /// The number of calls on this thread that were spread over
+    /// threads
+    static PARALLEL_CALLS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+/// Runs `f` with every call on this thread taking the sequential
+/// path so tests can compare it with the parallel one
+#[cfg(all(test, feature = "rayon"))]
+pub(crate) fn sequential<R>(f: impl FnOnce() -> R) -> R {
+    SEQUENTIAL.with(|s| s.set(true));
+    let result = f();
+    SEQUENTIAL.with(|s| s.set(false));
+    result
+}
+
+/// Returns the number of calls on this thread that were spread over
+/// threads
+#[cfg(all(test, feature = "rayon"))]
+pub(crate) fn parallel_calls() -> usize {
+    PARALLEL_CALLS.with(|c| c.get())
+}
+
+/// Returns `f` applied to each item, spread over threads with the
+/// `rayon` feature. Results are in the order of `items` either way so
+/// anything built from them is the same with or without the feature.
+pub(crate) fn map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync + Send,
+{
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+        #[cfg(test)]
+        let sequential = SEQUENTIAL.with(|s| s.get());
+        #[cfg(not(test))]
+        let sequential = false;
+        if items.len() >= MIN_PARALLEL_LEN && !sequential {
+            #[cfg(test)]
+            PARALLEL_CALLS.with(|c| c.set(c.get() + 1));
+            return items.par_iter().map(f).collect();
+        }
+    }
+    items.iter().map(f).collect()
+}
+
+/// Returns the items `keep` is true for, in the order of `items`
+pub(crate) fn filter<T, F>(items: Vec<T>, keep: F) -> Vec<T>
+where
+    T: Sync,
+    F: Fn(&T) -> bool + Sync + Send,
+{
+    let kept = map(&items, keep);
+    items.into_iter()
+        .zip(kept)
+        .filter_map(|(item, keep)| if keep { Some(item) } else { None })
+        .collect()
+}
@@ -0,0 +1,1002 @@
+use std::collections::{HashMap, HashSet};
+use std::error;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+use crate::proposition::Proposition;
+use crate::action::Action;
+use crate::domain::{Domain, DomainOptions};
+use crate::GraphPlan;
+use crate::schema::{ActionSchema, GroundActions, Grounder, Parameter, Template, OBJECT_TYPE};
+
+
+/// An error found while reading a PDDL domain or problem. Reports
+/// the file, line and column of the offending expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}: {}", self.file, self.line, self.column, self.message)
+    }
+}
+
+impl error::Error for ParseError {}
+
+/// Requirements that can be expressed with STRIPS style actions
+const SUPPORTED_REQUIREMENTS: [&str; 5] = [
+    ":strips",
+    ":typing",
+    ":negative-preconditions",
+    ":equality",
+    ":conditional-effects",
+];
+
+/// Literals paired with where they were read
+type Literals = Vec<(Template, Position)>;
+
+/// A name with a type such as `?loc - location` or `table - location`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypedName {
+    pub name: String,
+    pub kind: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PredicateDef {
+    pub name: String,
+    pub parameters: Vec<TypedName>,
+}
+
+/// A parsed `(define (domain ...))`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DomainDef {
+    pub name: String,
+    pub requirements: Vec<String>,
+    /// Declared types paired with their parent type
+    pub types: Vec<TypedName>,
+    pub constants: Vec<TypedName>,
+    pub predicates: Vec<PredicateDef>,
+    pub actions: Vec<ActionSchema>,
+}
+
+/// A parsed `(define (problem ...))`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProblemDef {
+    pub name: String,
+    pub domain: String,
+    pub objects: Vec<TypedName>,
+    pub init: Vec<Template>,
+    pub goal: Vec<Template>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Position {
+    line: usize,
+    column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Symbol(String, Position),
+    List(Vec<Expr>, Position),
+}
+
+impl Expr {
+    fn position(&self) -> Position {
+        match self {
+            Expr::Symbol(_, pos) => *pos,
+            Expr::List(_, pos) => *pos,
+        }
+    }
+
+    fn symbol(&self) -> Option<&str> {
+        match self {
+            Expr::Symbol(s, _) => Some(s),
+            Expr::List(..) => None,
+        }
+    }
+
+    fn list(&self) -> Option<&[Expr]> {
+        match self {
+            Expr::Symbol(..) => None,
+            Expr::List(items, _) => Some(items),
+        }
+    }
+
+    /// Returns the leading symbol of a list such as `and` in `(and ...)`
+    fn head(&self) -> Option<&str> {
+        self.list()
+            .and_then(|items| items.first())
+            .and_then(|i| i.symbol())
+    }
+}
+
+/// Tracks the position in the source while reading characters
+struct Cursor<'s> {
+    chars: Peekable<Chars<'s>>,
+    line: usize,
+    column: usize,
+}
+
+impl<'s> Cursor<'s> {
+    fn new(source: &'s str) -> Self {
+        Cursor { chars: source.chars().peekable(), line: 1, column: 1 }
+    }
+
+    fn position(&self) -> Position {
+        Position { line: self.line, column: self.column }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+}
+
+/// Reads PDDL source files into definitions. Holds the file name so
+/// every error can say where it came from.
+struct Reader<'f> {
+    file: &'f str,
+}
+
+impl<'f> Reader<'f> {
+    fn error<S: Into<String>>(&self, pos: Position, message: S) -> ParseError {
+        ParseError {
+            file: self.file.to_string(),
+            line: pos.line,
+            column: pos.column,
+            message: message.into(),
+        }
+    }
+
+    /// Reads the source into s-expressions. PDDL is case
+    /// insensitive so all symbols are lowercased.
+    fn read(&self, source: &str) -> Result<Vec<Expr>, ParseError> {
+        let mut cursor = Cursor::new(source);
+        let mut open: Vec<(Vec<Expr>, Position)> = Vec::new();
+        let mut top = Vec::new();
+
+        while let Some(c) = cursor.peek() {
+            let pos = cursor.position();
+            let expr = match c {
+                '(' => {
+                    cursor.bump();
+                    open.push((Vec::new(), pos));
+                    continue
+                },
+                ')' => {
+                    cursor.bump();
+                    let (items, start) = open.pop()
+                        .ok_or_else(|| self.error(pos, "unexpected `)`"))?;
+                    Expr::List(items, start)
+                },
+                ';' => {
+                    while cursor.peek().map(|c| c != '\n').unwrap_or(false) {
+                        cursor.bump();
+                    }
+                    continue
+                },
+                c if c.is_whitespace() => {
+                    cursor.bump();
+                    continue
+                },
+                _ => {
+                    let mut symbol = String::new();
+                    while let Some(c) = cursor.peek() {
+                        if c.is_whitespace() || c == '(' || c == ')' || c == ';' {
+                            break
+                        }
+                        symbol.extend(c.to_lowercase());
+                        cursor.bump();
+                    }
+                    Expr::Symbol(symbol, pos)
+                },
+            };
+
+            match open.last_mut() {
+                Some((items, _)) => items.push(expr),
+                None => top.push(expr),
+            }
+        }
+
+        if let Some((_, pos)) = open.pop() {
+            return Err(self.error(pos, "unclosed `(`"));
+        }
+
+        Ok(top)
+    }
+
+    /// Reads a `(define (<kind> <name>) ...)` form and returns the
+    /// name along with the remaining sections
+    fn define<'e>(&self, exprs: &'e [Expr], kind: &str) -> Result<(String, &'e [Expr]), ParseError> {
+        let start = Position { line: 1, column: 1 };
+        let define = match exprs {
+            [define] => define,
+            [] => return Err(self.error(start, format!("expected `(define ({} ...))`", kind))),
+            [_, extra, ..] => return Err(self.error(extra.position(), "unexpected expression after `define`")),
+        };
+
+        let items = define.list()
+            .filter(|_| define.head() == Some("define"))
+            .ok_or_else(|| self.error(define.position(), format!("expected `(define ({} ...))`", kind)))?;
+
+        let name = items.get(1)
+            .and_then(|header| match header.list() {
+                Some([k, name]) if k.symbol() == Some(kind) => name.symbol(),
+                _ => None,
+            })
+            .ok_or_else(|| {
+                let pos = items.get(1).unwrap_or(define).position();
+                self.error(pos, format!("expected `({} <name>)`", kind))
+            })?;
+
+        Ok((name.to_string(), &items[2..]))
+    }
+
+    /// Returns the keyword and arguments of a section like
+    /// `(:predicates ...)`
+    fn section<'e>(&self, expr: &'e Expr) -> Result<(&'e str, &'e [Expr]), ParseError> {
+        match (expr.head(), expr.list()) {
+            (Some(keyword), Some(items)) if keyword.starts_with(':') => Ok((keyword, &items[1..])),
+            _ => Err(self.error(expr.position(), "expected a section such as `(:action ...)`")),
+        }
+    }
+
+    fn symbol<'e>(&self, expr: &'e Expr, expected: &str) -> Result<&'e str, ParseError> {
+        expr.symbol()
+            .ok_or_else(|| self.error(expr.position(), format!("expected {}", expected)))
+    }
+
+    fn requirements(&self, items: &[Expr]) -> Result<Vec<String>, ParseError> {
+        items.iter()
+            .map(|i| {
+                let req = self.symbol(i, "a requirement")?;
+                if SUPPORTED_REQUIREMENTS.contains(&req) {
+                    Ok(req.to_string())
+                } else {
+                    Err(self.error(i.position(), format!("unsupported requirement `{}`", req)))
+                }
+            })
+            .collect()
+    }
+
+    /// Reads a list of names with optional types such as `a b - t c`
+    /// where `c` has no type and becomes an `object`
+    fn typed_list(&self, items: &[Expr], scope: &Scope) -> Result<Vec<(TypedName, Position)>, ParseError> {
+        let mut typed = Vec::new();
+        let mut pending = Vec::new();
+        let mut iter = items.iter();
+
+        while let Some(item) = iter.next() {
+            let name = self.symbol(item, "a name")?;
+            if name == "-" {
+                scope.require(self, ":typing", "types", item.position())?;
+                let kind_expr = iter.next()
+                    .ok_or_else(|| self.error(item.position(), "expected a type after `-`"))?;
+                if kind_expr.head() == Some("either") {
+                    return Err(self.error(kind_expr.position(), "`either` types are not supported"));
+                }
+                let kind = self.symbol(kind_expr, "a type")?;
+                if pending.is_empty() {
+                    return Err(self.error(item.position(), "expected a name before `-`"));
+                }
+                for (name, pos) in pending.drain(..) {
+                    typed.push((TypedName { name, kind: kind.to_string() }, pos));
+                }
+            } else {
+                pending.push((name.to_string(), item.position()));
+            }
+        }
+
+        for (name, pos) in pending {
+            typed.push((TypedName { name, kind: OBJECT_TYPE.to_string() }, pos));
+        }
+
+        Ok(typed)
+    }
+
+    fn atom(&self, expr: &Expr) -> Result<Template, ParseError> {
+        let items = expr.list()
+            .filter(|items| !items.is_empty())
+            .ok_or_else(|| self.error(expr.position(), "expected an atom such as `(on ?x ?y)`"))?;
+        let predicate = self.symbol(&items[0], "a predicate name")?;
+        let terms = items[1..].iter()
+            .map(|i| self.symbol(i, "a term").map(String::from))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Template { predicate: predicate.to_string(), terms, negation: false })
+    }
+
+    fn literal(&self, expr: &Expr) -> Result<Template, ParseError> {
+        if expr.head() == Some("not") {
+            match expr.list() {
+                Some([_, inner]) => Ok(self.atom(inner)?.negate()),
+                _ => Err(self.error(expr.position(), "expected `(not <atom>)`")),
+            }
+        } else {
+            self.atom(expr)
+        }
+    }
+
+    /// Reads a formula made of literals joined by `and`
+    fn conjunction(&self, expr: &Expr) -> Result<Literals, ParseError> {
+        match expr.head() {
+            Some("and") => {
+                let mut literals = Vec::new();
+                for i in &expr.list().unwrap_or(&[])[1..] {
+                    literals.extend(self.conjunction(i)?);
+                }
+                Ok(literals)
+            },
+            Some(keyword @ ("or" | "imply" | "exists" | "forall" | "when")) => {
+                Err(self.error(expr.position(), format!("`{}` is not supported in STRIPS formulas", keyword)))
+            },
+            _ if expr.list().map(|items| items.is_empty()).unwrap_or(false) => Ok(Vec::new()),
+            _ => Ok(vec![(self.literal(expr)?, expr.position())]),
+        }
+    }
+
+    /// Reads an effect made of literals and `(when <condition>
+    /// <effect>)` clauses joined by `and`. Returns the unconditional
+    /// literals and each condition with its literals.
+    fn effect(&self, expr: &Expr, scope: &Scope) -> Result<(Literals, Vec<(Literals, Literals)>), ParseError> {
+        match expr.head() {
+            Some("and") => {
+                let mut effects = Vec::new();
+                let mut conditional = Vec::new();
+                for i in &expr.list().unwrap_or(&[])[1..] {
+                    let (e, c) = self.effect(i, scope)?;
+                    effects.extend(e);
+                    conditional.extend(c);
+                }
+                Ok((effects, conditional))
+            },
+            Some("when") => match expr.list() {
+                Some([_, condition, effect]) => {
+                    let clause = (self.conjunction(condition)?, self.conjunction(effect)?);
+                    scope.require(self, ":conditional-effects", "`when`", expr.position())?;
+                    Ok((Vec::new(), vec![clause]))
+                },
+                _ => Err(self.error(expr.position(), "expected `(when <condition> <effect>)`")),
+            },
+            _ => Ok((self.conjunction(expr)?, Vec::new())),
+        }
+    }
+
+    fn action(&self, items: &[Expr], pos: Position, scope: &Scope) -> Result<ActionSchema, ParseError> {
+        let name = items.first()
+            .ok_or_else(|| self.error(pos, "expected an action name"))
+            .and_then(|i| self.symbol(i, "an action name"))?;
+        let mut action = ActionSchema::new(name, Vec::new(), Vec::new(), Vec::new());
+        let mut params = HashSet::new();
+
+        let mut iter = items[1..].iter();
+        while let Some(key) = iter.next() {
+            let keyword = self.symbol(key, "an action keyword")?;
+            let value = iter.next()
+                .ok_or_else(|| self.error(key.position(), format!("expected a value after `{}`", keyword)))?;
+            match keyword {
+                ":parameters" => {
+                    let list = value.list()
+                        .ok_or_else(|| self.error(value.position(), "expected a parameter list"))?;
+                    for (param, pos) in self.typed_list(list, scope)? {
+                        if !param.name.starts_with('?') {
+                            return Err(self.error(pos, format!("parameter `{}` must start with `?`", param.name)));
+                        }
+                        scope.check_type(self, &param.kind, pos)?;
+                        params.insert(param.name.clone());
+                        action.parameters.push(Parameter::new(&param.name, &param.kind));
+                    }
+                },
+                ":precondition" => {
+                    action.reqs = scope.check_conditions(self, self.conjunction(value)?, &params)?;
+                },
+                ":effect" => {
+                    let (effects, conditional) = self.effect(value, scope)?;
+                    action.effects = scope.check_literals(self, effects, &params)?;
+                    for (conditions, effects) in conditional {
+                        action.when(
+                            scope.check_conditions(self, conditions, &params)?,
+                            scope.check_literals(self, effects, &params)?,
+                        );
+                    }
+                },
+                _ => return Err(self.error(key.position(), format!("unknown action keyword `{}`", keyword))),
+            }
+        }
+
+        Ok(action)
+    }
+}
+
+/// Names declared by a domain (and problem) used to check that
+/// formulas only refer to things that exist
+#[derive(Default)]
+struct Scope {
+    requirements: HashSet<String>,
+    types: HashSet<String>,
+    predicates: HashMap<String, usize>,
+    objects: HashSet<String>,
+}
+
+impl Scope {
+    fn from_domain(domain: &DomainDef) -> Self {
+        let mut scope = Scope::default();
+        scope.requirements.extend(domain.requirements.iter().cloned());
+        scope.types.insert(OBJECT_TYPE.to_string());
+        scope.types.extend(domain.types.iter().map(|t| t.name.clone()));
+        scope.predicates.extend(domain.predicates.iter().map(|p| (p.name.clone(), p.parameters.len())));
+        scope.objects.extend(domain.constants.iter().map(|c| c.name.clone()));
+        scope
+    }
+
+    /// Checks `requirement` was declared for something that needs it
+    fn require(&self, reader: &Reader, requirement: &str, what: &str, pos: Position) -> Result<(), ParseError> {
+        if self.requirements.contains(requirement) {
+            Ok(())
+        } else {
+            Err(reader.error(pos, format!("`{}` is required for {}", requirement, what)))
+        }
+    }
+
+    fn check_type(&self, reader: &Reader, kind: &str, pos: Position) -> Result<(), ParseError> {
+        if self.types.contains(kind) {
+            Ok(())
+        } else {
+            Err(reader.error(pos, format!("undeclared type `{}`", kind)))
+        }
+    }
+
+    /// Checks every literal with `check_atom` and drops their
+    /// positions
+    fn check_literals(&self, reader: &Reader, literals: Literals, params: &HashSet<String>) -> Result<Vec<Template>, ParseError> {
+        literals.into_iter()
+            .map(|(literal, pos)| self.check_atom(reader, &literal, params, pos).map(|_| literal))
+            .collect()
+    }
+
+    /// Checks literals of a precondition or goal, negated atoms
+    /// other than `=` need `:negative-preconditions`
+    fn check_conditions(&self, reader: &Reader, literals: Literals, params: &HashSet<String>) -> Result<Vec<Template>, ParseError> {
+        for (literal, pos) in &literals {
+            if literal.negation && literal.predicate != "=" {
+                self.require(reader, ":negative-preconditions", "negative conditions", *pos)?;
+            }
+        }
+        self.check_literals(reader, literals, params)
+    }
+
+    /// Checks the predicate of an atom exists with a matching arity
+    /// and every term is a parameter or a known object
+    fn check_atom(&self, reader: &Reader, atom: &Template, params: &HashSet<String>, pos: Position) -> Result<(), ParseError> {
+        if atom.predicate == "=" {
+            self.require(reader, ":equality", "`=`", pos)?;
+            if atom.terms.len() != 2 {
+                return Err(reader.error(pos, "`=` takes exactly two terms"));
+            }
+        } else {
+            match self.predicates.get(&atom.predicate) {
+                None => return Err(reader.error(pos, format!("undeclared predicate `{}`", atom.predicate))),
+                Some(arity) if *arity != atom.terms.len() => return Err(reader.error(
+                    pos,
+                    format!("predicate `{}` expects {} terms but got {}", atom.predicate, arity, atom.terms.len()),
+                )),
+                Some(_) => (),
+            }
+        }
+
+        for term in &atom.terms {
+            if !params.contains(term) && !self.objects.contains(term) {
+                let kind = if term.starts_with('?') { "parameter" } else { "object" };
+                return Err(reader.error(pos, format!("unknown {} `{}`", kind, term)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses a PDDL `(define (domain ...))`. The file name is only used
+/// for reporting errors.
+pub fn parse_domain(file: &str, source: &str) -> Result<DomainDef, ParseError> {
+    let reader = Reader { file };
+    let exprs = reader.read(source)?;
+    let (name, sections) = reader.define(&exprs, "domain")?;
+
+    let mut domain = DomainDef {
+        name,
+        requirements: Vec::new(),
+        types: Vec::new(),
+        constants: Vec::new(),
+        predicates: Vec::new(),
+        actions: Vec::new(),
+    };
+
+    for section in sections {
+        let (keyword, items) = reader.section(section)?;
+        // PDDL requires declarations to come before they are used
+        // so the scope only needs to know about earlier sections
+        let scope = Scope::from_domain(&domain);
+        match keyword {
+            ":requirements" => domain.requirements = reader.requirements(items)?,
+            ":types" => {
+                scope.require(&reader, ":typing", "types", section.position())?;
+                let types = reader.typed_list(items, &scope)?;
+                let declared: HashSet<&str> = types.iter()
+                    .map(|(t, _)| t.name.as_str())
+                    .chain(std::iter::once(OBJECT_TYPE))
+                    .collect();
+                for (t, pos) in &types {
+                    if !declared.contains(t.kind.as_str()) {
+                        return Err(reader.error(*pos, format!("undeclared type `{}`", t.kind)));
+                    }
+                }
+                domain.types = types.into_iter().map(|(t, _)| t).collect();
+            },
+            ":constants" => {
+                for (constant, pos) in reader.typed_list(items, &scope)? {
+                    scope.check_type(&reader, &constant.kind, pos)?;
+                    domain.constants.push(constant);
+                }
+            },
+            ":predicates" => {
+                for i in items {
+                    let atom = reader.atom(i)?;
+                    let mut parameters = Vec::new();
+                    for (param, pos) in reader.typed_list(&i.list().unwrap_or(&[])[1..], &scope)? {
+                        scope.check_type(&reader, &param.kind, pos)?;
+                        parameters.push(param);
+                    }
+                    domain.predicates.push(PredicateDef { name: atom.predicate, parameters });
+                }
+            },
+            ":action" => {
+                let action = reader.action(items, section.position(), &scope)?;
+                domain.actions.push(action);
+            },
+            _ => return Err(reader.error(section.position(), format!("unsupported section `{}`", keyword))),
+        }
+    }
+
+    Ok(domain)
+}
+
+/// Parses a PDDL `(define (problem ...))` for the given domain. The
+/// file name is only used for reporting errors.
+pub fn parse_problem(file: &str, source: &str, domain: &DomainDef) -> Result<ProblemDef, ParseError> {
+    let reader = Reader { file };
+    let exprs = reader.read(source)?;
+    let (name, sections) = reader.define(&exprs, "problem")?;
+
+    let mut problem = ProblemDef {
+        name,
+        domain: String::new(),
+        objects: Vec::new(),
+        init: Vec::new(),
+        goal: Vec::new(),
+    };
+    let mut scope = Scope::from_domain(domain);
+    let no_params = HashSet::new();
+
+    for section in sections {
+        let (keyword, items) = reader.section(section)?;
+        match keyword {
+            ":domain" => {
+                let name = items.first()
+                    .ok_or_else(|| reader.error(section.position(), "expected a domain name"))
+                    .and_then(|i| reader.symbol(i, "a domain name"))?;
+                if name != domain.name {
+                    return Err(reader.error(
+                        section.position(),
+                        format!("problem is for domain `{}` but `{}` was given", name, domain.name),
+                    ));
+                }
+                problem.domain = name.to_string();
+            },
+            ":requirements" => scope.requirements.extend(reader.requirements(items)?),
+            ":objects" => {
+                for (object, pos) in reader.typed_list(items, &scope)? {
+                    scope.check_type(&reader, &object.kind, pos)?;
+                    scope.objects.insert(object.name.clone());
+                    problem.objects.push(object);
+                }
+            },
+            ":init" => {
+                for i in items {
+                    if i.head() == Some("not") {
+                        return Err(reader.error(i.position(), "negative literals are not allowed in `:init`"));
+                    }
+                    let atom = reader.atom(i)?;
+                    if atom.predicate == "=" {
+                        return Err(reader.error(i.position(), "`=` is not allowed in `:init`"));
+                    }
+                    scope.check_atom(&reader, &atom, &no_params, i.position())?;
+                    problem.init.push(atom);
+                }
+            },
+            ":goal" => {
+                let value = match items {
+                    [value] => value,
+                    _ => return Err(reader.error(section.position(), "expected a single goal formula")),
+                };
+                problem.goal.extend(scope.check_conditions(&reader, reader.conjunction(value)?, &no_params)?);
+            },
+            _ => return Err(reader.error(section.position(), format!("unsupported section `{}`", keyword))),
+        }
+    }
+
+    if problem.domain.is_empty() {
+        return Err(reader.error(exprs[0].position(), "expected a `(:domain <name>)` section"));
+    }
+
+    Ok(problem)
+}
+
+/// A problem with every action schema expanded for the objects
+/// matching its parameter types. Propositions and actions are
+/// identified by strings like `on arm table` and
+/// `pick-up arm teacup table`.
+///
+/// ## Example
+///
+/// ```
+/// use graphplan::{pddl, GraphPlan, SimpleSolver};
+///
+/// let domain = pddl::parse_domain("domain.pddl", "
+///   (define (domain morning)
+///     (:predicates (tired) (caffeinated))
+///     (:action drink-coffee
+///       :precondition (tired)
+///       :effect (and (caffeinated) (not (tired)))))
+/// ").unwrap();
+/// let problem = pddl::parse_problem("problem.pddl", "
+///   (define (problem wake-up)
+///     (:domain morning)
+///     (:init (tired))
+///     (:goal (caffeinated)))
+/// ", &domain).unwrap();
+///
+/// let ground = pddl::ground(&domain, &problem);
+/// let mut pg = GraphPlan::from_domain(&ground.domain());
+/// assert!(pg.search::<SimpleSolver>().is_ok());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroundProblem {
+    initial_props: HashSet<Proposition<String>>,
+    goals: HashSet<Proposition<String>>,
+    actions: GroundActions,
+}
+
+impl GroundProblem {
+    pub fn initial_props(&self) -> HashSet<&Proposition<String>> {
+        self.initial_props.iter().collect()
+    }
+
+    pub fn goals(&self) -> HashSet<&Proposition<String>> {
+        self.goals.iter().collect()
+    }
+
+    pub fn actions(&self) -> HashSet<Action<String, String>> {
+        self.actions.actions()
+    }
+
+    /// Returns a domain with maintenance actions ready to be passed
+    /// to `GraphPlan::from_domain`. Atoms missing from `:init` are
+    /// false so negative preconditions and goals are compiled away.
+    pub fn domain(&self) -> Domain<String, String> {
+        let actions = self.actions();
+        GraphPlan::create_domain_with(
+            self.initial_props(),
+            self.goals(),
+            actions.iter().collect(),
+            DomainOptions::new().compile_negative_preconditions(true),
+        )
+    }
+}
+
+fn ground_template(template: &Template) -> Proposition<String> {
+    let terms: Vec<&str> = template.terms.iter().map(String::as_str).collect();
+    let prop = crate::schema::proposition(&template.predicate, &terms);
+    if template.negation { prop.negate() } else { prop }
+}
+
+/// Expands every action in the domain for each combination of
+/// objects matching its parameter types
+pub fn ground(domain: &DomainDef, problem: &ProblemDef) -> GroundProblem {
+    let mut grounder = Grounder::new();
+    for t in &domain.types {
+        grounder.add_type(&t.name, &t.kind);
+    }
+    for o in domain.constants.iter().chain(problem.objects.iter()) {
+        grounder.add_object(&o.name, &o.kind);
+    }
+
+    let initial_props: HashSet<Proposition<String>> = problem.init.iter()
+        .map(ground_template)
+        .collect();
+    let actions = grounder.ground(&domain.actions, &initial_props.iter().collect());
+
+    GroundProblem {
+        goals: problem.goal.iter().map(ground_template).collect(),
+        initial_props,
+        actions,
+    }
+}
+
+#[cfg(test)]
+mod pddl_test {
+    use super::*;
+    use crate::GraphPlan;
+    use crate::solver::SimpleSolver;
+
+    const DOMAIN: &str = "
+; Moving a robot arm between rooms
+(define (domain Rooms)
+  (:requirements :strips :typing)
+  (:types room robot)
+  (:predicates (at ?r - robot ?x - room)
+               (door ?x - room ?y - room))
+  (:action move
+    :parameters (?r - robot ?from ?to - room)
+    :precondition (and (at ?r ?from) (door ?from ?to))
+    :effect (and (at ?r ?to) (not (at ?r ?from)))))
+";
+
+    const PROBLEM: &str = "
+(define (problem go-to-kitchen)
+  (:domain rooms)
+  (:objects bot - robot hall kitchen - room)
+  (:init (at bot hall) (door hall kitchen))
+  (:goal (at bot kitchen)))
+";
+
+    #[test]
+    fn parses_domain() {
+        let domain = parse_domain("domain.pddl", DOMAIN).unwrap();
+        assert_eq!(domain.name, "rooms");
+        assert_eq!(domain.requirements, vec![":strips", ":typing"]);
+        assert_eq!(domain.predicates.len(), 2);
+
+        let action = &domain.actions[0];
+        assert_eq!(action.name, "move");
+        assert_eq!(
+            action.parameters.iter().map(|p| (p.name.as_str(), p.kind.as_str())).collect::<Vec<_>>(),
+            vec![("?r", "robot"), ("?from", "room"), ("?to", "room")]
+        );
+        assert_eq!(action.reqs.len(), 2);
+        assert_eq!(action.effects[1], Template::new("at", &["?r", "?from"]).negate());
+    }
+
+    #[test]
+    fn grounds_actions_for_each_object() {
+        let domain = parse_domain("domain.pddl", DOMAIN).unwrap();
+        let problem = parse_problem("problem.pddl", PROBLEM, &domain).unwrap();
+        let ground = ground(&domain, &problem);
+
+        // Moves through rooms without a door are pruned since
+        // `door` is never an effect
+        assert_eq!(ground.actions().len(), 1);
+        assert!(ground.initial_props().contains(&Proposition::from(String::from("at bot hall"))));
+        assert!(ground.goals().contains(&Proposition::from(String::from("at bot kitchen"))));
+
+        let moves = ground.actions();
+        let go = moves.iter()
+            .find(|a| a.get_action().unwrap() == "move bot hall kitchen")
+            .unwrap();
+        assert!(go.effects.contains(&Proposition::from(String::from("at bot hall")).negate()));
+    }
+
+    #[test]
+    fn grounded_problem_can_be_solved() {
+        let domain = parse_domain("domain.pddl", DOMAIN).unwrap();
+        let problem = parse_problem("problem.pddl", PROBLEM, &domain).unwrap();
+        let ground = ground(&domain, &problem);
+        let actions = ground.actions();
+        let domain = GraphPlan::create_domain(
+            ground.initial_props(),
+            ground.goals(),
+            actions.iter().collect(),
+        );
+        let mut pg = GraphPlan::from_domain(&domain);
+        let plan = GraphPlan::format_plan(pg.search::<SimpleSolver>().unwrap());
+        assert_eq!(plan.len(), 1);
+        assert_eq!(plan[0].iter().next().unwrap().get_action().unwrap(), "move bot hall kitchen");
+    }
+
+    #[test]
+    fn solves_kitchen_example() {
+        let domain = crate::fixtures::kitchen();
+
+        // Propositions level off before their mutexes do so this
+        // needs the full termination test to find the plan
+        let mut pg = GraphPlan::from_domain(&domain);
+        let plan = pg.search::<SimpleSolver>().unwrap();
+        assert_eq!(plan.len(), 9);
+        assert_eq!(crate::validate_plan(&domain, &plan), Ok(()));
+    }
+
+    #[test]
+    fn equality_prunes_bindings() {
+        let source = "
+(define (domain rooms)
+  (:requirements :strips :equality)
+  (:predicates (at ?x))
+  (:action move
+    :parameters (?from ?to)
+    :precondition (and (at ?from) (not (= ?from ?to)))
+    :effect (and (at ?to) (not (at ?from)))))
+";
+        let domain = parse_domain("domain.pddl", source).unwrap();
+        let problem = parse_problem(
+            "problem.pddl",
+            "(define (problem p) (:domain rooms) (:objects a b) (:init (at a)) (:goal (at b)))",
+            &domain,
+        ).unwrap();
+        assert_eq!(ground(&domain, &problem).actions().len(), 2);
+    }
+
+    #[test]
+    fn negative_preconditions_hold_for_atoms_missing_from_init() {
+        let source = "
+(define (domain lights)
+  (:requirements :strips :negative-preconditions)
+  (:predicates (on) (checked))
+  (:action check
+    :precondition (not (on))
+    :effect (checked)))
+";
+        let domain = parse_domain("domain.pddl", source).unwrap();
+        let problem = parse_problem(
+            "problem.pddl",
+            "(define (problem p) (:domain lights) (:init) (:goal (and (checked) (not (on)))))",
+            &domain,
+        ).unwrap();
+        let domain = ground(&domain, &problem).domain();
+        let mut pg = GraphPlan::from_domain(&domain);
+        let plan = pg.search::<SimpleSolver>().unwrap();
+        assert_eq!(plan.len(), 1);
+        assert_eq!(crate::validate_plan(&domain, &plan), Ok(()));
+    }
+
+    #[test]
+    fn conditional_effects_move_loaded_packages() {
+        let source = "
+(define (domain trucks)
+  (:requirements :strips :conditional-effects)
+  (:predicates (at ?x ?l) (in ?p))
+  (:constants truck box)
+  (:action load
+    :parameters (?p ?l)
+    :precondition (and (at truck ?l) (at ?p ?l))
+    :effect (and (in ?p) (not (at ?p ?l))))
+  (:action drive
+    :parameters (?from ?to)
+    :precondition (at truck ?from)
+    :effect (and (at truck ?to) (not (at truck ?from))
+                 (when (in box) (and (at box ?to) (not (at box ?from)))))))
+";
+        let domain = parse_domain("domain.pddl", source).unwrap();
+        assert_eq!(domain.actions[1].conditional_effects.len(), 1);
+        let problem = parse_problem(
+            "problem.pddl",
+            "(define (problem p) (:domain trucks) (:objects depot shop)
+               (:init (at truck depot) (at box depot))
+               (:goal (at box shop)))",
+            &domain,
+        ).unwrap();
+        let domain = ground(&domain, &problem).domain();
+        let mut pg = GraphPlan::from_domain(&domain);
+        let plan = pg.search::<SimpleSolver>().unwrap();
+        assert_eq!(crate::validate_plan(&domain, &plan), Ok(()));
+        let ids: Vec<&String> = plan.iter()
+            .map(|step| step.iter().next().unwrap().get_action().unwrap())
+            .collect();
+        assert_eq!(ids, vec!["load box depot", "drive depot shop"]);
+    }
+
+    #[test]
+    fn nested_when_is_an_error() {
+        let source = "
+(define (domain d)
+  (:predicates (p) (q))
+  (:action a
+    :effect (when (p) (when (q) (p)))))
+";
+        let err = parse_domain("domain.pddl", source).unwrap_err();
+        assert_eq!(err.message, "`when` is not supported in STRIPS formulas");
+    }
+
+    #[test]
+    fn errors_report_position() {
+        let err = parse_domain("domain.pddl", "(define (domain d)\n  (:predicates (p))\n  (:action a\n    :effect (q)))").unwrap_err();
+        assert_eq!(err.file, "domain.pddl");
+        assert_eq!((err.line, err.column), (4, 13));
+        assert_eq!(err.to_string(), "domain.pddl:4:13: undeclared predicate `q`");
+
+        let err = parse_domain("domain.pddl", "(define (domain d)\n  (:requirements :fluents))").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 18));
+
+        let err = parse_domain("domain.pddl", "(define (domain d)\n  (:predicates (p))").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 1));
+        assert_eq!(err.message, "unclosed `(`");
+    }
+
+    #[test]
+    fn rejects_undeclared_requirements() {
+        let err = parse_domain("domain.pddl", "(define (domain d)\n  (:requirements :strips)\n  (:predicates (p) (q))\n  (:action a\n    :precondition (not (p))\n    :effect (q)))").unwrap_err();
+        assert_eq!(err.to_string(), "domain.pddl:5:19: `:negative-preconditions` is required for negative conditions");
+
+        let err = parse_domain("domain.pddl", "(define (domain d)\n  (:types room))").unwrap_err();
+        assert_eq!(err.to_string(), "domain.pddl:2:3: `:typing` is required for types");
+
+        let err = parse_domain("domain.pddl", "(define (domain d)\n  (:predicates (at ?x - room)))").unwrap_err();
+        assert_eq!(err.to_string(), "domain.pddl:2:23: `:typing` is required for types");
+
+        let err = parse_domain("domain.pddl", "(define (domain d)\n  (:predicates (p ?x))\n  (:action a :parameters (?x ?y)\n    :precondition (= ?x ?y)\n    :effect (p ?x)))").unwrap_err();
+        assert_eq!(err.to_string(), "domain.pddl:4:19: `:equality` is required for `=`");
+
+        let err = parse_domain("domain.pddl", "(define (domain d)\n  (:predicates (p) (q))\n  (:action a\n    :effect (when (p) (q))))").unwrap_err();
+        assert_eq!(err.to_string(), "domain.pddl:4:13: `:conditional-effects` is required for `when`");
+
+        // Delete effects and negated `=` are part of `:strips` and
+        // `:equality`
+        parse_domain("domain.pddl", "(define (domain d)\n  (:requirements :equality)\n  (:predicates (p ?x))\n  (:action a :parameters (?x ?y)\n    :precondition (not (= ?x ?y))\n    :effect (not (p ?x))))").unwrap();
+    }
+
+    #[test]
+    fn problems_need_requirements_for_negative_goals() {
+        let domain = parse_domain("domain.pddl", "(define (domain d) (:predicates (p) (q)))").unwrap();
+        let err = parse_problem(
+            "problem.pddl",
+            "(define (problem p)\n  (:domain d)\n  (:goal (and (q) (not (p)))))",
+            &domain,
+        ).unwrap_err();
+        assert_eq!(err.to_string(), "problem.pddl:3:19: `:negative-preconditions` is required for negative conditions");
+
+        parse_problem(
+            "problem.pddl",
+            "(define (problem p)\n  (:domain d)\n  (:requirements :negative-preconditions)\n  (:goal (and (q) (not (p)))))",
+            &domain,
+        ).unwrap();
+    }
+
+    #[test]
+    fn equality_is_not_allowed_in_init() {
+        let domain = parse_domain(
+            "domain.pddl",
+            "(define (domain d) (:requirements :equality) (:predicates (p ?x)))",
+        ).unwrap();
+        let err = parse_problem(
+            "problem.pddl",
+            "(define (problem p)\n  (:domain d)\n  (:objects a)\n  (:init (p a) (= a a)))",
+            &domain,
+        ).unwrap_err();
+        assert_eq!(err.to_string(), "problem.pddl:4:16: `=` is not allowed in `:init`");
+    }
+
+    #[test]
+    fn problem_errors_report_position() {
+        let domain = parse_domain("domain.pddl", DOMAIN).unwrap();
+        let err = parse_problem(
+            "problem.pddl",
+            "(define (problem p)\n  (:domain rooms)\n  (:objects bot - robot)\n  (:init (at bot attic)))",
+            &domain,
+        ).unwrap_err();
+        assert_eq!(err.to_string(), "problem.pddl:4:10: unknown object `attic`");
+    }
+}
@@ -1,106 +1,207 @@
 use std::fmt::{Debug, Display};
 use std::collections::{HashMap, HashSet};
 use std::hash::Hash;
+use std::time::Duration;
 use crate::proposition::Proposition;
 use crate::action::Action;
-use crate::pairset::pairs;
-use crate::layer::{Layer, MutexPairs};
+use crate::domain::{ActionIndex, Domain, PropositionIndex};
+use crate::error::Error;
+use crate::limits::SearchLimits;
+use crate::observer::ExtendedLayer;
+use crate::layer::{ActionLayerData, Layer, MutexPairs, PropositionLayerData};
+use crate::parallel;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 
 type LayerNumber = usize;
-pub type Solution<'a, ActionId, PropositionId> = Vec<HashSet<&'a Action<'a, ActionId, PropositionId>>>;
-
-#[derive(Debug)]
-pub struct PlanGraph<'a,
-                     ActionId: Eq + Hash + Ord + PartialOrd + Clone + Debug,
-                     PropositionId: Eq + Hash + Ord + PartialOrd + Clone + Debug + Display> {
-    pub goals: HashSet<&'a Proposition<PropositionId>>,
-    pub actions: HashSet<&'a Action<'a, ActionId, PropositionId>>,
-    pub layers: Vec<Layer<'a, ActionId, PropositionId>>,
-    pub mutex_props: HashMap<LayerNumber, MutexPairs<&'a Proposition<PropositionId>>>,
-    pub mutex_actions: HashMap<LayerNumber, MutexPairs<&'a Action<'a, ActionId, PropositionId>>>,
+pub type Solution<ActionId, PropositionId> = Vec<HashSet<Action<ActionId, PropositionId>>>;
+
+/// Cost and makespan of a `Solution`
+pub trait SolutionCost {
+    /// Returns the total cost of the actions in every step
+    fn cost(&self) -> u64;
+
+    /// Returns the number of steps with an action that isn't a
+    /// maintenance action
+    fn makespan(&self) -> usize;
+}
+
+impl<ActionId, PropositionId> SolutionCost for Solution<ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    fn cost(&self) -> u64 {
+        self.iter().flat_map(|step| step.iter().map(|a| a.cost)).sum()
+    }
+
+    fn makespan(&self) -> usize {
+        self.iter()
+            .filter(|step| step.iter().any(|a| a.get_action().is_ok()))
+            .count()
+    }
+}
+
+/// The cheapest plan found by `GraphPlan::search_cheapest`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CostOptimalPlan<ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    pub solution: Solution<ActionId, PropositionId>,
+    pub makespan: usize,
+    pub cost: u64,
+}
+
+impl<ActionId, PropositionId> CostOptimalPlan<ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    pub fn new(solution: Solution<ActionId, PropositionId>) -> Self {
+        CostOptimalPlan { makespan: solution.makespan(), cost: solution.cost(), solution }
+    }
+}
+
+/// With the `serde` feature a plangraph can be saved as a snapshot
+/// of its domain, layers and mutexes and loaded later to continue
+/// extending and searching it.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PlanGraph<ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    domain: Domain<ActionId, PropositionId>,
+    pub layers: Vec<Layer>,
+    pub mutex_props: HashMap<LayerNumber, MutexPairs<PropositionIndex>>,
+    pub mutex_actions: HashMap<LayerNumber, MutexPairs<ActionIndex>>,
 }
 
-impl<'a,
-     ActionId: Eq + Hash + Ord + PartialOrd + Clone + Debug,
-     PropositionId: Eq + Hash + Ord + PartialOrd + Clone + Debug + Display>
-    PlanGraph<'a, ActionId, PropositionId> {
-    pub fn new(initial_props: HashSet<&'a Proposition<PropositionId>>,
-               goals: HashSet<&'a Proposition<PropositionId>>,
-               actions: HashSet<&'a Action<ActionId, PropositionId>>) -> Self {
-        let init_layer = Layer::PropositionLayer(initial_props);
+impl<ActionId, PropositionId> PlanGraph<ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    /// Returns a new plangraph over the given actions as is. Note:
+    /// you probably want `from_domain` with a domain from
+    /// `GraphPlan::create_domain` which includes maintenance actions.
+    pub fn new(initial_props: HashSet<&Proposition<PropositionId>>,
+               goals: HashSet<&Proposition<PropositionId>>,
+               actions: HashSet<&Action<ActionId, PropositionId>>) -> Self {
+        PlanGraph::from_domain(Domain::new(
+            initial_props.into_iter().cloned(),
+            goals.into_iter().cloned(),
+            actions.into_iter().cloned(),
+        ))
+    }
+
+    pub fn from_domain(domain: Domain<ActionId, PropositionId>) -> Self {
+        let initial_props = domain.initial_props().iter().collect();
+        PlanGraph::from_state(domain, initial_props)
+    }
+
+    /// Returns a plangraph that starts from `props` rather than the
+    /// initial propositions of the domain
+    pub(crate) fn from_state(domain: Domain<ActionId, PropositionId>,
+                             props: PropositionLayerData) -> Self {
         PlanGraph {
-            goals,
-            actions,
-            layers: vec![init_layer],
+            domain,
+            layers: vec![Layer::PropositionLayer(props)],
             mutex_props: HashMap::new(),
             mutex_actions: HashMap::new()
         }
     }
 
+    /// Returns the domain the plangraph was built from
+    pub fn domain(&self) -> &Domain<ActionId, PropositionId> {
+        &self.domain
+    }
+
+    pub(crate) fn into_domain(self) -> Domain<ActionId, PropositionId> {
+        self.domain
+    }
+
     /// Extends the plangraph to depth i+1
     /// Inserts another action layer and proposition layer
-    pub fn extend(&mut self) -> &mut Self {
+    pub fn extend(&mut self) -> Result<&mut Self, Error> {
+        self.extend_within(&SearchLimits::default())
+    }
+
+    /// Same as `extend` but returns `Error::LimitReached` instead if
+    /// the plangraph is at its maximum depth or the search is
+    /// cancelled or past its deadline while computing the new layers.
+    /// The plangraph is left unchanged in that case.
+    pub fn extend_within(&mut self, limits: &SearchLimits) -> Result<&mut Self, Error> {
+        limits.check_depth(self.depth())?;
+        limits.check()?;
+
+        let domain = &self.domain;
         let layers = &self.layers;
-        let actions = &self.actions;
         let length = layers.len();
 
-        let layer = layers.last()
-            .expect("Tried to extend a plangraph that is not initialized. Please use PlanGraph::new instead of instantiating it as a struct.");
+        let layer = layers.last().ok_or(Error::Uninitialized)?;
+        if let Layer::ActionLayer(_) = layer {
+            return Err(Error::UnexpectedLayer(length - 1));
+        }
 
         let mutex_props = self.mutex_props.get(&(length - 1));
-        let actions_no_mutex_reqs = if let Some(mux) = mutex_props {
-            // Filter out the actions that we know are mutex
-            actions
-                .iter()
-                .filter(|a| {
-                    pairs(&a.reqs)
-                        .intersection(mux)
-                        .next()
-                        .is_none()})
-                .copied()
-                .collect::<HashSet<&'a Action<'a, ActionId, PropositionId>>>()
-        } else {
-            actions.to_owned()
-        };
+        let sets = domain.sets();
+        // Filter out the actions that we know are mutex
+        let actions_no_mutex_reqs = parallel::filter(domain.action_indices().collect(), |a| {
+            match mutex_props {
+                Some(mux) => !mux.any_pair_mutex(sets.req_set(*a)),
+                None => true,
+            }
+        });
+        let actions_no_mutex_reqs: ActionLayerData = actions_no_mutex_reqs.into_iter().collect();
 
         let action_layer = Layer::from_layer(
+            domain,
             &actions_no_mutex_reqs,
-            &layer
+            layer
         );
 
         let prop_layer = Layer::from_layer(
-            &actions,
+            domain,
+            &actions_no_mutex_reqs,
             &action_layer
         );
 
+        limits.check()?;
+
         let action_layer_actions = match &action_layer {
             Layer::ActionLayer(action_data) => action_data,
             _ => unreachable!("Tried to get actions from PropositionLayer")
         };
         let action_mutexes = Layer::action_mutexes(
+            domain,
             action_layer_actions,
             mutex_props
         );
-        self.mutex_actions.insert(
-            length,
-            action_mutexes.clone().into_iter().collect()
-        );
+
+        limits.check()?;
 
         let prop_layer_props = match &prop_layer {
             Layer::PropositionLayer(prop_data) => prop_data,
             _ => unreachable!("Tried to get propositions from ActionLayerr")
         };
         let prop_mutexes = Layer::proposition_mutexes(
+            domain,
             prop_layer_props,
             action_layer_actions,
-            Some(action_mutexes)
+            Some(&action_mutexes)
         );
-        self.mutex_props.insert(length, prop_mutexes);
+        self.mutex_actions.insert(length, action_mutexes);
+        self.mutex_props.insert(length + 1, prop_mutexes);
         self.layers.push(action_layer);
         self.layers.push(prop_layer);
 
-        self
+        Ok(self)
     }
 
     /// Returns the depth of the planning graph
@@ -112,41 +213,66 @@ impl<'a,
         }
     }
 
+    /// Describes the last two layers added by `extend`
+    pub(crate) fn extended_layer(&self, elapsed: Duration) -> ExtendedLayer {
+        let len = self.layers.len();
+        let size = |idx: Option<usize>| match idx.and_then(|i| self.layers.get(i)) {
+            Some(Layer::ActionLayer(actions)) => actions.len(),
+            Some(Layer::PropositionLayer(props)) => props.len(),
+            None => 0,
+        };
+        let props_idx = len.checked_sub(1);
+        let actions_idx = len.checked_sub(2);
+        ExtendedLayer {
+            depth: self.depth(),
+            propositions: size(props_idx),
+            actions: size(actions_idx),
+            mutex_props: props_idx.and_then(|i| self.mutex_props.get(&i)).map_or(0, |m| m.len()),
+            mutex_actions: actions_idx.and_then(|i| self.mutex_actions.get(&i)).map_or(0, |m| m.len()),
+            elapsed,
+        }
+    }
+
+    /// Returns the last proposition layer and its mutexes
+    fn last_props(&self) -> Result<(&PropositionLayerData, Option<&MutexPairs<PropositionIndex>>), Error> {
+        let last_layer_idx = self.layers.len().checked_sub(1).ok_or(Error::Uninitialized)?;
+        match &self.layers[last_layer_idx] {
+            Layer::PropositionLayer(props) => Ok((props, self.mutex_props.get(&last_layer_idx))),
+            Layer::ActionLayer(_) => Err(Error::UnexpectedLayer(last_layer_idx)),
+        }
+    }
+
+    /// Returns true if all goals exist in the last proposition layer
+    pub fn goals_present(&self) -> Result<bool, Error> {
+        let (props, _) = self.last_props()?;
+        Ok(self.domain.goals().iter().all(|g| props.contains(*g)))
+    }
+
+    /// Returns true if any pair of goals is mutex in the last
+    /// proposition layer
+    pub fn goals_mutex(&self) -> Result<bool, Error> {
+        let (_, mutexes) = self.last_props()?;
+        Ok(matches!(mutexes, Some(mx) if mx.any_pair_mutex(&self.domain.goals().iter().collect())))
+    }
+
     /// A solution is possible if all goals exist in the last
     /// proposition layer and are not mutex
-    pub fn has_possible_solution(&self) -> bool {
-        let goals = self.goals.clone();
-        let last_layer_idx = self.layers.len() - 1;
-
-        if let Some(prop_layer) = self.layers.get(last_layer_idx) {
-            match prop_layer {
-                Layer::PropositionLayer(props) => {
-                    let all_goals_present = &goals.is_subset(props);
-                    let mutexes = self.mutex_props
-                        .get(&last_layer_idx)
-                        .unwrap_or(&MutexPairs::new())
-                        .to_owned();
-                    let goal_pairs = pairs(&goals);
-                    let mutex_goals: HashSet<_> = mutexes
-                        .intersection(&goal_pairs)
-                        .collect();
-                    *all_goals_present && mutex_goals.is_empty()
-                },
-                _ => panic!("Tried to check for a solution in a Layer::Action")
-            }
-        } else {
-            false
-        }
+    pub fn has_possible_solution(&self) -> Result<bool, Error> {
+        Ok(self.goals_present()? && !self.goals_mutex()?)
     }
 
     /// The graph is considered to have "leveled off" when proposition
-    /// layer P and an adjacent proposition layer Q are equal
+    /// layer P and an adjacent proposition layer Q are equal and so
+    /// are their mutexes. Every layer after that will be the same.
     pub fn has_leveled_off(&self) -> bool {
         let len = self.layers.len();
-        if len > 2 as usize {
+        if len > 2 {
             let prop_layer = self.layers.get(len - 1).expect("Failed to get layer");
             let adjacent_prop_layer = self.layers.get(len - 3).expect("Failed to get adjacent layer");
-            prop_layer == adjacent_prop_layer
+            let no_mutexes = MutexPairs::new();
+            let mutexes = self.mutex_props.get(&(len - 1)).unwrap_or(&no_mutexes);
+            let adjacent_mutexes = self.mutex_props.get(&(len - 3)).unwrap_or(&no_mutexes);
+            prop_layer == adjacent_prop_layer && mutexes == adjacent_mutexes
         } else {
             false
         }
@@ -158,27 +284,147 @@ mod plangraph_test {
     use super::*;
     #[test]
     fn extend_plangraph_works() {
-        let mut plangraph: PlanGraph<&'static str, &'static str> = PlanGraph::new(
+        let mut plangraph: PlanGraph<&str, &str> = PlanGraph::new(
             fragset!{},
             fragset!{},
             fragset!{},
         );
 
         assert_eq!(plangraph.depth(), 0);
-        plangraph.extend();
+        plangraph.extend().unwrap();
         assert_eq!(plangraph.depth(), 1)
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn has_leveled_off_works() {
-        let mut plangraph: PlanGraph<&'static str, &'static str> = PlanGraph::new(
+        let mut plangraph: PlanGraph<&str, &str> = PlanGraph::new(
             fragset!{},
             fragset!{},
             fragset!{},
         );
 
         assert_eq!(plangraph.has_leveled_off(), false);
-        plangraph.extend();
+        plangraph.extend().unwrap();
         assert_eq!(plangraph.has_leveled_off(), true);
     }
+
+    #[test]
+    fn has_leveled_off_waits_for_mutexes() {
+        let p1 = Proposition::from("tired");
+        let not_p1 = p1.negate();
+        let a1 = Action::new_maintenance(&p1);
+        let a2 = Action::new_maintenance(&not_p1);
+        let mut plangraph: PlanGraph<&str, &str> = PlanGraph::new(
+            fragset!{[&p1, &not_p1]},
+            fragset!{},
+            fragset!{[&a1, &a2]},
+        );
+
+        // The propositions are the same but are now mutex
+        plangraph.extend().unwrap();
+        assert_eq!(plangraph.layers[0], plangraph.layers[2]);
+        assert!(!plangraph.has_leveled_off());
+
+        plangraph.extend().unwrap();
+        assert!(plangraph.has_leveled_off());
+    }
+
+    #[test]
+    fn uninitialized_plangraph_errors() {
+        let mut plangraph: PlanGraph<&str, &str> = PlanGraph::new(
+            fragset!{},
+            fragset!{},
+            fragset!{},
+        );
+        plangraph.layers.clear();
+
+        assert_eq!(plangraph.extend().err(), Some(Error::Uninitialized));
+        assert_eq!(plangraph.has_possible_solution(), Err(Error::Uninitialized));
+    }
+
+    #[test]
+    fn extend_within_stops_at_limits() {
+        use crate::limits::Limit;
+        let mut plangraph: PlanGraph<&str, &str> = PlanGraph::new(
+            fragset!{},
+            fragset!{},
+            fragset!{},
+        );
+
+        let limits = SearchLimits::new().max_depth(1).clone();
+        plangraph.extend_within(&limits).unwrap();
+        assert_eq!(plangraph.extend_within(&limits).err(), Some(Error::LimitReached(Limit::Depth)));
+
+        let cancel = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(true));
+        let limits = SearchLimits::new().cancel(cancel).clone();
+        assert_eq!(plangraph.extend_within(&limits).err(), Some(Error::LimitReached(Limit::Cancelled)));
+        assert_eq!(plangraph.depth(), 1);
+    }
+
+    /// Packages that can each be moved between any two locations,
+    /// enough actions for mutexes to be computed in parallel
+    fn logistics(packages: usize, locations: usize) -> PlanGraph<String, String> {
+        let at = |p: usize, l: usize| Proposition::from(format!("package{}_location{}", p, l));
+        let mut actions = Vec::new();
+        for p in 0..packages {
+            for from in 0..locations {
+                for to in (0..locations).filter(|to| *to != from) {
+                    let (here, there) = (at(p, from), at(p, to));
+                    let not_here = here.negate();
+                    actions.push(Action::new(format!("move{}_{}_{}", p, from, to),
+                                             fragset!{[&here]},
+                                             fragset!{[&there, &not_here]}));
+                }
+            }
+        }
+        let initial_props: Vec<_> = (0..packages).map(|p| at(p, 0)).collect();
+        let domain = crate::GraphPlan::create_domain(
+            initial_props.iter().collect(),
+            fragset!{},
+            actions.iter().collect(),
+        );
+        PlanGraph::from_domain(domain)
+    }
+
+    #[test]
+    fn extends_large_domains_the_same_with_or_without_rayon() {
+        let mut plangraph = logistics(3, 6);
+        for _ in 0..3 {
+            plangraph.extend().unwrap();
+        }
+        // The same as without the feature, see the test below for
+        // the parallel path
+        let sizes: Vec<usize> = (1..=6).map(|i| match &plangraph.layers[i] {
+            Layer::ActionLayer(actions) => actions.len(),
+            Layer::PropositionLayer(props) => props.len(),
+        }).collect();
+        assert_eq!(sizes, vec![18, 21, 111, 36, 126, 36]);
+        let mutexes: Vec<usize> = vec![
+            plangraph.mutex_actions[&1].len(), plangraph.mutex_props[&2].len(),
+            plangraph.mutex_actions[&3].len(), plangraph.mutex_props[&4].len(),
+            plangraph.mutex_actions[&5].len(), plangraph.mutex_props[&6].len(),
+        ];
+        assert_eq!(mutexes, vec![45, 48, 1923, 93, 2118, 63]);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn extends_the_same_in_parallel() {
+        let extend = || {
+            let mut plangraph = logistics(3, 6);
+            for _ in 0..3 {
+                plangraph.extend().unwrap();
+            }
+            plangraph
+        };
+        let sequential = crate::parallel::sequential(extend);
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+        let (parallel, calls) = pool.install(|| (extend(), crate::parallel::parallel_calls()));
+        // The layers are big enough to be split over threads
+        assert!(calls > 0);
+        assert_eq!(parallel.layers, sequential.layers);
+        assert_eq!(parallel.mutex_actions, sequential.mutex_actions);
+        assert_eq!(parallel.mutex_props, sequential.mutex_props);
+    }
 }
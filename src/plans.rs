@@ -0,0 +1,123 @@
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+use log::debug;
+use crate::action::Action;
+use crate::error::Error;
+use crate::limits::SearchBudget;
+use crate::plangraph::Solution;
+use crate::solver::{GraphPlanSolver, PlanCursor};
+use crate::GraphPlan;
+
+
+/// The steps of a plan that take an action, used to tell plans apart
+type PlanKey<ActionId, PropositionId> = Vec<BTreeSet<Action<ActionId, PropositionId>>>;
+
+/// Iterator over the distinct plans of a problem, shortest first. See
+/// `GraphPlan::plans`.
+pub struct Plans<'a, ActionId, PropositionId, Solver>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+    Solver: GraphPlanSolver<ActionId, PropositionId>,
+{
+    graphplan: &'a mut GraphPlan<ActionId, PropositionId>,
+    budget: SearchBudget,
+    cursor: PlanCursor,
+    seen: HashSet<PlanKey<ActionId, PropositionId>>,
+    // Whether the current depth had a plan that wasn't seen before
+    found_new: bool,
+    started: bool,
+    done: bool,
+    solver: Solver,
+}
+
+impl<'a, ActionId, PropositionId, Solver> Plans<'a, ActionId, PropositionId, Solver>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+    Solver: GraphPlanSolver<ActionId, PropositionId>,
+{
+    pub(crate) fn new(graphplan: &'a mut GraphPlan<ActionId, PropositionId>, solver: Solver) -> Self {
+        let budget = SearchBudget::new(graphplan.limits.clone());
+        Plans {
+            graphplan,
+            budget,
+            cursor: PlanCursor::new(),
+            seen: HashSet::new(),
+            found_new: false,
+            started: false,
+            done: false,
+            solver,
+        }
+    }
+
+    /// Returns the plan if it differs from every plan yielded so far
+    /// in the actions it takes. Maintenance actions and steps without
+    /// actions don't count.
+    fn unseen(&mut self, plan: Solution<ActionId, PropositionId>) -> Option<Solution<ActionId, PropositionId>> {
+        let key = plan.iter()
+            .map(|step| step.iter().filter(|a| a.get_action().is_ok()).cloned().collect::<BTreeSet<_>>())
+            .filter(|step| !step.is_empty())
+            .collect();
+        if self.seen.insert(key) {
+            self.found_new = true;
+            Some(plan)
+        } else {
+            debug!("Skipping plan already found {:?}", plan);
+            None
+        }
+    }
+
+    fn next_plan(&mut self) -> Result<Option<Solution<ActionId, PropositionId>>, Error> {
+        if !self.started {
+            self.started = true;
+            let first = self.graphplan.search_within(&mut self.solver, &self.budget, &mut ())?;
+            return Ok(self.unseen(first));
+        }
+
+        loop {
+            let found = self.solver.next_plan(&self.graphplan.plangraph, &self.budget, &mut (), &mut self.cursor)?;
+            match found {
+                Some(plan) => {
+                    if let Some(plan) = self.unseen(plan) {
+                        return Ok(Some(plan));
+                    }
+                },
+                None => {
+                    let graphplan = &mut *self.graphplan;
+                    let depth = graphplan.plangraph.depth();
+                    // A depth without new plans only ends the search
+                    // when there is no maximum depth to go up to
+                    let unlimited = graphplan.limits.max_depth.is_none();
+                    if (unlimited && !self.found_new) || graphplan.limits.check_depth(depth).is_err() {
+                        debug!("No more plans after depth {}", depth);
+                        return Ok(None);
+                    }
+                    self.found_new = false;
+                    graphplan.plangraph.extend_within(&graphplan.limits)?;
+                },
+            }
+        }
+    }
+}
+
+impl<'a, ActionId, PropositionId, Solver> Iterator for Plans<'a, ActionId, PropositionId, Solver>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+    Solver: GraphPlanSolver<ActionId, PropositionId>,
+{
+    type Item = Result<Solution<ActionId, PropositionId>, Error>;
+
+    /// Yields the next plan or the error that stopped the search,
+    /// after which iteration ends
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.next_plan().transpose();
+        self.done = !matches!(result, Some(Ok(_)));
+        result
+    }
+}
@@ -0,0 +1,282 @@
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::Hash;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+use log::debug;
+use crate::error::Error;
+use crate::limits::SearchBudget;
+use crate::observer::SearchObserver;
+use crate::plangraph::{PlanGraph, Solution};
+use crate::solver::{GraphPlanSolver, Nogoods};
+use crate::validate::validate_plan;
+
+
+/// How often the race checks whether the caller's limits were hit
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Races several solvers on threads over the same plangraph. The
+/// first valid plan found is returned and the other solvers are
+/// cancelled, see `winner` for which solver found it. When none of
+/// them finds a plan it waits for all of them, so the nogoods of a
+/// solver that records them are complete and `GraphPlan::search` can
+/// still prove a problem unsolvable.
+///
+/// Each solver gets what is left of the budget and the nodes and
+/// backtracks of all of them are counted against it. The solvers
+/// don't report to the observer since it can't be shared between
+/// threads.
+pub struct PortfolioSolver<ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    names: Vec<String>,
+    solvers: Vec<Box<dyn GraphPlanSolver<ActionId, PropositionId> + Send>>,
+    winner: Option<usize>,
+}
+
+impl<ActionId, PropositionId> PortfolioSolver<ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    pub fn new() -> Self {
+        PortfolioSolver { names: Vec::new(), solvers: Vec::new(), winner: None }
+    }
+
+    /// Adds a solver to the race under a name to report if it wins
+    pub fn add<Solver>(&mut self, name: &str, solver: Solver) -> &mut Self
+    where
+        Solver: GraphPlanSolver<ActionId, PropositionId> + Send + 'static,
+    {
+        self.names.push(name.to_string());
+        self.solvers.push(Box::new(solver));
+        self
+    }
+
+    /// Returns the name of the solver that found the last plan
+    pub fn winner(&self) -> Option<&str> {
+        self.winner.map(|idx| self.names[idx].as_str())
+    }
+}
+
+impl<ActionId, PropositionId> Default for PortfolioSolver<ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    fn default() -> Self {
+        PortfolioSolver::new()
+    }
+}
+
+impl<ActionId, PropositionId> Debug for PortfolioSolver<ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("PortfolioSolver")
+            .field("solvers", &self.names)
+            .field("winner", &self.winner())
+            .finish()
+    }
+}
+
+impl<ActionId, PropositionId> GraphPlanSolver<ActionId, PropositionId> for PortfolioSolver<ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone + Send + Sync,
+    PropositionId: Debug + Display + Hash + Ord + Clone + Send + Sync,
+{
+    fn search(&mut self, plangraph: &PlanGraph<ActionId, PropositionId>) -> Result<Option<Solution<ActionId, PropositionId>>, Error> {
+        self.search_within(plangraph, &SearchBudget::default(), &mut ())
+    }
+
+    fn search_within(&mut self,
+                     plangraph: &PlanGraph<ActionId, PropositionId>,
+                     budget: &SearchBudget,
+                     _observer: &mut dyn SearchObserver)
+                     -> Result<Option<Solution<ActionId, PropositionId>>, Error> {
+        budget.check()?;
+        self.winner = None;
+        let domain = plangraph.domain();
+        let stop = Arc::new(AtomicBool::new(false));
+        let limits = budget.remaining().cancel(stop.clone()).clone();
+
+        let (sender, receiver) = mpsc::channel();
+        let mut results: Vec<_> = self.solvers.iter().map(|_| None).collect();
+        let mut winner = None;
+        thread::scope(|scope| {
+            for (idx, solver) in self.solvers.iter_mut().enumerate() {
+                let sender = sender.clone();
+                let limits = limits.clone();
+                scope.spawn(move || {
+                    let budget = SearchBudget::new(limits);
+                    let result = solver.search_within(plangraph, &budget, &mut ());
+                    // The receiver outlives every thread
+                    sender.send((idx, result, budget)).ok();
+                });
+            }
+            drop(sender);
+
+            loop {
+                match receiver.recv_timeout(POLL_INTERVAL) {
+                    Ok((idx, result, used)) => {
+                        budget.charge(&used);
+                        if let (None, Ok(Some(plan))) = (winner, &result) {
+                            match validate_plan(domain, plan) {
+                                Ok(()) => {
+                                    winner = Some(idx);
+                                    stop.store(true, Ordering::Relaxed);
+                                },
+                                Err(e) => debug!("Ignoring invalid plan from {}: {:?}", self.names[idx], e),
+                            }
+                        }
+                        results[idx] = Some(result);
+                    },
+                    // Cancelling or the deadline of the caller's
+                    // limits stops every solver
+                    Err(RecvTimeoutError::Timeout) => if budget.limits().check().is_err() {
+                        stop.store(true, Ordering::Relaxed);
+                    },
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+        });
+
+        if let Some(idx) = winner {
+            debug!("{} found a plan first", self.names[idx]);
+            self.winner = winner;
+            return results[idx].take().expect("Winner has a result");
+        }
+        budget.check()?;
+        // Without a plan the first solver that stopped early says why
+        for result in results.into_iter().flatten() {
+            result?;
+        }
+        Ok(None)
+    }
+
+    /// Returns the nogoods of the first solver that records them
+    fn nogoods(&self) -> Option<&Nogoods> {
+        self.solvers.iter().find_map(|solver| solver.nogoods())
+    }
+
+    fn reset(&mut self) {
+        self.winner = None;
+        for solver in &mut self.solvers {
+            solver.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod portfolio_test {
+    use super::*;
+    use crate::action::Action;
+    use crate::domain::Domain;
+    use crate::fixtures::{morning, token};
+    use crate::proposition::Proposition;
+    use crate::{GraphPlan, HeuristicSearchSolver, SatSolver, SimpleSolver};
+
+    type Portfolio = PortfolioSolver<&'static str, &'static str>;
+
+    /// Never finds a plan, only stops when its limits are hit
+    struct Stubborn;
+
+    impl GraphPlanSolver<&'static str, &'static str> for Stubborn {
+        fn search(&mut self, plangraph: &PlanGraph<&'static str, &'static str>)
+                  -> Result<Option<Solution<&'static str, &'static str>>, Error> {
+            self.search_within(plangraph, &SearchBudget::default(), &mut ())
+        }
+
+        fn search_within(&mut self,
+                         _plangraph: &PlanGraph<&'static str, &'static str>,
+                         budget: &SearchBudget,
+                         _observer: &mut dyn SearchObserver)
+                         -> Result<Option<Solution<&'static str, &'static str>>, Error> {
+            loop {
+                budget.check()?;
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+    }
+
+    /// Claims that doing nothing meets the goals
+    struct Liar;
+
+    impl GraphPlanSolver<&'static str, &'static str> for Liar {
+        fn search(&mut self, _plangraph: &PlanGraph<&'static str, &'static str>)
+                  -> Result<Option<Solution<&'static str, &'static str>>, Error> {
+            Ok(Some(vec![]))
+        }
+    }
+
+    #[test]
+    fn returns_the_first_valid_plan() {
+        let domain = morning();
+        let mut portfolio = Portfolio::new();
+        portfolio.add("stubborn", Stubborn).add("liar", Liar).add("simple", SimpleSolver::default());
+        let plan = GraphPlan::from_domain(&domain).search_with(&mut portfolio).unwrap();
+        assert_eq!(validate_plan(&domain, &plan), Ok(()));
+        assert_eq!(portfolio.winner(), Some("simple"));
+    }
+
+    #[test]
+    fn races_every_kind_of_solver() {
+        let domain = morning();
+        let mut portfolio = Portfolio::new();
+        portfolio
+            .add("simple", SimpleSolver::default())
+            .add("sat", SatSolver)
+            .add("heuristic", HeuristicSearchSolver::default());
+        let plan = GraphPlan::from_domain(&domain).search_with(&mut portfolio).unwrap();
+        assert_eq!(validate_plan(&domain, &plan), Ok(()));
+        assert!(portfolio.winner().is_some());
+    }
+
+    #[test]
+    fn proves_unsolvable_with_the_nogoods_of_its_solvers() {
+        // Only two of a, b and c can be made with the one token
+        let domain = token();
+
+        let mut portfolio = Portfolio::new();
+        portfolio.add("sat", SatSolver).add("simple", SimpleSolver::default());
+        assert_eq!(GraphPlan::from_domain(&domain).search_with(&mut portfolio), Err(Error::LeveledOff));
+        assert_eq!(portfolio.winner(), None);
+    }
+
+    #[test]
+    fn stops_at_the_callers_limits() {
+        use crate::limits::{Limit, SearchLimits};
+        let cancel = Arc::new(AtomicBool::new(false));
+        let mut pg = GraphPlan::from_domain(&morning());
+        pg.set_limits(SearchLimits::new().cancel(cancel.clone()).clone());
+
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            cancel.store(true, Ordering::Relaxed);
+        });
+        let mut portfolio = Portfolio::new();
+        portfolio.add("stubborn", Stubborn);
+        assert_eq!(pg.search_with(&mut portfolio), Err(Error::LimitReached(Limit::Cancelled)));
+        canceller.join().unwrap();
+    }
+
+    #[test]
+    fn shares_plangraphs_between_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Proposition<String>>();
+        assert_send_sync::<Action<String, String>>();
+        assert_send_sync::<Domain<String, String>>();
+        assert_send_sync::<PlanGraph<String, String>>();
+        assert_send_sync::<Solution<String, String>>();
+
+        fn assert_send<T: Send>() {}
+        assert_send::<PortfolioSolver<String, String>>();
+    }
+}
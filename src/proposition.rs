@@ -1,11 +1,13 @@
 use std::fmt;
 use std::fmt::{Debug, Display};
 use std::hash::{Hash, Hasher};
-use std::collections::hash_map::DefaultHasher;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 
 
-#[derive(Eq, Ord, PartialOrd, Clone)]
+#[derive(PartialEq, Eq, Ord, PartialOrd, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Proposition<PropositionId> where PropositionId: Hash {
     pub id: PropositionId,
     pub negation: bool,
@@ -24,16 +26,6 @@ impl<PropositionId: Hash> Hash for Proposition<PropositionId> {
     }
 }
 
-impl<PropositionId: Hash> PartialEq for Proposition<PropositionId> {
-    fn eq(&self, other: &Self) -> bool {
-        let mut hasher_left = DefaultHasher::new();
-        let mut hasher_right = DefaultHasher::new();
-        self.hash(&mut hasher_left);
-        other.hash(&mut hasher_right);
-        hasher_left.finish() == hasher_right.finish()
-    }
-}
-
 impl<PropositionId: Clone + PartialEq + Hash> Proposition<PropositionId> {
     pub fn new(id: PropositionId, negation: bool) -> Self {
         Proposition {id, negation}
@@ -44,7 +36,7 @@ impl<PropositionId: Clone + PartialEq + Hash> Proposition<PropositionId> {
     }
 
     pub fn is_negation(&self, prop: &Self) -> bool {
-        prop.id == self.id && prop.negation == !self.negation
+        prop.id == self.id && prop.negation != self.negation
     }
 }
 
@@ -79,6 +71,7 @@ mod proposition_test {
     }
 
     #[test]
+    #[allow(clippy::bool_comparison)]
     fn propositions_can_be_negated() {
         // Sanity check
         assert_eq!(Proposition::from("test"), Proposition::from("test"));
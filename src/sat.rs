@@ -0,0 +1,265 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Debug, Display, Write};
+use std::hash::Hash;
+use log::debug;
+use crate::action::Action;
+use crate::cdcl::{Cnf, Literal};
+use crate::domain::{ActionIndex, PropositionIndex};
+use crate::error::Error;
+use crate::limits::SearchBudget;
+use crate::observer::SearchObserver;
+use crate::layer::Layer;
+use crate::pairset::PairSet;
+use crate::plangraph::{PlanGraph, Solution};
+use crate::solver::GraphPlanSolver;
+
+
+/// Solves the plangraph by encoding it as a satisfiability problem
+/// in the style of Blackbox and handing it to a bundled CDCL solver.
+/// It does not record unsolvable goal sets so `GraphPlan::search`
+/// returns `SearchExhausted` rather than `LeveledOff` when there is
+/// no plan.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SatSolver;
+
+type LayerNumber = usize;
+
+/// A plangraph encoded as CNF with a variable for every proposition
+/// and action in every layer
+struct Encoding {
+    cnf: Cnf,
+    props: HashMap<(LayerNumber, PropositionIndex), Literal>,
+    actions: HashMap<(LayerNumber, ActionIndex), Literal>,
+}
+
+impl Encoding {
+    /// Encodes the plangraph with these clauses:
+    ///
+    /// - the initial propositions hold
+    /// - the goals hold in the last layer
+    /// - an action implies its preconditions in the layer before it
+    /// - a proposition implies one of the actions that add it in the
+    ///   layer before it
+    /// - mutex actions and mutex propositions are not both true
+    fn new<ActionId, PropositionId>(plangraph: &PlanGraph<ActionId, PropositionId>) -> Result<Self, Error>
+    where
+        ActionId: Debug + Hash + Ord + Clone,
+        PropositionId: Debug + Display + Hash + Ord + Clone,
+    {
+        if plangraph.layers.len() < 3 {
+            return Err(Error::NotExtended);
+        }
+        let domain = plangraph.domain();
+        let mut cnf = Cnf::new();
+        let mut props = HashMap::new();
+        let mut actions = HashMap::new();
+
+        // Number the variables in layer order so the formula is the
+        // same every time
+        for (idx, layer) in plangraph.layers.iter().enumerate() {
+            match layer {
+                Layer::PropositionLayer(layer_props) => for p in layer_props {
+                    props.insert((idx, p), cnf.new_var());
+                },
+                Layer::ActionLayer(layer_actions) => for a in layer_actions {
+                    actions.insert((idx, a), cnf.new_var());
+                },
+            }
+        }
+
+        for (idx, layer) in plangraph.layers.iter().enumerate() {
+            match layer {
+                Layer::PropositionLayer(layer_props) if idx == 0 => for p in layer_props {
+                    cnf.add_clause(vec![props[&(idx, p)]]);
+                },
+                Layer::PropositionLayer(layer_props) => {
+                    let adders = match &plangraph.layers[idx - 1] {
+                        Layer::ActionLayer(adders) => adders,
+                        Layer::PropositionLayer(_) => return Err(Error::UnexpectedLayer(idx - 1)),
+                    };
+                    for p in layer_props {
+                        let support = adders.iter()
+                            .filter(|a| domain.effect_set(*a).contains(p))
+                            .map(|a| actions[&(idx - 1, a)]);
+                        cnf.add_clause(std::iter::once(-props[&(idx, p)]).chain(support));
+                    }
+                },
+                Layer::ActionLayer(layer_actions) => for a in layer_actions {
+                    for p in domain.reqs(a) {
+                        let req = props.get(&(idx - 1, *p)).ok_or(Error::MissingLayer(idx - 1))?;
+                        cnf.add_clause(vec![-actions[&(idx, a)], *req]);
+                    }
+                },
+            }
+        }
+
+        let last = plangraph.layers.len() - 1;
+        for goal in domain.goals() {
+            // A goal missing from the last layer leaves an empty
+            // clause which makes the formula unsatisfiable
+            cnf.add_clause(props.get(&(last, *goal)).copied());
+        }
+
+        // Sort the mutexes so the formula is the same every time
+        let mut mutex_actions: Vec<(Literal, Literal)> = plangraph.mutex_actions.iter()
+            .flat_map(|(idx, pairs)| pairs.iter().filter_map(|PairSet(a, b)| {
+                Some((*actions.get(&(*idx, a))?, *actions.get(&(*idx, b))?))
+            }).collect::<Vec<_>>())
+            .collect();
+        let mut mutex_props: Vec<(Literal, Literal)> = plangraph.mutex_props.iter()
+            .flat_map(|(idx, pairs)| pairs.iter().filter_map(|PairSet(p, q)| {
+                Some((*props.get(&(*idx, p))?, *props.get(&(*idx, q))?))
+            }).collect::<Vec<_>>())
+            .collect();
+        mutex_actions.append(&mut mutex_props);
+        let mut mutexes: Vec<(Literal, Literal)> = mutex_actions.into_iter()
+            .map(|(x, y)| (x.min(y), x.max(y)))
+            .collect();
+        mutexes.sort_unstable();
+        mutexes.dedup();
+        for (x, y) in mutexes {
+            cnf.add_clause(vec![-x, -y]);
+        }
+
+        debug!("Encoded plangraph as {} variables and {} clauses",
+               cnf.num_vars, cnf.clauses.len());
+        Ok(Encoding { cnf, props, actions })
+    }
+}
+
+impl SatSolver {
+    /// Returns the CNF encoding of the plangraph so it can be
+    /// inspected or solved elsewhere
+    pub fn to_cnf<ActionId, PropositionId>(plangraph: &PlanGraph<ActionId, PropositionId>) -> Result<Cnf, Error>
+    where
+        ActionId: Debug + Hash + Ord + Clone,
+        PropositionId: Debug + Display + Hash + Ord + Clone,
+    {
+        Encoding::new(plangraph).map(|encoding| encoding.cnf)
+    }
+
+    /// Renders the CNF encoding of the plangraph in DIMACS format for
+    /// external SAT solvers. Comment lines name the proposition or
+    /// action and layer of each variable.
+    pub fn to_dimacs<ActionId, PropositionId>(plangraph: &PlanGraph<ActionId, PropositionId>) -> Result<String, Error>
+    where
+        ActionId: Debug + Hash + Ord + Clone,
+        PropositionId: Debug + Display + Hash + Ord + Clone,
+    {
+        let encoding = Encoding::new(plangraph)?;
+        let domain = plangraph.domain();
+
+        let mut names: Vec<(Literal, String)> = encoding.props.iter()
+            .map(|((idx, p), var)| (*var, format!("l{} {:?}", idx, domain.proposition(*p))))
+            .chain(encoding.actions.iter()
+                   .map(|((idx, a), var)| (*var, format!("l{} {:?}", idx, domain.action(*a).id))))
+            .collect();
+        names.sort();
+
+        let mut out = String::new();
+        for (var, name) in names {
+            writeln!(out, "c {} {}", var, name).unwrap();
+        }
+        out.push_str(&encoding.cnf.to_dimacs());
+        Ok(out)
+    }
+}
+
+impl<ActionId, PropositionId> GraphPlanSolver<ActionId, PropositionId> for SatSolver
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    fn search(&mut self, plangraph: &PlanGraph<ActionId, PropositionId>) -> Result<Option<Solution<ActionId, PropositionId>>, Error> {
+        self.search_within(plangraph, &SearchBudget::default(), &mut ())
+    }
+
+    fn search_within(&mut self,
+                     plangraph: &PlanGraph<ActionId, PropositionId>,
+                     budget: &SearchBudget,
+                     _observer: &mut dyn SearchObserver)
+                     -> Result<Option<Solution<ActionId, PropositionId>>, Error> {
+        let encoding = Encoding::new(plangraph)?;
+        budget.check()?;
+        let model = match encoding.cnf.solve_within(budget)? {
+            Some(model) => model,
+            None => {
+                debug!("Encoding is unsatisfiable at depth {}", plangraph.depth());
+                return Ok(None);
+            },
+        };
+
+        let domain = plangraph.domain();
+        let is_true = |var: Literal| model[var as usize - 1];
+        let plan = plangraph.layers.iter()
+            .enumerate()
+            .filter_map(|(idx, layer)| match layer {
+                Layer::ActionLayer(actions) => Some(
+                    actions.iter()
+                        .filter(|a| !domain.is_maintenance(*a))
+                        .filter(|a| is_true(encoding.actions[&(idx, *a)]))
+                        .map(|a| domain.action(a).clone())
+                        .collect::<HashSet<Action<ActionId, PropositionId>>>()
+                ),
+                Layer::PropositionLayer(_) => None,
+            })
+            .collect();
+        Ok(Some(plan))
+    }
+}
+
+#[cfg(test)]
+mod sat_test {
+    use super::*;
+    use crate::fixtures::{kitchen, morning};
+    use crate::validate::validate_plan;
+    use crate::{GraphPlan, SimpleSolver};
+
+    #[test]
+    fn finds_valid_plans() {
+        let domain = morning();
+        let mut pg = GraphPlan::from_domain(&domain);
+        let solution = pg.search::<SatSolver>().unwrap();
+        assert_eq!(validate_plan(&domain, &solution), Ok(()));
+
+        let expected = GraphPlan::from_domain(&domain).search::<SimpleSolver>().unwrap();
+        assert_eq!(solution, expected);
+    }
+
+    #[test]
+    fn unsatisfiable_before_goals_are_reachable() {
+        let mut pg = PlanGraph::from_domain(morning());
+        pg.extend().unwrap();
+        assert_eq!(SatSolver.search(&pg), Ok(None));
+        pg.extend().unwrap();
+        assert!(SatSolver.search(&pg).unwrap().is_some());
+    }
+
+    #[test]
+    fn solves_kitchen_example() {
+        let domain = kitchen();
+        let mut pg = GraphPlan::from_domain(&domain);
+        let plan = pg.search::<SatSolver>().unwrap();
+        assert_eq!(plan.len(), 9);
+        assert_eq!(validate_plan(&domain, &plan), Ok(()));
+    }
+
+    #[test]
+    fn errors_on_unextended_plangraph() {
+        let pg = PlanGraph::from_domain(morning());
+        assert_eq!(SatSolver.search(&pg), Err(Error::NotExtended));
+    }
+
+    #[test]
+    fn writes_dimacs() {
+        let mut pg = PlanGraph::from_domain(morning());
+        pg.extend().unwrap();
+        let cnf = SatSolver::to_cnf(&pg).unwrap();
+        let dimacs = SatSolver::to_dimacs(&pg).unwrap();
+
+        assert!(dimacs.contains("c 1 l0 P:dog needs to pee\n"));
+        assert!(dimacs.contains(&format!("p cnf {} {}\n", cnf.num_vars, cnf.clauses.len())));
+        assert_eq!(dimacs.lines().filter(|l| l.starts_with("c ")).count(), cnf.num_vars);
+        assert_eq!(dimacs, SatSolver::to_dimacs(&pg).unwrap());
+    }
+}
@@ -0,0 +1,446 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use crate::proposition::Proposition;
+use crate::action::Action;
+
+
+/// The root of the type hierarchy. Every type is an `object`.
+pub const OBJECT_TYPE: &str = "object";
+
+/// Returns the proposition for a predicate applied to objects. Ids
+/// are the predicate followed by its objects separated by spaces
+/// such as `on arm table`.
+pub fn proposition(predicate: &str, objects: &[&str]) -> Proposition<String> {
+    let id = std::iter::once(predicate)
+        .chain(objects.iter().copied())
+        .collect::<Vec<_>>()
+        .join(" ");
+    Proposition::new(id, false)
+}
+
+/// A typed parameter of an action schema such as `?loc - location`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Parameter {
+    pub name: String,
+    pub kind: String,
+}
+
+impl Parameter {
+    pub fn new(name: &str, kind: &str) -> Self {
+        Parameter { name: name.to_string(), kind: kind.to_string() }
+    }
+}
+
+/// A predicate applied to terms that becomes a `Proposition` once
+/// the parameters are bound. Terms that start with `?` refer to
+/// parameters of the schema, anything else is an object. The
+/// predicate `=` is built in and only holds when both terms are
+/// bound to the same object.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Template {
+    pub predicate: String,
+    pub terms: Vec<String>,
+    pub negation: bool,
+}
+
+impl Template {
+    pub fn new(predicate: &str, terms: &[&str]) -> Self {
+        Template {
+            predicate: predicate.to_string(),
+            terms: terms.iter().map(|t| t.to_string()).collect(),
+            negation: false,
+        }
+    }
+
+    pub fn negate(&self) -> Self {
+        Template { negation: !self.negation, ..self.clone() }
+    }
+
+    fn is_equality(&self) -> bool {
+        self.predicate == "="
+    }
+
+    /// Returns the object bound to each term or `None` if a
+    /// parameter is not bound yet
+    fn objects<'b>(&'b self, binding: &HashMap<&str, &'b str>) -> Option<Vec<&'b str>> {
+        self.terms.iter()
+            .map(|t| if t.starts_with('?') {
+                binding.get(t.as_str()).copied()
+            } else {
+                Some(t.as_str())
+            })
+            .collect()
+    }
+
+    fn ground(&self, binding: &HashMap<&str, &str>) -> Option<Proposition<String>> {
+        let objects = self.objects(binding)?;
+        let prop = proposition(&self.predicate, &objects);
+        Some(if self.negation { prop.negate() } else { prop })
+    }
+}
+
+/// A lifted action with typed parameters. Preconditions and effects
+/// are templates over the parameters which are expanded into an
+/// `Action` for each binding of objects by a `Grounder`.
+///
+/// ## Example
+///
+/// ```
+/// use graphplan::schema::{ActionSchema, Parameter, Template};
+///
+/// let mv = ActionSchema::new(
+///     "move",
+///     vec![Parameter::new("?from", "room"), Parameter::new("?to", "room")],
+///     vec![Template::new("at", &["?from"]), Template::new("door", &["?from", "?to"])],
+///     vec![Template::new("at", &["?to"]), Template::new("at", &["?from"]).negate()],
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActionSchema {
+    pub name: String,
+    pub parameters: Vec<Parameter>,
+    pub reqs: Vec<Template>,
+    pub effects: Vec<Template>,
+    pub conditional_effects: Vec<ConditionalTemplate>,
+}
+
+impl ActionSchema {
+    pub fn new(name: &str,
+               parameters: Vec<Parameter>,
+               reqs: Vec<Template>,
+               effects: Vec<Template>) -> Self {
+        ActionSchema {
+            name: name.to_string(),
+            parameters,
+            reqs,
+            effects,
+            conditional_effects: Vec::new(),
+        }
+    }
+
+    /// Adds effects that only happen if all of `conditions` hold,
+    /// see `Action::when`
+    pub fn when(&mut self, conditions: Vec<Template>, effects: Vec<Template>) -> &mut Self {
+        self.conditional_effects.push(ConditionalTemplate { conditions, effects });
+        self
+    }
+}
+
+/// A conditional effect of an `ActionSchema`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConditionalTemplate {
+    pub conditions: Vec<Template>,
+    pub effects: Vec<Template>,
+}
+
+/// Ground conditions and the effects that happen if they hold
+type GroundConditionalEffect = (BTreeSet<Proposition<String>>, BTreeSet<Proposition<String>>);
+
+/// An action with all of its parameters bound to objects
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct GroundAction {
+    id: String,
+    reqs: BTreeSet<Proposition<String>>,
+    effects: BTreeSet<Proposition<String>>,
+    conditional_effects: Vec<GroundConditionalEffect>,
+}
+
+/// The actions produced by a `Grounder`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct GroundActions {
+    actions: Vec<GroundAction>,
+}
+
+impl GroundActions {
+    /// Returns the ground actions. Ids are the schema name followed
+    /// by the bound objects such as `move hall kitchen`.
+    pub fn actions(&self) -> HashSet<Action<String, String>> {
+        self.actions.iter()
+            .map(|a| {
+                let mut action = Action::new(a.id.clone(), &a.reqs, &a.effects);
+                for (conditions, effects) in &a.conditional_effects {
+                    action.when(conditions, effects);
+                }
+                action
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.actions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+}
+
+/// Expands action schemas into ground actions for every binding of
+/// objects to parameters of a matching type. Objects of a subtype
+/// can be bound to parameters of any of its ancestors.
+#[derive(Debug, Clone, Default)]
+pub struct Grounder {
+    parents: HashMap<String, String>,
+    objects: Vec<Parameter>,
+}
+
+impl Grounder {
+    pub fn new() -> Self {
+        Grounder::default()
+    }
+
+    /// Declares `kind` as a subtype of `parent`
+    pub fn add_type(&mut self, kind: &str, parent: &str) -> &mut Self {
+        self.parents.insert(kind.to_string(), parent.to_string());
+        self
+    }
+
+    pub fn add_object(&mut self, name: &str, kind: &str) -> &mut Self {
+        self.objects.push(Parameter::new(name, kind));
+        self
+    }
+
+    /// Returns true if `kind` is `ancestor` or one of its subtypes
+    fn is_subtype(&self, kind: &str, ancestor: &str) -> bool {
+        if ancestor == OBJECT_TYPE {
+            return true;
+        }
+        let mut current = kind;
+        // Bounded by the number of types so a cyclic declaration
+        // can't loop forever
+        for _ in 0..=self.parents.len() {
+            if current == ancestor {
+                return true;
+            }
+            match self.parents.get(current) {
+                Some(parent) => current = parent,
+                None => return false,
+            }
+        }
+        false
+    }
+
+    /// Returns the ground actions for each schema. Predicates that
+    /// no schema has as an effect can never change, so bindings
+    /// where such a precondition does not hold in the initial state
+    /// are pruned.
+    pub fn ground(&self,
+                  schemas: &[ActionSchema],
+                  initial_props: &HashSet<&Proposition<String>>) -> GroundActions {
+        let fluents: HashSet<&str> = schemas.iter()
+            .flat_map(|s| s.effects.iter().chain(s.conditional_effects.iter().flat_map(|ce| ce.effects.iter())))
+            .map(|e| e.predicate.as_str())
+            .collect();
+
+        let mut actions = Vec::new();
+        for schema in schemas {
+            let candidates: Vec<Vec<&str>> = schema.parameters.iter()
+                .map(|p| self.objects.iter()
+                     .filter(|o| self.is_subtype(&o.kind, &p.kind))
+                     .map(|o| o.name.as_str())
+                     .collect())
+                .collect();
+            let statics: Vec<&Template> = schema.reqs.iter()
+                .filter(|r| r.is_equality() || !fluents.contains(r.predicate.as_str()))
+                .collect();
+
+            let mut binding = HashMap::new();
+            bind(schema, &candidates, &statics, initial_props, &mut binding, &mut actions);
+        }
+
+        GroundActions { actions }
+    }
+}
+
+/// Returns false if a static template with all of its terms bound
+/// does not hold. A negated template holds when its atom is missing
+/// from the initial state.
+fn holds(template: &Template,
+         initial_props: &HashSet<&Proposition<String>>,
+         binding: &HashMap<&str, &str>) -> bool {
+    match template.objects(binding) {
+        None => true,
+        Some(objects) if template.is_equality() => (objects[0] == objects[1]) != template.negation,
+        Some(_) => template.ground(binding)
+            .map(|p| if p.negation {
+                !initial_props.contains(&p.negate())
+            } else {
+                initial_props.contains(&p)
+            })
+            .unwrap_or(true),
+    }
+}
+
+/// Binds parameters one at a time and backtracks as soon as a static
+/// precondition fails so pruned bindings are never fully expanded
+fn bind<'s>(schema: &'s ActionSchema,
+            candidates: &[Vec<&'s str>],
+            statics: &[&Template],
+            initial_props: &HashSet<&Proposition<String>>,
+            binding: &mut HashMap<&'s str, &'s str>,
+            actions: &mut Vec<GroundAction>) {
+    let idx = binding.len();
+    if idx == schema.parameters.len() {
+        actions.push(ground_action(schema, binding));
+        return;
+    }
+
+    let param = schema.parameters[idx].name.as_str();
+    for object in &candidates[idx] {
+        binding.insert(param, object);
+        if statics.iter().all(|s| holds(s, initial_props, binding)) {
+            bind(schema, candidates, statics, initial_props, binding, actions);
+        }
+        binding.remove(param);
+    }
+}
+
+fn ground_action(schema: &ActionSchema, binding: &HashMap<&str, &str>) -> GroundAction {
+    let ground = |t: &Template| t.ground(binding)
+        .expect("All parameters are bound before grounding");
+
+    let reqs = schema.reqs.iter()
+        .filter(|r| !r.is_equality())
+        .map(ground)
+        .collect();
+
+    // Deletes are applied before adds so an action that adds and
+    // deletes the same proposition keeps it
+    let ground_effects = |effects: &[Template]| {
+        let adds: BTreeSet<Proposition<String>> = effects.iter()
+            .filter(|e| !e.negation)
+            .map(ground)
+            .collect();
+        effects.iter()
+            .filter(|e| e.negation)
+            .map(ground)
+            .filter(|p| !adds.contains(&p.negate()))
+            .chain(adds.iter().cloned())
+            .collect::<BTreeSet<_>>()
+    };
+    let effects = ground_effects(&schema.effects);
+
+    // Equalities in conditions are decided by the binding so the
+    // effect either always or never happens
+    let no_props = HashSet::new();
+    let conditional_effects = schema.conditional_effects.iter()
+        .filter(|ce| ce.conditions.iter()
+                .filter(|c| c.is_equality())
+                .all(|c| holds(c, &no_props, binding)))
+        .map(|ce| {
+            let conditions = ce.conditions.iter()
+                .filter(|c| !c.is_equality())
+                .map(ground)
+                .collect();
+            (conditions, ground_effects(&ce.effects))
+        })
+        .collect();
+
+    let objects: Vec<&str> = schema.parameters.iter()
+        .map(|p| binding[p.name.as_str()])
+        .collect();
+    let id = proposition(&schema.name, &objects).id;
+
+    GroundAction { id, reqs, effects, conditional_effects }
+}
+
+#[cfg(test)]
+mod schema_test {
+    use super::*;
+
+    fn move_schema() -> ActionSchema {
+        ActionSchema::new(
+            "move",
+            vec![Parameter::new("?r", "robot"), Parameter::new("?from", "room"), Parameter::new("?to", "room")],
+            vec![Template::new("at", &["?r", "?from"]), Template::new("door", &["?from", "?to"])],
+            vec![Template::new("at", &["?r", "?to"]), Template::new("at", &["?r", "?from"]).negate()],
+        )
+    }
+
+    fn grounder() -> Grounder {
+        let mut grounder = Grounder::new();
+        grounder
+            .add_type("robot", "bot")
+            .add_object("r2", "robot")
+            .add_object("hall", "room")
+            .add_object("kitchen", "room")
+            .add_object("attic", "room");
+        grounder
+    }
+
+    #[test]
+    fn grounds_every_binding_by_type() {
+        let schema = ActionSchema::new(
+            "wave",
+            vec![Parameter::new("?b", "bot"), Parameter::new("?x", "room")],
+            vec![],
+            vec![Template::new("waved", &["?b", "?x"])],
+        );
+        let ground = grounder().ground(&[schema], &HashSet::new());
+        let actions = ground.actions();
+        let ids: BTreeSet<&str> = actions.iter().map(|a| a.get_action().unwrap().as_str()).collect();
+        assert_eq!(ids, btreeset!{"wave r2 attic", "wave r2 hall", "wave r2 kitchen"});
+    }
+
+    #[test]
+    fn prunes_false_static_preconditions() {
+        let door = proposition("door", &["hall", "kitchen"]);
+        let at = proposition("at", &["r2", "hall"]);
+        let initial_props = fragset!{[&door, &at]};
+        let ground = grounder().ground(&[move_schema()], &initial_props);
+
+        // `door` is never an effect so only the one real door is
+        // kept while `at` changes so it is not used for pruning
+        assert_eq!(ground.len(), 1);
+        let actions = ground.actions();
+        let action = actions.iter().next().unwrap();
+        assert_eq!(action.get_action().unwrap(), "move r2 hall kitchen");
+        assert!(action.reqs.contains(&door));
+        assert!(action.effects.contains(&proposition("at", &["r2", "kitchen"])));
+        assert!(action.effects.contains(&at.negate()));
+    }
+
+    #[test]
+    fn negated_static_preconditions_hold_when_missing() {
+        let schema = ActionSchema::new(
+            "go",
+            vec![Parameter::new("?x", "room")],
+            vec![Template::new("blocked", &["?x"]).negate()],
+            vec![Template::new("at", &["?x"])],
+        );
+        let blocked = proposition("blocked", &["attic"]);
+        let ground = grounder().ground(&[schema], &fragset!{[&blocked]});
+        let actions = ground.actions();
+        let ids: BTreeSet<&str> = actions.iter().map(|a| a.get_action().unwrap().as_str()).collect();
+        assert_eq!(ids, btreeset!{"go hall", "go kitchen"});
+    }
+
+    #[test]
+    fn equality_is_static() {
+        let schema = ActionSchema::new(
+            "swap",
+            vec![Parameter::new("?a", "room"), Parameter::new("?b", "room")],
+            vec![Template::new("=", &["?a", "?b"]).negate()],
+            vec![Template::new("swapped", &["?a", "?b"])],
+        );
+        let ground = grounder().ground(&[schema], &HashSet::new());
+        assert_eq!(ground.len(), 6);
+        assert!(ground.actions().iter().all(|a| a.reqs.is_empty()));
+    }
+
+    #[test]
+    fn adds_win_over_deletes() {
+        let schema = ActionSchema::new(
+            "stay",
+            vec![Parameter::new("?from", "room"), Parameter::new("?to", "room")],
+            vec![],
+            vec![Template::new("at", &["?to"]), Template::new("at", &["?from"]).negate()],
+        );
+        let ground = grounder().ground(&[schema], &HashSet::new());
+        let actions = ground.actions();
+        let stay = actions.iter()
+            .find(|a| a.get_action().unwrap() == "stay hall hall")
+            .unwrap();
+        let at_hall = proposition("at", &["hall"]);
+        assert_eq!(stay.effects, fragset!{[at_hall]});
+    }
+}
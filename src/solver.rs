@@ -1,62 +1,305 @@
 use std::fmt::{Debug, Display};
 use std::hash::Hash;
-use std::iter::FromIterator;
 use std::collections::{HashMap, HashSet, BTreeSet, VecDeque};
 use log::{debug};
-use crate::proposition::Proposition;
-use crate::action::{Action, ActionType};
-use crate::pairset::{pairs_from_sets};
-use crate::layer::{MutexPairs, Layer};
-use crate::plangraph::{PlanGraph, Solution};
+use crate::domain::{ActionIndex, Domain, PropositionIndex};
+use crate::error::Error;
+use crate::limits::SearchBudget;
+use crate::observer::SearchObserver;
+use crate::ordering::{SearchHeuristics, SearchOrder};
+use crate::layer::{ActionLayerData, MutexPairs, Layer};
+use crate::plangraph::{PlanGraph, Solution, SolutionCost};
 
 
-pub trait GraphPlanSolver<'a,
-                          ActionId: Hash + Ord + Clone + Debug,
-                          PropositionId: Hash + Ord + Clone + Debug + Display> {
+/// Searches a plangraph for a plan. Solvers are values so they can
+/// hold options and what they learn between calls, and the trait is
+/// object safe so a `Box<dyn GraphPlanSolver>` can be picked at
+/// runtime.
+pub trait GraphPlanSolver<ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
     /// Searches a plangraph for a sequence of collection of actions
-    /// that satisfy the goals
-    fn search<'b>(plangraph: &'b PlanGraph<'a, ActionId, PropositionId>) -> Option<Solution<'a, ActionId, PropositionId>>;
+    /// that satisfy the goals. Returns `Ok(None)` if there is no plan
+    /// at the current depth of the plangraph.
+    fn search(&mut self, plangraph: &PlanGraph<ActionId, PropositionId>) -> Result<Option<Solution<ActionId, PropositionId>>, Error>;
+
+    /// Same as `search` but counts nodes and backtracks against the
+    /// budget and returns `Error::LimitReached` when it runs out.
+    /// Goal sets are only recorded as unsolvable when they fail,
+    /// never when the search was stopped. Solvers that don't check
+    /// the budget are only stopped between depths by
+    /// `GraphPlan::search`. Solvers with a goal set stack report what
+    /// they do to `observer`, the rest can ignore it.
+    fn search_within(&mut self,
+                     plangraph: &PlanGraph<ActionId, PropositionId>,
+                     budget: &SearchBudget,
+                     _observer: &mut dyn SearchObserver)
+                     -> Result<Option<Solution<ActionId, PropositionId>>, Error> {
+        budget.check()?;
+        self.search(plangraph)
+    }
+
+    /// Same as `search_within` but returns the cheapest plan at the
+    /// current depth that costs less than `bound`, or `Ok(None)` if
+    /// there is none. Goal sets are only recorded as unsolvable if
+    /// they fail regardless of the bound. Solvers that don't
+    /// implement this return the first plan they find if it costs
+    /// less than `bound`, which need not be the cheapest.
+    fn search_cheapest(&mut self,
+                       plangraph: &PlanGraph<ActionId, PropositionId>,
+                       budget: &SearchBudget,
+                       observer: &mut dyn SearchObserver,
+                       bound: Option<u64>)
+                       -> Result<Option<Solution<ActionId, PropositionId>>, Error> {
+        let solution = self.search_within(plangraph, budget, observer)?;
+        Ok(solution.filter(|s| match bound {
+            Some(b) => s.cost() < b,
+            None => true,
+        }))
+    }
+
+    /// Same as `search_within` but resumes the search from `cursor`
+    /// and returns the next plan at the current depth, or `Ok(None)`
+    /// when there are no more. Goal sets that lead to a plan are
+    /// never recorded as unsolvable. Solvers that can't resume a
+    /// search only return the first plan.
+    fn next_plan(&mut self,
+                 plangraph: &PlanGraph<ActionId, PropositionId>,
+                 budget: &SearchBudget,
+                 observer: &mut dyn SearchObserver,
+                 cursor: &mut PlanCursor)
+                 -> Result<Option<Solution<ActionId, PropositionId>>, Error> {
+        if cursor.layers == Some(plangraph.layers.len()) {
+            return Ok(None);
+        }
+        cursor.layers = Some(plangraph.layers.len());
+        self.search_within(plangraph, budget, observer)
+    }
+
+    /// Returns the goal sets found to be unsolvable so far. These
+    /// are kept as the plangraph is extended and `GraphPlan::search`
+    /// uses them to decide when a problem is unsolvable. Solvers
+    /// that don't memoize return `None`, in which case
+    /// `GraphPlan::search` gives up after a bound instead.
+    fn nogoods(&self) -> Option<&Nogoods> {
+        None
+    }
+
+    /// Forgets what was learned from the plangraphs searched so far,
+    /// which only holds for the same problem. `GraphPlan` calls this
+    /// before each search.
+    fn reset(&mut self) {}
 }
 
-#[derive(Default)]
-pub struct SimpleSolver;
+impl<ActionId, PropositionId, Solver> GraphPlanSolver<ActionId, PropositionId> for &mut Solver
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+    Solver: GraphPlanSolver<ActionId, PropositionId> + ?Sized,
+{
+    fn search(&mut self, plangraph: &PlanGraph<ActionId, PropositionId>) -> Result<Option<Solution<ActionId, PropositionId>>, Error> {
+        (**self).search(plangraph)
+    }
+
+    fn search_within(&mut self,
+                     plangraph: &PlanGraph<ActionId, PropositionId>,
+                     budget: &SearchBudget,
+                     observer: &mut dyn SearchObserver)
+                     -> Result<Option<Solution<ActionId, PropositionId>>, Error> {
+        (**self).search_within(plangraph, budget, observer)
+    }
+
+    fn search_cheapest(&mut self,
+                       plangraph: &PlanGraph<ActionId, PropositionId>,
+                       budget: &SearchBudget,
+                       observer: &mut dyn SearchObserver,
+                       bound: Option<u64>)
+                       -> Result<Option<Solution<ActionId, PropositionId>>, Error> {
+        (**self).search_cheapest(plangraph, budget, observer, bound)
+    }
+
+    fn next_plan(&mut self,
+                 plangraph: &PlanGraph<ActionId, PropositionId>,
+                 budget: &SearchBudget,
+                 observer: &mut dyn SearchObserver,
+                 cursor: &mut PlanCursor)
+                 -> Result<Option<Solution<ActionId, PropositionId>>, Error> {
+        (**self).next_plan(plangraph, budget, observer, cursor)
+    }
+
+    fn nogoods(&self) -> Option<&Nogoods> {
+        (**self).nogoods()
+    }
+
+    fn reset(&mut self) {
+        (**self).reset()
+    }
+}
+
+impl<ActionId, PropositionId, Solver> GraphPlanSolver<ActionId, PropositionId> for Box<Solver>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+    Solver: GraphPlanSolver<ActionId, PropositionId> + ?Sized,
+{
+    fn search(&mut self, plangraph: &PlanGraph<ActionId, PropositionId>) -> Result<Option<Solution<ActionId, PropositionId>>, Error> {
+        (**self).search(plangraph)
+    }
+
+    fn search_within(&mut self,
+                     plangraph: &PlanGraph<ActionId, PropositionId>,
+                     budget: &SearchBudget,
+                     observer: &mut dyn SearchObserver)
+                     -> Result<Option<Solution<ActionId, PropositionId>>, Error> {
+        (**self).search_within(plangraph, budget, observer)
+    }
+
+    fn search_cheapest(&mut self,
+                       plangraph: &PlanGraph<ActionId, PropositionId>,
+                       budget: &SearchBudget,
+                       observer: &mut dyn SearchObserver,
+                       bound: Option<u64>)
+                       -> Result<Option<Solution<ActionId, PropositionId>>, Error> {
+        (**self).search_cheapest(plangraph, budget, observer, bound)
+    }
+
+    fn next_plan(&mut self,
+                 plangraph: &PlanGraph<ActionId, PropositionId>,
+                 budget: &SearchBudget,
+                 observer: &mut dyn SearchObserver,
+                 cursor: &mut PlanCursor)
+                 -> Result<Option<Solution<ActionId, PropositionId>>, Error> {
+        (**self).next_plan(plangraph, budget, observer, cursor)
+    }
+
+    fn nogoods(&self) -> Option<&Nogoods> {
+        (**self).nogoods()
+    }
+
+    fn reset(&mut self) {
+        (**self).reset()
+    }
+}
+
+/// Goal sets known to be unsolvable at a proposition layer, and so
+/// is every goal set that contains one. These remain valid as the
+/// plangraph is extended because earlier layers never change.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Nogoods(HashMap<usize, HashSet<BTreeSet<PropositionIndex>>>);
+
+impl Nogoods {
+    pub fn new() -> Self {
+        Nogoods::default()
+    }
+
+    /// Returns true if `goals` contains a goal set known to be
+    /// unsolvable at a layer
+    pub fn contains(&self, layer: usize, goals: &BTreeSet<PropositionIndex>) -> bool {
+        self.get(layer, goals).is_some()
+    }
+
+    /// Returns a goal set known to be unsolvable at a layer that
+    /// `goals` contains
+    pub fn get(&self, layer: usize, goals: &BTreeSet<PropositionIndex>) -> Option<&BTreeSet<PropositionIndex>> {
+        let nogoods = self.0.get(&layer)?;
+        nogoods.get(goals).or_else(|| nogoods.iter().find(|n| n.is_subset(goals)))
+    }
+
+    /// Records an unsolvable goal set unless it contains one that is
+    /// already recorded
+    pub fn insert(&mut self, layer: usize, goals: BTreeSet<PropositionIndex>) -> bool {
+        if self.contains(layer, &goals) {
+            return false;
+        }
+        self.0.entry(layer).or_default().insert(goals)
+    }
+
+    /// Returns the number of unsolvable goal sets at a layer
+    pub fn count(&self, layer: usize) -> usize {
+        self.0.get(&layer).map(HashSet::len).unwrap_or(0)
+    }
+
+    /// Returns the number of unsolvable goal sets across all layers
+    pub fn len(&self) -> usize {
+        self.0.values().map(HashSet::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Searches backwards from the goals through the plangraph as in
+/// the paper, ordering goals and actions with its heuristics. The
+/// goal sets it finds to be unsolvable are kept until `reset` so
+/// searching the same plangraph at a greater depth doesn't find them
+/// again.
+#[derive(Debug, Clone, Default)]
+pub struct SimpleSolver {
+    pub heuristics: SearchHeuristics,
+    nogoods: Nogoods,
+}
 
 type GoalIndex = usize;
-type Attempts<'a, ActionId, PropositionId> = HashMap<usize, BTreeSet<&'a Action<'a, ActionId, PropositionId>>>;
+type Attempts = HashMap<usize, VecDeque<ActionIndex>>;
 
 #[derive(Clone, Debug, PartialEq)]
-struct ActionCombination<'a,
-                         ActionId: Hash + PartialEq + Clone + Debug ,
-                         PropositionId: Hash + Eq + PartialEq + Clone + Debug + Display>(
-    HashMap<GoalIndex, &'a Action<'a, ActionId, PropositionId>>
-);
+struct ActionCombination(HashMap<GoalIndex, ActionIndex>);
 
 #[derive(Clone, Debug)]
-struct GoalSetActionGenerator<'a,
-                              ActionId: Debug + Hash + Clone + Eq + Ord,
-                              PropositionId: Debug + Display + Hash + Clone + Eq + Ord> {
-    goals: BTreeSet<&'a Proposition<PropositionId>>,
-    actions: BTreeSet<&'a Action<'a, ActionId, PropositionId>>,
-    mutexes: Option<MutexPairs<&'a Action<'a, ActionId, PropositionId>>>,
+struct GoalSetActionGenerator<'a, ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    domain: &'a Domain<ActionId, PropositionId>,
+    goals: BTreeSet<PropositionIndex>,
+    goal_list: Vec<PropositionIndex>, // goals in the order they are met
+    actions: ActionLayerData,
+    mutexes: Option<&'a MutexPairs<ActionIndex>>,
+    budget: Option<&'a SearchBudget>,
+    order: Option<&'a SearchOrder<'a>>,
 }
 
-impl<'a,
-     ActionId: Ord + Clone + Hash + Debug,
-     PropositionId: Ord + Clone + Hash + Debug + Display>
-    GoalSetActionGenerator<'a, ActionId, PropositionId> {
-    pub fn new(goals: BTreeSet<&'a Proposition<PropositionId>>,
-               actions: BTreeSet<&'a Action<'a, ActionId, PropositionId>>,
-               mutexes: Option<MutexPairs<&'a Action<'a, ActionId, PropositionId>>>,)
+impl<'a, ActionId, PropositionId> GoalSetActionGenerator<'a, ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    pub fn new(domain: &'a Domain<ActionId, PropositionId>,
+               goals: BTreeSet<PropositionIndex>,
+               actions: ActionLayerData,
+               mutexes: Option<&'a MutexPairs<ActionIndex>>)
                -> GoalSetActionGenerator<'a, ActionId, PropositionId> {
-        GoalSetActionGenerator {goals, actions, mutexes}
+        let goal_list = goals.iter().copied().collect();
+        GoalSetActionGenerator {domain, goals, goal_list, actions, mutexes, budget: None, order: None}
+    }
+
+    /// Meets goals and tries actions in the order of the search
+    /// heuristics rather than by index
+    pub fn with_order(mut self, order: &'a SearchOrder<'a>) -> Self {
+        order.sort_goals(&mut self.goal_list);
+        self.order = Some(order);
+        self
+    }
+
+    /// Counts backtracks against the budget. Iteration ends early
+    /// when a limit is hit so check the budget before treating the
+    /// goals as unsolvable.
+    pub fn with_budget(mut self, budget: &'a SearchBudget) -> Self {
+        self.budget = Some(budget);
+        self
     }
 }
 
-impl<'a,
-     ActionId: Ord + Clone + Hash + Debug,
-     PropositionId: Ord + Clone + Hash + Debug + Display>
-    IntoIterator for GoalSetActionGenerator<'a, ActionId, PropositionId> {
-    type Item = ActionCombination<'a, ActionId, PropositionId>;
+impl<'a, ActionId, PropositionId> IntoIterator for GoalSetActionGenerator<'a, ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    type Item = ActionCombination;
     type IntoIter = ActionCombinationIterator<'a, ActionId, PropositionId>;
 
     fn into_iter(self) -> Self::IntoIter {
@@ -65,65 +308,164 @@ impl<'a,
 }
 
 #[derive(Clone, Debug)]
-struct ActionCombinationIterator<'a,
-                                 ActionId: Ord + Clone + Hash + Debug,
-                                 PropositionId: Ord + Clone + Hash + Debug + Display> {
+struct ActionCombinationIterator<'a, ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
     meta: GoalSetActionGenerator<'a, ActionId, PropositionId>, // defines goals we are trying achieve
-    attempts: Attempts<'a, ActionId, PropositionId>, // previous attempts to meet a goal
+    state: CombinationState, // how far the iteration got
+}
+
+/// The progress of an `ActionCombinationIterator`, kept apart from
+/// the plangraph it borrows so the iteration can be resumed later
+#[derive(Clone, Debug, Default)]
+struct CombinationState {
+    attempts: Attempts, // previous attempts to meet a goal
     goals_met: bool, // flag indicating all goals are met or restart
-    accum: HashMap<GoalIndex, &'a Action<'a, ActionId, PropositionId>> // combination of actions
+    accum: HashMap<GoalIndex, ActionIndex>, // combination of actions
+    yielded: HashSet<BTreeSet<ActionIndex>>, // action sets already returned
+    conflicts: HashMap<GoalIndex, BTreeSet<GoalIndex>>, // earlier goals whose choices ruled out actions for a goal
+    explanation: BTreeSet<GoalIndex>, // goals involved in every failure so far
+    culprits: Option<BTreeSet<GoalIndex>>, // goals blamed for the last combination failing
 }
 
-impl<'a,
-     ActionId: Ord + Debug + Hash + Clone,
-     PropositionId: Ord + Debug + Display + Hash + Clone>
-    ActionCombinationIterator<'a, ActionId, PropositionId> {
-    pub fn new(action_combinations: GoalSetActionGenerator<ActionId, PropositionId>) -> ActionCombinationIterator<ActionId, PropositionId> {
-        ActionCombinationIterator {
-            meta: action_combinations,
-            attempts: Attempts::new(),
-            goals_met: false,
-            accum: HashMap::new(),
-        }
+impl CombinationState {
+    /// Blames the goals whose chosen actions made the last
+    /// combination fail, so the next one changes one of them
+    fn blame(&mut self, culprits: BTreeSet<GoalIndex>) {
+        self.culprits = Some(culprits);
+    }
+
+    /// Jumps back to the latest goal in `conflict`, the goals whose
+    /// choices ruled out the actions left for a later goal, and
+    /// forgets the choices for the goals after it since changing
+    /// them can't resolve the conflict. The rest of the conflict is
+    /// passed on to that goal. Returns `None` if no choice is to
+    /// blame, in which case there are no more combinations.
+    fn jump_back(&mut self, mut conflict: BTreeSet<GoalIndex>) -> Option<GoalIndex> {
+        let target = conflict.iter().next_back().copied()?;
+        conflict.remove(&target);
+        self.conflicts.entry(target).or_default().extend(conflict);
+        self.attempts.retain(|g, _| *g <= target);
+        self.conflicts.retain(|g, _| *g <= target);
+        self.accum.retain(|g, _| *g < target);
+        Some(target)
     }
 }
 
-impl<'a,
-     ActionId: Ord + Clone + Hash + Debug + PartialEq,
-     PropositionId: Ord + Clone + Hash + Debug + Display + PartialEq>
-    Iterator for ActionCombinationIterator<'a, ActionId, PropositionId> {
-    type Item = ActionCombination<'a, ActionId, PropositionId>;
+impl<'a, ActionId, PropositionId> ActionCombinationIterator<'a, ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    pub fn new(action_combinations: GoalSetActionGenerator<'a, ActionId, PropositionId>)
+               -> ActionCombinationIterator<'a, ActionId, PropositionId> {
+        ActionCombinationIterator::resume(action_combinations, CombinationState::default())
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let goals = Vec::from_iter(&self.meta.goals);
+    /// Continues from where an iterator over the same goals and
+    /// actions left off
+    fn resume(action_combinations: GoalSetActionGenerator<'a, ActionId, PropositionId>,
+              state: CombinationState)
+              -> ActionCombinationIterator<'a, ActionId, PropositionId> {
+        ActionCombinationIterator { meta: action_combinations, state }
+    }
+
+    fn into_state(self) -> CombinationState {
+        self.state
+    }
+
+    /// Returns true if no action can be removed from the combination
+    /// so that the effects of the remaining actions still contain
+    /// the goals
+    fn is_minimal(&self) -> bool {
+        let domain = self.meta.domain;
+        let chosen: BTreeSet<ActionIndex> = self.state.accum.values().copied().collect();
+        chosen.iter().all(|removed| {
+            !self.meta.goals.iter().all(|goal| {
+                chosen.iter().any(|a| a != removed && domain.effects(*a).contains(goal))
+            })
+        })
+    }
+
+    /// Finds the next combination of actions that meets every goal,
+    /// choosing actions only for goals the actions chosen for
+    /// earlier goals don't already meet. Returns false when there
+    /// are no more.
+    fn next_combination(&mut self) -> bool {
+        let domain = self.meta.domain;
+        let goals = &self.meta.goal_list;
         let actions = &self.meta.actions;
         let goal_len = goals.len();
 
+        // Nothing is needed to meet no goals, which happens when
+        // the actions chosen for the next layer require nothing
+        if goal_len == 0 {
+            return !std::mem::replace(&mut self.state.goals_met, true);
+        }
+
         let mut stack = VecDeque::new();
 
         // If the goals have already been met, we need to look for a
         // new combination that also meets the goals
-        if self.goals_met {
-            // Remove the previous action used to satisfy the last
-            // goal and start the loop from the last goal. This will
-            // yield a new combination or recursively back track.
-            self.goals_met = false;
-            let goal_idx = goal_len - 1;
-            self.accum.remove(&goal_idx);
-            stack.push_front(goal_idx);
+        if self.state.goals_met {
+            // Jump back to the last goal to blame for the previous
+            // combination failing and start the loop from that goal.
+            // Without a failure to explain, e.g. when looking for
+            // more plans, every choice is to blame so this is the
+            // last goal that needed an action. This will yield a new
+            // combination or recursively back track.
+            self.state.goals_met = false;
+            let conflict = match self.state.culprits.take() {
+                Some(culprits) => {
+                    self.state.explanation.extend(&culprits);
+                    culprits
+                },
+                None => self.state.accum.keys().copied().collect(),
+            };
+            match self.state.jump_back(conflict) {
+                Some(goal_idx) => stack.push_front(goal_idx),
+                None => return false,
+            }
         } else {
             stack.push_front(0);
         }
 
         while let Some(goal_idx) = stack.pop_front() {
-            let available_actions = if let Some(acts) = self.attempts.get(&goal_idx) {
-                acts.to_owned()
+            if matches!(self.meta.budget, Some(b) if b.check().is_err()) {
+                return false;
+            }
+
+            let mut available_actions = if let Some(acts) = self.state.attempts.remove(&goal_idx) {
+                acts
             } else {
-                let goal = &goals[goal_idx];
-                debug!("Working on goal {:?}", goal);
+                let goal = goals[goal_idx];
+                debug!("Working on goal {:?}", domain.proposition(goal));
+
+                // Later goals may still hold actions from an earlier
+                // combination so they are ignored
+                let chosen: ActionLayerData = self.state.accum.iter()
+                    .filter(|(g, _)| **g < goal_idx)
+                    .map(|(_, a)| *a)
+                    .collect();
+
+                // Skip goals already met by the actions chosen for
+                // the previous goals
+                if chosen.iter().any(|a| domain.effect_set(a).contains(goal)) {
+                    debug!("Goal {:?} is already met", goal_idx);
+                    self.state.accum.remove(&goal_idx);
+                    if goal_idx < goal_len - 1 {
+                        stack.push_front(goal_idx + 1);
+                    } else {
+                        self.state.goals_met = true;
+                    }
+                    continue;
+                }
 
-                let mut available = BTreeSet::new();
-                let accum = &self.accum;
+                let mut available = Vec::new();
+                let mut conflict = BTreeSet::new();
+                let mut producers = false;
 
                 // Only actions that produce the goal and are not
                 // mutex with any other actions and have not
@@ -133,78 +475,97 @@ impl<'a,
                 for a in actions {
                     // Early continue since the later checks are
                     // more expensive
-                    if !a.effects.contains(*goal) {
+                    if !domain.effect_set(a).contains(goal) {
                         continue
                     };
 
-                    // Early break if we already know this action
-                    // works for the goal from previous attempts
-                    if accum.get(&goal_idx).map(|i| i == a).unwrap_or(false) {
-                        available.insert(*a);
-                        break
-                    };
+                    producers = true;
 
                     // Check if this action is mutex with any of
-                    // the other accumulated actions
-                    let acts = accum.values().copied().collect();
-                    let pairs = pairs_from_sets(
-                        // fragset!{*a},
-                        fragset!{[*a]},
-                        acts);
-                    debug!("Checking pairs: {:?} against mutexes: {:?}", &pairs, &self.meta.mutexes);
-
-                    if let Some(muxes) = &self.meta.mutexes {
-                        if muxes.intersection(&pairs).next().is_none() {
-                            available.insert(*a);
+                    // the actions chosen for the previous goals
+                    debug!("Checking {:?} against chosen actions: {:?}", a, &chosen);
+                    if let Some(muxes) = self.meta.mutexes {
+                        if !muxes.any_mutex(a, &chosen) {
+                            available.push(a);
+                        } else {
+                            // Remember which goals ruled it out
+                            conflict.extend(self.state.accum.iter()
+                                .filter(|(g, c)| **g < goal_idx && muxes.is_mutex(a, **c))
+                                .map(|(g, _)| *g));
                         }
                     };
                 };
-                available
+
+                if !producers || !conflict.is_empty() {
+                    self.state.explanation.insert(goal_idx);
+                    self.state.explanation.extend(&conflict);
+                }
+                self.state.conflicts.insert(goal_idx, conflict);
+                if let Some(order) = self.meta.order {
+                    order.sort_actions(domain, &mut available);
+                }
+                VecDeque::from(available)
             };
 
             if available_actions.is_empty() {
-                if goal_idx == 0 {
-                    // Complete fail
-                    break;
-                }
-                debug!("Unable to find actions for goal {:?}. Going back to previous goal...", goal_idx);
                 // Clear attempts for this goal so finding an action
                 // can be retried with a new set of actions
-                self.attempts.remove(&goal_idx);
-                // Backtrack to the previous goal
-                stack.push_front(goal_idx - 1);
+                self.state.attempts.remove(&goal_idx);
+                self.state.accum.remove(&goal_idx);
+                let conflict = self.state.conflicts.remove(&goal_idx).unwrap_or_default();
+                let prev_idx = match self.state.jump_back(conflict) {
+                    Some(prev_idx) => prev_idx,
+                    // Complete fail
+                    None => break,
+                };
+                debug!("Unable to find actions for goal {:?}. Going back to goal {:?}...", goal_idx, prev_idx);
+                // Backtrack to the last goal to blame for it
+                if matches!(self.meta.budget, Some(b) if b.backtrack().is_err()) {
+                    return false;
+                }
+                stack.push_front(prev_idx);
             } else {
-                let next_action = available_actions.iter().next().unwrap();
-                // TODO only add the action if the goal isn't met yet
-                self.accum.insert(goal_idx, *next_action);
+                let next_action = available_actions.pop_front().unwrap();
+                self.state.accum.insert(goal_idx, next_action);
 
                 // Add to previous attempts in case we need to backtrack
-                let mut remaining_actions = available_actions.clone();
-                remaining_actions.remove(next_action);
-                self.attempts.insert(goal_idx, remaining_actions);
-
-                // TODO Implement minimal action sets i.e handle if
-                // this action staisfies more than one
-                // goal. Otherwise, the speed of finding a solution is
-                // dependent on the ordering of goals
-                // From the paper: no action can be
-                // removed from A so that the add effects of the
-                // actions remaining still contain G
+                self.state.attempts.insert(goal_idx, available_actions);
 
                 // Proceed to the next goal
                 if goal_idx < goal_len - 1 {
                     stack.push_front(goal_idx + 1);
                 } else {
-                    self.goals_met = true;
+                    self.state.goals_met = true;
                 }
             };
         };
 
-        if self.goals_met {
-            Some(ActionCombination(self.accum.clone()))
-        } else {
-            None
+        self.state.goals_met
+    }
+}
+
+impl<'a, ActionId, PropositionId> Iterator for ActionCombinationIterator<'a, ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    type Item = ActionCombination;
+
+    /// Yields minimal action sets as described in the paper: no
+    /// action can be removed so that the add effects of the actions
+    /// remaining still contain the goals. Skipping goals that are
+    /// already met can still choose an action that a later one makes
+    /// redundant, and different choices can reach the same set, so
+    /// those are passed over.
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.next_combination() {
+            let action_set = self.state.accum.values().copied().collect();
+            if self.is_minimal() && self.state.yielded.insert(action_set) {
+                return Some(ActionCombination(self.state.accum.clone()));
+            }
+            debug!("Skipping action set {:?} that is not minimal", self.state.accum);
         }
+        None
     }
 }
 
@@ -212,24 +573,28 @@ impl<'a,
 #[cfg(test)]
 mod goal_set_action_generator_test {
     use super::*;
+    use crate::action::Action;
+    use crate::proposition::Proposition;
+    use crate::pairset::PairSet;
 
     #[test]
     fn single_goal() {
         let p1 = Proposition::from("coffee");
         let p2 = Proposition::from("caffeinated");
-        let goals = btreeset!{&p2};
-
-        let mut actions = BTreeSet::new();
         let a1 = Action::new(
             String::from("drink coffee"),
             fragset!{[&p1]},
             fragset!{[&p2]}
         );
-        actions.insert(&a1);
+        let domain: Domain<String, &str> = Domain::new(vec![], vec![], vec![a1.clone()]);
+        let a1 = domain.action_index(&a1).unwrap();
+
+        let goals = btreeset!{domain.proposition_index(&p2).unwrap()};
+        let actions = [a1].iter().collect();
 
-        let mutexes = Some(MutexPairs::new());
-        let expected = ActionCombination(hashmap!{0usize => &a1});
-        let actual = GoalSetActionGenerator::new(goals, actions, mutexes)
+        let mutexes = MutexPairs::new();
+        let expected = ActionCombination(hashmap!{0usize => a1});
+        let actual = GoalSetActionGenerator::new(&domain, goals, actions, Some(&mutexes))
             .into_iter()
             .next()
             .unwrap();
@@ -255,18 +620,34 @@ mod goal_set_action_generator_test {
             fragset!{[&p4]}
         );
 
-        let actions = btreeset!{&a1, &a2};
-        let goals = btreeset!{&p2, &p4};
+        let domain: Domain<String, &str> = Domain::new(vec![], vec![p2.clone(), p4.clone()], vec![a1.clone(), a2.clone()]);
+        let (a1, a2) = (domain.action_index(&a1).unwrap(), domain.action_index(&a2).unwrap());
+        let actions = [a1, a2].iter().collect();
+        let goals = domain.goals().clone();
 
-        let mutexes = Some(MutexPairs::new());
-        let expected = ActionCombination(hashmap!{0usize => &a1, 1usize => &a2});
-        let actual = GoalSetActionGenerator::new(goals, actions, mutexes)
+        let mutexes = MutexPairs::new();
+        // Goals are ordered so "caffeinated" comes before "full"
+        let expected = ActionCombination(hashmap!{0usize => a1, 1usize => a2});
+        let actual = GoalSetActionGenerator::new(&domain, goals, actions, Some(&mutexes))
             .into_iter()
             .next()
             .unwrap();
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn empty_goals_need_no_actions() {
+        let p1 = Proposition::from("coffee");
+        let a1 = Action::new("make coffee", fragset!{}, fragset!{[&p1]});
+        let domain: Domain<&str, &str> = Domain::new(vec![], vec![], vec![a1]);
+        let actions = domain.action_indices().collect();
+
+        let mutexes = MutexPairs::new();
+        let generator = GoalSetActionGenerator::new(&domain, BTreeSet::new(), actions, Some(&mutexes));
+        let combinations: Vec<ActionCombination> = generator.into_iter().collect();
+        assert_eq!(combinations, vec![ActionCombination(HashMap::new())]);
+    }
+
     #[test]
     fn yields_all() {
         let p1 = Proposition::from("tea");
@@ -281,22 +662,26 @@ mod goal_set_action_generator_test {
         let a3 = Action::new("eat scone", fragset!{[&p4]}, fragset!{[&p6]});
         let a4 = Action::new("eat muffin", fragset!{[&p5]}, fragset!{[&p6]});
 
-        let actions = btreeset!{&a1, &a2, &a3, &a4};
-        let goals = btreeset!{&p3, &p6};
+        let domain: Domain<&str, &str> = Domain::new(
+            vec![],
+            vec![p3.clone(), p6.clone()],
+            vec![a1.clone(), a2.clone(), a3.clone(), a4.clone()],
+        );
+        let actions = domain.action_indices().collect();
+        let goals = domain.goals().clone();
 
-        let mutexes = Some(MutexPairs::new());
+        let mutexes = MutexPairs::new();
         let expected = vec![
             vec![&a1, &a4],
             vec![&a1, &a3],
             vec![&a2, &a4],
             vec![&a2, &a3],
         ];
-        let generator = GoalSetActionGenerator::new(goals, actions, mutexes);
+        let generator = GoalSetActionGenerator::new(&domain, goals, actions, Some(&mutexes));
         let actual: Vec<Vec<&Action<_, _>>> = generator.into_iter()
             .map(|combo| {
                 let mut out = combo.0.values()
-                    .into_iter()
-                    .map(|i| *i)
+                    .map(|a| domain.action(*a))
                     .collect::<Vec<&Action<&str, &str>>>();
                 out.sort();
                 out
@@ -305,122 +690,485 @@ mod goal_set_action_generator_test {
 
         assert_eq!(expected, actual);
     }
+
+    /// Returns the ids of the actions in every combination yielded
+    fn combinations(domain: &Domain<&'static str, &'static str>,
+                    mutexes: &MutexPairs<ActionIndex>) -> BTreeSet<Vec<&'static str>> {
+        let actions = domain.action_indices().collect();
+        GoalSetActionGenerator::new(domain, domain.goals().clone(), actions, Some(mutexes))
+            .into_iter()
+            .map(|combo| {
+                let mut out = combo.0.values()
+                    .map(|a| *domain.action(*a).get_action().unwrap())
+                    .collect::<Vec<_>>();
+                out.sort();
+                out
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ignores_stale_choices_for_later_goals() {
+        let caffeinated = Proposition::from("caffeinated");
+        let full = Proposition::from("full");
+        let stretched = Proposition::from("stretched");
+        let coffee = Action::new("drink coffee", fragset!{}, fragset!{[&caffeinated]});
+        let tea = Action::new("drink tea", fragset!{}, fragset!{[&caffeinated]});
+        let scone = Action::new("eat scone", fragset!{}, fragset!{[&full]});
+        let muffin = Action::new("eat muffin", fragset!{}, fragset!{[&full]});
+        let stretch = Action::new("stretch", fragset!{}, fragset!{[&stretched]});
+        let domain: Domain<&str, &str> = Domain::new(
+            vec![],
+            vec![caffeinated, full, stretched],
+            vec![coffee, tea, scone.clone(), muffin.clone(), stretch],
+        );
+
+        // Goals are ordered by name so "stretched" is met last. There
+        // is only one plate so the scone and the muffin can't both be
+        // eaten, but neither may be ruled out by the other being left
+        // over from the combination before
+        let idx = |a: &Action<&str, &str>| domain.action_index(a).unwrap();
+        let mutexes = vec![PairSet(idx(&scone), idx(&muffin))].into_iter().collect();
+        let expected = btreeset!{
+            vec!["drink coffee", "eat muffin", "stretch"],
+            vec!["drink coffee", "eat scone", "stretch"],
+            vec!["drink tea", "eat muffin", "stretch"],
+            vec!["drink tea", "eat scone", "stretch"]
+        };
+        assert_eq!(combinations(&domain, &mutexes), expected);
+    }
+
+    #[test]
+    fn checks_every_choice_against_previous_goals() {
+        let caffeinated = Proposition::from("caffeinated");
+        let full = Proposition::from("full");
+        let stretched = Proposition::from("stretched");
+        let coffee = Action::new("drink coffee", fragset!{}, fragset!{[&caffeinated]});
+        let tea = Action::new("drink tea", fragset!{}, fragset!{[&caffeinated]});
+        let scone = Action::new("eat scone", fragset!{}, fragset!{[&full]});
+        let stretch = Action::new("stretch", fragset!{}, fragset!{[&stretched]});
+        let domain: Domain<&str, &str> = Domain::new(
+            vec![],
+            vec![caffeinated, full, stretched],
+            vec![coffee, tea.clone(), scone.clone(), stretch],
+        );
+
+        // The scone chosen along with the coffee must not be reused
+        // with the tea it is mutex with
+        let idx = |a: &Action<&str, &str>| domain.action_index(a).unwrap();
+        let mutexes = vec![PairSet(idx(&tea), idx(&scone))].into_iter().collect();
+        let expected = btreeset!{vec!["drink coffee", "eat scone", "stretch"]};
+        assert_eq!(combinations(&domain, &mutexes), expected);
+    }
+
+    #[test]
+    fn jumps_back_to_the_goal_to_blame() {
+        let p1 = Proposition::from("tea");
+        let p2 = Proposition::from("coffee");
+        let p3 = Proposition::from("caffeinated");
+        let p4 = Proposition::from("scone");
+        let p5 = Proposition::from("muffin");
+        let p6 = Proposition::from("full");
+
+        let a1 = Action::new("drink coffee", fragset!{[&p2]}, fragset!{[&p3]});
+        let a2 = Action::new("drink tea", fragset!{[&p1]}, fragset!{[&p3]});
+        let a3 = Action::new("eat scone", fragset!{[&p4]}, fragset!{[&p6]});
+        let a4 = Action::new("eat muffin", fragset!{[&p5]}, fragset!{[&p6]});
+
+        let domain: Domain<&str, &str> = Domain::new(
+            vec![],
+            vec![p3.clone(), p6.clone()],
+            vec![a1.clone(), a2.clone(), a3.clone(), a4.clone()],
+        );
+        let actions = domain.action_indices().collect();
+        let goals = domain.goals().clone();
+        let mutexes = MutexPairs::new();
+        let mut combinations = GoalSetActionGenerator::new(&domain, goals, actions, Some(&mutexes)).into_iter();
+
+        let first = combinations.next().unwrap();
+        assert_eq!(domain.action(first.0[&0]), &a1);
+        // Whatever is eaten, coffee is to blame so the next
+        // combination drinks tea instead
+        combinations.state.blame(btreeset!{0});
+        let second = combinations.next().unwrap();
+        assert_eq!(domain.action(second.0[&0]), &a2);
+        assert_eq!(combinations.state.explanation, btreeset!{0});
+    }
+
+    fn action_sets<'a>(domain: &'a Domain<&'static str, &'static str>,
+                       generator: GoalSetActionGenerator<'a, &'static str, &'static str>)
+                       -> Vec<Vec<&'a Action<&'static str, &'static str>>> {
+        let mut sets: Vec<Vec<&Action<_, _>>> = generator.into_iter()
+            .map(|combo| {
+                let mut out = combo.0.values()
+                    .map(|a| domain.action(*a))
+                    .collect::<Vec<_>>();
+                out.sort();
+                out.dedup();
+                out
+            })
+            .collect();
+        sets.sort();
+        sets
+    }
+
+    #[test]
+    fn yields_minimal_sets() {
+        let p1 = Proposition::from("tea");
+        let p2 = Proposition::from("coffee");
+        let p3 = Proposition::from("caffeinated");
+        let p4 = Proposition::from("scone");
+        let p5 = Proposition::from("muffin");
+        let p6 = Proposition::from("full");
+        let p7 = Proposition::from("brunch");
+
+        let a1 = Action::new("drink coffee", fragset!{[&p2]}, fragset!{[&p3]});
+        let a2 = Action::new("drink tea", fragset!{[&p1]}, fragset!{[&p3]});
+        let a3 = Action::new("eat scone", fragset!{[&p4]}, fragset!{[&p6]});
+        let a4 = Action::new("eat muffin", fragset!{[&p5]}, fragset!{[&p6]});
+        let a5 = Action::new("have brunch", fragset!{[&p7]}, fragset!{[&p3, &p6]});
+
+        let domain: Domain<&str, &str> = Domain::new(
+            vec![],
+            vec![p3.clone(), p6.clone()],
+            vec![a1.clone(), a2.clone(), a3.clone(), a4.clone(), a5.clone()],
+        );
+        let actions = domain.action_indices().collect();
+        let goals = domain.goals().clone();
+        let mutexes = MutexPairs::new();
+        let generator = GoalSetActionGenerator::new(&domain, goals, actions, Some(&mutexes));
+
+        // One action per goal would give 3 x 3 combinations, most of
+        // them with brunch and something it makes redundant
+        let mut expected = vec![
+            vec![&a1, &a3],
+            vec![&a1, &a4],
+            vec![&a2, &a3],
+            vec![&a2, &a4],
+            vec![&a5],
+        ];
+        for set in expected.iter_mut() {
+            set.sort();
+        }
+        expected.sort();
+        assert_eq!(action_sets(&domain, generator), expected);
+    }
+
+    #[test]
+    fn minimal_sets_do_not_depend_on_goal_order() {
+        let p1 = Proposition::from("hot");
+        let p2 = Proposition::from("caffeinated");
+        let hot = Proposition::from("kettle");
+
+        let a1 = Action::new("boil water", fragset!{[&hot]}, fragset!{[&p1]});
+        let a2 = Action::new("make coffee", fragset!{[&hot]}, fragset!{[&p1, &p2]});
+
+        // Whichever goal comes first, boiling water is redundant once
+        // coffee is made
+        for goals in [vec![p1.clone(), p2.clone()], vec![p2.clone(), p1.clone()]] {
+            let domain: Domain<&str, &str> = Domain::new(vec![], goals, vec![a1.clone(), a2.clone()]);
+            let actions = domain.action_indices().collect();
+            let mutexes = MutexPairs::new();
+            let generator = GoalSetActionGenerator::new(&domain, domain.goals().clone(), actions, Some(&mutexes));
+            assert_eq!(action_sets(&domain, generator), vec![vec![&a2]]);
+        }
+    }
+}
+
+/// A goal set at a layer with how far the iteration over the action
+/// sets that meet it got, and whether the goals may still be
+/// solvable when it runs out, i.e. a plan was found from them or
+/// action sets were skipped for costing too much
+type SearchFrame = (usize, BTreeSet<PropositionIndex>, Option<CombinationState>, bool);
+
+/// Where a search of the plangraph left off so it can be resumed to
+/// find more plans at the same depth, see
+/// `GraphPlanSolver::next_plan`. A cursor started at one depth is
+/// started over when the plangraph is extended.
+#[derive(Debug, Clone, Default)]
+pub struct PlanCursor {
+    stack: VecDeque<SearchFrame>,
+    // Actions chosen at each layer from the last one, without
+    // maintenance actions
+    plan: Vec<BTreeSet<ActionIndex>>,
+    // Cost of each step in the plan
+    costs: Vec<u64>,
+    layers: Option<usize>,
+}
+
+impl PlanCursor {
+    pub fn new() -> Self {
+        PlanCursor::default()
+    }
 }
 
-type SearchStack<'a, ActionId, PropositionId> = VecDeque<(usize, BTreeSet<&'a Proposition<PropositionId>>, Option<ActionCombinationIterator<'a, ActionId, PropositionId>>)>;
+impl SimpleSolver {
+    pub fn new(heuristics: SearchHeuristics) -> Self {
+        SimpleSolver { heuristics, nogoods: Nogoods::new() }
+    }
+
+    /// Goal sets found to be unsolvable since the solver was created
+    /// or last reset
+    pub fn nogoods(&self) -> &Nogoods {
+        &self.nogoods
+    }
+
+    /// Forgets the goal sets found to be unsolvable, e.g. before
+    /// searching another problem
+    pub fn reset(&mut self) {
+        self.nogoods = Nogoods::new();
+    }
+
+    /// Blames the goals in the goal set on top of the stack whose
+    /// chosen actions require any of `nogood`, the goals that failed
+    /// in the layer below. Its next action set changes one of them
+    /// rather than goals that had nothing to do with the failure.
+    fn blame_parent<ActionId, PropositionId>(domain: &Domain<ActionId, PropositionId>,
+                                             stack: &mut VecDeque<SearchFrame>,
+                                             nogood: &BTreeSet<PropositionIndex>)
+    where
+        ActionId: Debug + Hash + Ord + Clone,
+        PropositionId: Debug + Display + Hash + Ord + Clone,
+    {
+        if let Some((_, _, Some(state), _)) = stack.front_mut() {
+            let culprits = state.accum.iter()
+                .filter(|(_, a)| nogood.iter().any(|p| domain.req_set(**a).contains(*p)))
+                .map(|(g, _)| *g)
+                .collect();
+            state.blame(culprits);
+        }
+    }
 
-impl<'a,
-     ActionId: Ord + Clone + Hash + Debug,
-     PropositionId: Ord + Clone + Hash + Debug + Display>
-    GraphPlanSolver<'a, ActionId, PropositionId> for SimpleSolver {
-    fn search<'b>(plangraph: &'b PlanGraph<'a, ActionId, PropositionId>) -> Option<Solution<'a, ActionId, PropositionId>> {
-        let mut success = false;
-        let mut plan = Vec::new();
-        // HashSets are not Hash so we can't use them here. However,
-        // BTreeSets are Hash so we can use that here
-        let mut failed_goals_memo: HashSet<(usize, BTreeSet<&Proposition<PropositionId>>)> = HashSet::new();
+    /// Searches backwards from the goals in the last layer, picking
+    /// up from `cursor`, and returns the next plan that costs less
+    /// than `bound`. Action sets that would make the plan cost at
+    /// least as much are skipped. Returns `Ok(None)` when there are
+    /// no more plans.
+    fn resume<ActionId, PropositionId>(&mut self,
+                                       plangraph: &PlanGraph<ActionId, PropositionId>,
+                                       budget: &SearchBudget,
+                                       observer: &mut dyn SearchObserver,
+                                       cursor: &mut PlanCursor,
+                                       bound: Option<u64>)
+                                       -> Result<Option<Solution<ActionId, PropositionId>>, Error>
+    where
+        ActionId: Debug + Hash + Ord + Clone,
+        PropositionId: Debug + Display + Hash + Ord + Clone,
+    {
+        if plangraph.layers.len() < 3 {
+            return Err(Error::NotExtended);
+        }
+        let domain = plangraph.domain();
+        let order = SearchOrder::new(&self.heuristics, plangraph);
+        let failed_goals_memo = &mut self.nogoods;
 
         // Initialize the loop
-        let mut stack: SearchStack<ActionId, PropositionId> = VecDeque::new();
-        let init_goals = BTreeSet::from_iter(plangraph.goals.clone());
-        let init_layer_idx = plangraph.layers.len() - 1;
-        let init_action_gen = None;
+        if cursor.layers != Some(plangraph.layers.len()) {
+            *cursor = PlanCursor::new();
+            cursor.layers = Some(plangraph.layers.len());
+            let init_goals = domain.goals().clone();
+            let init_layer_idx = plangraph.layers.len() - 1;
+            let init_action_gen = None;
 
-        stack.push_front((init_layer_idx, init_goals, init_action_gen));
+            observer.goals_pushed(init_layer_idx, &init_goals);
+            cursor.stack.push_front((init_layer_idx, init_goals, init_action_gen, false));
+        }
 
-        while let Some((idx, goals, action_gen)) = stack.pop_front() {
+        while let Some((idx, goals, action_gen, mut solvable)) = cursor.stack.pop_front() {
             debug!("Working on layer {:?} with goals {:?}", idx, goals);
-            // HashSets are not Hash so we convert goals into a BTreeSet
-            let goals_as_btree = BTreeSet::from_iter(goals.clone());
-            // Check if the goal set is unsolvablpe at level idx
-            if failed_goals_memo.contains(&(idx, goals_as_btree.clone())) {
-                // Continue to previous layer (the next element in the queue)
+            // Check if the goal set is unsolvable at level idx
+            if let Some(nogood) = failed_goals_memo.get(idx, &goals) {
+                observer.memo_hit(idx, &goals);
+                // Remove the step that led to this goal set and
+                // continue to previous layer (the next element in
+                // the queue)
+                cursor.plan.pop();
+                cursor.costs.pop();
+                Self::blame_parent(domain, &mut cursor.stack, nogood);
+                observer.backtracked(idx);
+                budget.backtrack()?;
                 continue;
             }
+            observer.goals_popped(idx, &goals);
 
             // Note: This is a btreeset so ordering is guaranteed
             // which makes the plans yielded deterministic
-            let actions = plangraph.layers.get(idx - 1).map_or(
-                Err(format!("Layer {} does not exist", idx - 1)),
-                |layer| {
-                    match layer {
-                        Layer::ActionLayer(actions) => {
-                            let acts = actions
-                                .iter()
-                                .cloned()
-                                .collect::<BTreeSet<_>>();
-                            Ok(acts)
-                        },
-                        Layer::PropositionLayer(_) => {
-                            Err(format!("Tried to get actions from proposition layer {}",
-                                        idx - 1))
-                        }
-                    }
-                }
-            ).expect("Failed to get actions");
+            let actions = match plangraph.layers.get(idx - 1) {
+                Some(Layer::ActionLayer(actions)) => actions.clone(),
+                Some(Layer::PropositionLayer(_)) => return Err(Error::UnexpectedLayer(idx - 1)),
+                None => return Err(Error::MissingLayer(idx - 1)),
+            };
 
-            let mutexes = plangraph.mutex_actions.get(&(idx - 1)).cloned();
-            let mut gen = action_gen
-                .or_else(|| Some(GoalSetActionGenerator::new(goals.clone(),
-                                                             actions.clone(),
-                                                             mutexes).into_iter()))
-                .unwrap();
+            let mutexes = plangraph.mutex_actions.get(&(idx - 1));
+            let meta = GoalSetActionGenerator::new(domain, goals.clone(), actions, mutexes)
+                .with_budget(budget)
+                .with_order(&order);
+            let mut gen = match action_gen {
+                Some(state) => ActionCombinationIterator::resume(meta, state),
+                None => {
+                    budget.expand()?;
+                    meta.into_iter()
+                },
+            };
 
-            if let Some(goal_actions) = gen.next() {
+            // Skip action sets that make the plan cost at least as
+            // much as the bound
+            let cost_so_far: u64 = cursor.costs.iter().sum();
+            let next_actions = gen.by_ref().find_map(|goal_actions| {
+                let step_cost = goal_actions.0.values()
+                    .collect::<BTreeSet<_>>()
+                    .into_iter()
+                    .map(|a| domain.action(*a).cost)
+                    .sum::<u64>();
+                if matches!(bound, Some(b) if cost_so_far + step_cost >= b) {
+                    solvable = true;
+                    None
+                } else {
+                    Some((goal_actions, step_cost))
+                }
+            });
+
+            if let Some((goal_actions, step_cost)) = next_actions {
                 debug!("Actions: {:?} for goals: {:?}", goal_actions, goals);
                 // If we are are on the second to last proposition
                 // layer, we are done
                 let goal_action_set = goal_actions.0
                     .values()
                     // Don't include maintenance actions in Solution
-                    .filter(|a| match a.id {
-                        ActionType::Action(_) => true,
-                        ActionType::Maintenance(_) => false
-                    })
+                    .filter(|a| !domain.is_maintenance(**a))
                     .copied()
-                    .collect::<HashSet<&'a Action<ActionId, PropositionId>>>();
+                    .collect();
+                cursor.plan.push(goal_action_set);
+                cursor.costs.push(step_cost);
                 if (idx - 2) == 0 {
-                    plan.push(goal_action_set);
-                    debug!("Found plan! {:?}", plan);
-                    success = true;
-                    break;
+                    // Since this solver goes from the last layer to
+                    // the first, we need to reverse the plan
+                    let found: Solution<ActionId, PropositionId> = cursor.plan.iter()
+                        .rev()
+                        .map(|step| step.iter().map(|a| domain.action(*a).clone()).collect())
+                        .collect();
+                    debug!("Found plan! {:?}", found);
+
+                    // Leave the goals on the stack to look for more
+                    // plans from them when resumed
+                    cursor.plan.pop();
+                    cursor.costs.pop();
+                    observer.goals_pushed(idx, &goals);
+                    cursor.stack.push_front((idx, goals, Some(gen.into_state()), true));
+                    return Ok(Some(found));
                 } else {
-                    let next_goals = goal_action_set.iter()
-                        .flat_map(|action| action.reqs.clone())
+                    // Maintenance actions are left out of the plan
+                    // but the goals they carry forward still need to
+                    // be met by the previous layer
+                    let next_goals = goal_actions.0
+                        .values()
+                        .flat_map(|action| domain.reqs(*action))
+                        .copied()
                         .collect();
 
-                    plan.push(goal_action_set);
                     // Add this layer back into the queue incase we need to backtrack
-                    stack.push_front((idx, goals, Some(gen)));
-                    stack.push_front((idx - 2, next_goals, None));
+                    observer.goals_pushed(idx, &goals);
+                    cursor.stack.push_front((idx, goals, Some(gen.into_state()), solvable));
+                    observer.goals_pushed(idx - 2, &next_goals);
+                    cursor.stack.push_front((idx - 2, next_goals, None, false));
                 };
             } else {
+                // The generator also stops when the budget runs out
+                // in which case the goals may still be solvable
+                budget.check()?;
                 debug!("Unable to find actions for goals {:?} from actions {:?}",
-                       goals, actions);
-                // Record the failed goals at level idx
-                failed_goals_memo.insert((idx, goals_as_btree));
+                       goals, gen.meta.actions);
+                if solvable {
+                    // The goals led to a plan or may be solvable at a
+                    // higher cost so they aren't recorded and neither
+                    // are the goal sets that led to them
+                    if let Some(parent) = cursor.stack.front_mut() {
+                        parent.3 = true;
+                    }
+                } else {
+                    // Record the goals that caused the failure at
+                    // level idx, any goal set with them fails too
+                    let nogood = gen.state.explanation.iter()
+                        .map(|g| gen.meta.goal_list[*g])
+                        .collect();
+                    observer.goals_memoized(idx, &nogood);
+                    Self::blame_parent(domain, &mut cursor.stack, &nogood);
+                    failed_goals_memo.insert(idx, nogood);
+                }
                 // Remove the last step in the plan from which this
                 // set of goals comes from
-                plan.pop();
+                cursor.plan.pop();
+                cursor.costs.pop();
+                observer.backtracked(idx);
+                budget.backtrack()?;
                 // Backtrack to previous layer and goalset or nothing
                 // (the next element in the queue)
             }
         };
 
-        if success {
-            // Since this solver goes from the last layer to the
-            // first, we need to reverse the plan
-            plan.reverse();
-            Some(plan)
-        } else {
-            None
+        Ok(None)
+    }
+}
+
+impl<ActionId, PropositionId> GraphPlanSolver<ActionId, PropositionId> for SimpleSolver
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    fn search(&mut self, plangraph: &PlanGraph<ActionId, PropositionId>) -> Result<Option<Solution<ActionId, PropositionId>>, Error> {
+        self.search_within(plangraph, &SearchBudget::default(), &mut ())
+    }
+
+    fn search_within(&mut self,
+                     plangraph: &PlanGraph<ActionId, PropositionId>,
+                     budget: &SearchBudget,
+                     observer: &mut dyn SearchObserver)
+                     -> Result<Option<Solution<ActionId, PropositionId>>, Error> {
+        self.resume(plangraph, budget, observer, &mut PlanCursor::new(), None)
+    }
+
+    fn search_cheapest(&mut self,
+                       plangraph: &PlanGraph<ActionId, PropositionId>,
+                       budget: &SearchBudget,
+                       observer: &mut dyn SearchObserver,
+                       bound: Option<u64>)
+                       -> Result<Option<Solution<ActionId, PropositionId>>, Error> {
+        // Each plan found lowers the bound for the rest of the search
+        let mut cursor = PlanCursor::new();
+        let mut best = None;
+        let mut bound = bound;
+        while let Some(found) = self.resume(plangraph, budget, observer, &mut cursor, bound)? {
+            bound = Some(found.cost());
+            best = Some(found);
         }
+        Ok(best)
+    }
+
+    fn next_plan(&mut self,
+                 plangraph: &PlanGraph<ActionId, PropositionId>,
+                 budget: &SearchBudget,
+                 observer: &mut dyn SearchObserver,
+                 cursor: &mut PlanCursor)
+                 -> Result<Option<Solution<ActionId, PropositionId>>, Error> {
+        self.resume(plangraph, budget, observer, cursor, None)
+    }
+
+    fn nogoods(&self) -> Option<&Nogoods> {
+        Some(SimpleSolver::nogoods(self))
+    }
+
+    fn reset(&mut self) {
+        SimpleSolver::reset(self)
     }
 }
 
 #[cfg(test)]
 mod simple_solver_test {
     use super::*;
+    use crate::action::Action;
+    use crate::ordering::{ActionOrder, GoalOrder};
+    use crate::proposition::Proposition;
+    use crate::fixtures::token_actions;
 
     #[test]
     fn solver_works() {
@@ -455,13 +1203,153 @@ mod simple_solver_test {
             goals,
             actions,
         );
-        pg.extend();
-        pg.extend();
+        pg.extend().unwrap();
+        pg.extend().unwrap();
 
-        let expected = vec![fragset!{[&a1]}, fragset!{[&a2]}];
-        let actual = SimpleSolver::search(&pg).unwrap();
+        let expected = vec![fragset!{[a1.clone()]}, fragset!{[a2.clone()]}];
+        let actual = SimpleSolver::default().search(&pg).unwrap().unwrap();
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn keeps_the_goals_of_maintenance_actions() {
+        // Making a spends b and making c spends the tool a needs so
+        // a has to be made first and carried forward while b and
+        // then c are made
+        let (a, b, c) = (Proposition::from("a"), Proposition::from("b"), Proposition::from("c"));
+        let tool = Proposition::from("tool");
+        let (not_b, broken) = (b.negate(), tool.negate());
+        let make_a = Action::new("make a", fragset!{[&tool]}, fragset!{[&a, &not_b]});
+        let make_b = Action::new("make b", fragset!{}, fragset!{[&b]});
+        let make_c = Action::new("make c", fragset!{[&b]}, fragset!{[&c, &broken]});
+        let domain = crate::GraphPlan::create_domain(
+            fragset!{[&tool]},
+            fragset!{[&a, &c]},
+            fragset!{[&make_a, &make_b, &make_c]},
+        );
+
+        let mut pg = PlanGraph::from_domain(domain);
+        pg.extend().unwrap();
+        pg.extend().unwrap();
+        pg.extend().unwrap();
+
+        let plan = SimpleSolver::default().search(&pg).unwrap().unwrap();
+        assert_eq!(plan, vec![fragset!{[make_a]}, fragset!{[make_b]}, fragset!{[make_c]}]);
+    }
+
+    #[test]
+    fn unwinds_the_plan_on_memo_hits() {
+        // Only two of a, b and c can be made with the token so the
+        // first way to make g fails, the second is already known to
+        // fail and the third succeeds
+        let (a, b, c, d) = (Proposition::from("a"), Proposition::from("b"), Proposition::from("c"), Proposition::from("d"));
+        let (g, token) = (Proposition::from("g"), Proposition::from("token"));
+        let make_d = Action::new("make d", fragset!{}, fragset!{[&d]});
+        let make_g1 = Action::new("make g 1", fragset!{[&a, &b, &c]}, fragset!{[&g]});
+        let make_g2 = Action::new("make g 2", fragset!{[&a, &b, &c]}, fragset!{[&g]});
+        let make_g3 = Action::new("make g 3", fragset!{[&d]}, fragset!{[&g]});
+        let mut actions = token_actions();
+        actions.extend(vec![make_d.clone(), make_g1, make_g2, make_g3.clone()]);
+        let domain = crate::GraphPlan::create_domain(fragset!{[&token]}, fragset!{[&g]}, actions.iter().collect());
+        let mut pg = PlanGraph::from_domain(domain);
+        pg.extend().unwrap();
+        pg.extend().unwrap();
+
+        let plan = SimpleSolver::default().search(&pg).unwrap().unwrap();
+        assert_eq!(plan, vec![fragset!{[make_d]}, fragset!{[make_g3]}]);
+    }
+
+    #[test]
+    fn heuristics_keep_plans_valid_and_deterministic() {
+        let p1 = Proposition::from("tired");
+        let not_p1 = p1.negate();
+        let p2 = Proposition::from("dog needs to pee");
+        let not_p2 = p2.negate();
+        let a1 = Action::new("coffee", fragset!{[&p1]}, fragset!{[&not_p1]});
+        let a2 = Action::new("walk dog", fragset!{[&p2, &not_p1]}, fragset!{[&not_p2]});
+        let a3 = Action::new("tea", fragset!{[&p1]}, fragset!{[&not_p1]});
+        let domain = crate::GraphPlan::create_domain(
+            fragset!{[&p1, &p2]},
+            fragset!{[&not_p1, &not_p2]},
+            fragset!{[&a1, &a2, &a3]},
+        );
+        let mut pg = PlanGraph::from_domain(domain.clone());
+        pg.extend().unwrap();
+        pg.extend().unwrap();
+
+        let reversed: crate::ordering::Comparator<ActionIndex> = std::sync::Arc::new(|a, b| b.cmp(&a));
+        let configs = vec![
+            SearchHeuristics::new(),
+            SearchHeuristics::new().goal_order(GoalOrder::HardestFirst).action_order(ActionOrder::NoopsFirst).clone(),
+            SearchHeuristics::new().action_order(ActionOrder::FewestPreconditions).clone(),
+            SearchHeuristics::new().action_order(ActionOrder::EarliestPreconditions).clone(),
+            SearchHeuristics::new().action_order(ActionOrder::Custom(reversed)).clone(),
+        ];
+        for heuristics in configs {
+            let mut solver = SimpleSolver::new(heuristics);
+            let plan = solver.search(&pg).unwrap().unwrap();
+            assert_eq!(plan.len(), 2);
+            assert_eq!(crate::validate_plan(&domain, &plan), Ok(()));
+            assert_eq!(solver.search(&pg).unwrap().unwrap(), plan);
+        }
+
+        // Coffee comes before tea by index and after it reversed
+        let reversed: crate::ordering::Comparator<ActionIndex> = std::sync::Arc::new(|a, b| b.cmp(&a));
+        let mut solver = SimpleSolver::new(SearchHeuristics::new().action_order(ActionOrder::Custom(reversed)).clone());
+        assert_eq!(SimpleSolver::default().search(&pg).unwrap().unwrap()[0], fragset!{[a1.clone()]});
+        assert_eq!(solver.search(&pg).unwrap().unwrap()[0], fragset!{[a3.clone()]});
+    }
+
+    #[test]
+    fn memoizes_only_the_goals_in_conflict() {
+        // Any two of a, b and c can be made at once with the token
+        // but not all three, whatever happens to d
+        let (a, b, c, d) = (Proposition::from("a"), Proposition::from("b"), Proposition::from("c"), Proposition::from("d"));
+        let token = Proposition::from("token");
+        let mut actions = token_actions();
+        actions.push(Action::new("make d", fragset!{}, fragset!{[&d]}));
+        let domain = crate::GraphPlan::create_domain(
+            fragset!{[&token]},
+            fragset!{[&a, &b, &c, &d]},
+            actions.iter().collect(),
+        );
+        let mut pg = PlanGraph::from_domain(domain.clone());
+        pg.extend().unwrap();
+
+        let mut solver = SimpleSolver::default();
+        assert_eq!(solver.search(&pg), Ok(None));
+        let idx = |p: &Proposition<&str>| domain.proposition_index(p).unwrap();
+        let conflict = btreeset!{idx(&a), idx(&b), idx(&c)};
+        assert_eq!(solver.nogoods().get(2, domain.goals()), Some(&conflict));
+        assert_eq!(solver.nogoods().len(), 1);
+
+        // The nogood still holds once the plangraph is extended, and
+        // is forgotten on reset
+        pg.extend().unwrap();
+        assert!(solver.search(&pg).is_ok());
+        assert_eq!(solver.nogoods().get(2, domain.goals()), Some(&conflict));
+        solver.reset();
+        assert!(solver.nogoods().is_empty());
+    }
+
+    #[test]
+    fn nogoods_contain_supersets() {
+        let (p1, p2, p3) = (PropositionIndex(0), PropositionIndex(1), PropositionIndex(2));
+        let mut nogoods = Nogoods::new();
+        assert!(nogoods.insert(2, btreeset!{p1, p2}));
+        assert!(nogoods.contains(2, &btreeset!{p1, p2, p3}));
+        assert!(!nogoods.contains(2, &btreeset!{p1, p3}));
+        assert!(!nogoods.contains(4, &btreeset!{p1, p2}));
+        // Already known from the smaller set
+        assert!(!nogoods.insert(2, btreeset!{p1, p2, p3}));
+        assert_eq!(nogoods.count(2), 1);
+    }
+
+    #[test]
+    fn solver_errors_on_unextended_plangraph() {
+        let pg: PlanGraph<&str, &str> = PlanGraph::new(fragset!{}, fragset!{}, fragset!{});
+        assert_eq!(SimpleSolver::default().search(&pg), Err(Error::NotExtended));
+    }
 }
 
 #[cfg(test)]
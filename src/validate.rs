@@ -0,0 +1,231 @@
+use std::collections::{BTreeSet, HashSet};
+use std::error;
+use std::fmt;
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+use crate::proposition::Proposition;
+use crate::action::{Action, ActionType};
+use crate::domain::Domain;
+use crate::plangraph::Solution;
+
+
+/// Why a plan failed validation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationFailure<ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    /// A requirement of the action did not hold before the step
+    UnmetRequirement,
+    /// The action deletes a requirement or effect of another action
+    /// in the same step
+    Interference(ActionType<ActionId, PropositionId>),
+    /// A goal did not hold after the last step
+    UnmetGoal,
+}
+
+/// Report of the first point at which a plan fails. Steps are
+/// indices into the `Solution`, an unmet goal is reported at the
+/// step after the last one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError<ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    pub step: usize,
+    pub action: Option<ActionType<ActionId, PropositionId>>,
+    pub proposition: Proposition<PropositionId>,
+    pub reason: ValidationFailure<ActionId, PropositionId>,
+}
+
+impl<ActionId, PropositionId> Display for ValidationError<ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.reason, &self.action) {
+            (ValidationFailure::UnmetRequirement, Some(action)) => write!(
+                f, "step {}: {:?} requires {:?} which does not hold",
+                self.step, action, self.proposition
+            ),
+            (ValidationFailure::Interference(other), Some(action)) => write!(
+                f, "step {}: {:?} interferes with {:?} on {:?}",
+                self.step, action, other, self.proposition
+            ),
+            _ => write!(
+                f, "step {}: goal {:?} does not hold",
+                self.step, self.proposition
+            ),
+        }
+    }
+}
+
+impl<ActionId, PropositionId> error::Error for ValidationError<ActionId, PropositionId>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{}
+
+/// Returns the first proposition of `props` whose negation is in
+/// `others`
+fn first_negated<'p, PropositionId>(props: &'p HashSet<Proposition<PropositionId>>,
+                                    others: &HashSet<Proposition<PropositionId>>)
+                                    -> Option<&'p Proposition<PropositionId>>
+where
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    props.iter()
+        .filter(|p| others.contains(&p.negate()))
+        .min()
+}
+
+/// Simulates a solution from the initial state of the domain. Every
+/// action's requirements must hold before its step, actions within
+/// a step must not interfere with each other and the goals must hold
+/// after the last step. Conditional effects fire if their conditions
+/// hold before the step. Actions do not need to be part of the domain
+/// so hand written plans can be checked too.
+pub fn validate_plan<ActionId, PropositionId>(domain: &Domain<ActionId, PropositionId>,
+                                              solution: &Solution<ActionId, PropositionId>)
+                                              -> Result<(), ValidationError<ActionId, PropositionId>>
+where
+    ActionId: Debug + Hash + Ord + Clone,
+    PropositionId: Debug + Display + Hash + Ord + Clone,
+{
+    let mut state: HashSet<Proposition<PropositionId>> = domain.initial_props()
+        .iter()
+        .map(|p| domain.proposition(*p).clone())
+        .collect();
+
+    for (step, actions) in solution.iter().enumerate() {
+        // Sort so the same plan always reports the same failure
+        let actions: Vec<&Action<ActionId, PropositionId>> = actions.iter()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+
+        for action in &actions {
+            let unmet = action.reqs.iter()
+                .filter(|p| !state.contains(*p))
+                .min();
+            if let Some(prop) = unmet {
+                return Err(ValidationError {
+                    step,
+                    action: Some(action.id.clone()),
+                    proposition: prop.clone(),
+                    reason: ValidationFailure::UnmetRequirement,
+                });
+            }
+        }
+
+        // Conditional effects fire depending on the state before
+        // the step
+        let effects: Vec<HashSet<Proposition<PropositionId>>> = actions.iter()
+            .map(|a| a.effects_in(&state))
+            .collect();
+
+        for (idx, a1) in actions.iter().enumerate() {
+            for (a2, a2_effects) in actions.iter().zip(&effects).skip(idx + 1) {
+                let a1_effects = &effects[idx];
+                let interference = first_negated(a1_effects, &a2.reqs)
+                    .or_else(|| first_negated(a1_effects, a2_effects))
+                    .map(|p| (a1, a2, p))
+                    .or_else(|| first_negated(a2_effects, &a1.reqs).map(|p| (a2, a1, p)));
+                if let Some((action, other, prop)) = interference {
+                    return Err(ValidationError {
+                        step,
+                        action: Some(action.id.clone()),
+                        proposition: prop.clone(),
+                        reason: ValidationFailure::Interference(other.id.clone()),
+                    });
+                }
+            }
+        }
+
+        for action_effects in effects {
+            for p in &action_effects {
+                state.remove(&p.negate());
+            }
+            state.extend(action_effects);
+        }
+    }
+
+    let unmet_goal = domain.goals()
+        .iter()
+        .map(|p| domain.proposition(*p))
+        .find(|p| !state.contains(*p));
+    if let Some(prop) = unmet_goal {
+        return Err(ValidationError {
+            step: solution.len(),
+            action: None,
+            proposition: prop.clone(),
+            reason: ValidationFailure::UnmetGoal,
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod validate_test {
+    use super::*;
+    use crate::{GraphPlan, SimpleSolver};
+    use crate::fixtures;
+
+    /// The morning domain with a nap that makes you tired again
+    fn morning_with_nap() -> (Domain<&'static str, &'static str>, Vec<Action<&'static str, &'static str>>) {
+        let tired = Proposition::from("tired");
+        let not_tired = tired.negate();
+        let mut actions = fixtures::morning_actions();
+        actions.push(Action::new("nap", fragset!{[&not_tired]}, fragset!{[&tired]}));
+        (fixtures::morning(), actions)
+    }
+
+    #[test]
+    fn accepts_solver_solutions() {
+        let (domain, _) = morning_with_nap();
+        let mut pg = GraphPlan::from_domain(&domain);
+        let solution = pg.search::<SimpleSolver>().unwrap();
+        assert_eq!(validate_plan(&domain, &solution), Ok(()));
+    }
+
+    #[test]
+    fn reports_unmet_requirement() {
+        let (domain, actions) = morning_with_nap();
+        let solution = vec![fragset!{[actions[1].clone()]}];
+        let error = validate_plan(&domain, &solution).unwrap_err();
+        assert_eq!(error.step, 0);
+        assert_eq!(error.action, Some(actions[1].id.clone()));
+        assert_eq!(error.proposition, Proposition::from("tired").negate());
+        assert_eq!(error.reason, ValidationFailure::UnmetRequirement);
+    }
+
+    #[test]
+    fn reports_interference_within_a_step() {
+        let (domain, actions) = morning_with_nap();
+        let solution = vec![
+            fragset!{[actions[0].clone()]},
+            fragset!{[actions[1].clone(), actions[2].clone()]},
+        ];
+        let error = validate_plan(&domain, &solution).unwrap_err();
+        assert_eq!(error.step, 1);
+        assert_eq!(error.action, Some(actions[2].id.clone()));
+        assert_eq!(error.proposition, Proposition::from("tired"));
+        assert_eq!(error.reason, ValidationFailure::Interference(actions[1].id.clone()));
+    }
+
+    #[test]
+    fn reports_unmet_goal() {
+        let (domain, actions) = morning_with_nap();
+        let solution = vec![fragset!{[actions[0].clone()]}];
+        let error = validate_plan(&domain, &solution).unwrap_err();
+        assert_eq!(error.step, 1);
+        assert_eq!(error.action, None);
+        assert_eq!(error.proposition, Proposition::from("dog needs to pee").negate());
+        assert_eq!(error.reason, ValidationFailure::UnmetGoal);
+        assert_eq!(error.to_string(), "step 1: goal ¬P:dog needs to pee does not hold");
+    }
+}